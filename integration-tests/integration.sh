@@ -287,9 +287,7 @@ function test_review_tx_bad_recipient () {
         "content":"unexistent tx",
         "rating":0,
         "title":"better going somewhere else",
-        "tx_id": 8,
-        "tx_page": 0,
-        "viewing_key": "vk"
+        "receipts": [{"snip20": {"tx_id": 8, "tx_page": 0, "viewing_key": "vk"}}]
       }
     }'
 
@@ -321,9 +319,7 @@ function test_review_unexistent_tx () {
         "content":"unexistent tx",
         "rating":0,
         "title":"better going somewhere else",
-        "tx_id": 7,
-        "tx_page": 0,
-        "viewing_key": "vk"
+        "receipts": [{"snip20": {"tx_id": 7, "tx_page": 0, "viewing_key": "vk"}}]
       }
     }'
 
@@ -354,9 +350,7 @@ function test_reviews () {
         "content": "great stuff!",
         "rating": 5,
         "title":"amazing restaurant",
-        "tx_id": 4,
-        "tx_page": 0,
-        "viewing_key": "vk"
+        "receipts": [{"snip20": {"tx_id": 4, "tx_page": 0, "viewing_key": "vk"}}]
       }
     }'
 
@@ -378,11 +372,11 @@ function test_reviews () {
 
     result="$(compute_query "$contract_addr" "$query_single_business_message" 2>&1 || true )"
     result_comparable=$(echo $result | sed 's/ Usage:.*//')
-    assert_eq "$result_comparable" '{"single_business":{"business":{"name":"Starbucks","description":"a place to eat","address":"secret1fc3fzy78ttp0lwuujw7e52rhspxn8uj52zfyne","average_rating":"5000","reviews_count":1},"status":"Successfully retrieved business by address"}}'
+    assert_eq "$result_comparable" '{"single_business":{"business":{"name":"Starbucks","description":"a place to eat","address":"secret1fc3fzy78ttp0lwuujw7e52rhspxn8uj52zfyne","category":null,"claimed":false,"brand":null,"status":"active","rating_scale":"stars","average_rating":"5","decayed_average_rating":null,"dimension_ratings":[],"reviews_count":1},"status":"Successfully retrieved business by address"}}'
     local rating
     rating="$(jq -er '.single_business.business.average_rating' <<< "$result_comparable")"
     log "rating after a rated: $rating"
-    assert_eq $rating '5000'
+    assert_eq $rating '5'
 
     local review_message
     review_message='{
@@ -391,9 +385,7 @@ function test_reviews () {
         "content": "Not so good",
         "rating": 0,
         "title": "D first review",
-        "tx_id": 5,
-        "tx_page": 0,
-        "viewing_key": "vk"
+        "receipts": [{"snip20": {"tx_id": 5, "tx_page": 0, "viewing_key": "vk"}}]
       }
     }'
 
@@ -419,7 +411,7 @@ function test_reviews () {
     local rating
     rating="$(jq -er '.single_business.business.average_rating' <<< "$result_comparable")"
     log "rating after d rated: $rating"
-    assert_eq $rating '1250'
+    assert_eq $rating '1.25'
     log "query single business: SUCCESS!"
 
     local review_message
@@ -429,9 +421,7 @@ function test_reviews () {
         "content": "In the middle",
         "rating": 3,
         "title": "not good and not bad",
-        "tx_id": 6,
-        "tx_page": 0,
-        "viewing_key": "vk"
+        "receipts": [{"snip20": {"tx_id": 6, "tx_page": 0, "viewing_key": "vk"}}]
       }
     }'
 
@@ -457,7 +447,7 @@ function test_reviews () {
     local rating
     rating="$(jq -er '.single_business.business.average_rating' <<< "$result_comparable")"
     log "rating after c rated: $rating"
-    assert_eq $rating '1833'
+    assert_eq $rating '1.833333'
 
     local query_reviews_on_business
     query_reviews_on_business='{
@@ -481,9 +471,7 @@ function test_reviews () {
         "content": "second time was amazing",
         "rating": 5,
         "title": "2nd time is the charm",
-        "tx_id": 7,
-        "tx_page": 0,
-        "viewing_key": "vk"
+        "receipts": [{"snip20": {"tx_id": 7, "tx_page": 0, "viewing_key": "vk"}}]
       }
     }'
 
@@ -509,7 +497,7 @@ function test_reviews () {
     local rating
     rating="$(jq -er '.single_business.business.average_rating' <<< "$result_comparable")"
     log "rating after 2nd time d rated: $rating"
-    assert_eq $rating '4428'
+    assert_eq $rating '4.428571'
 
     local query_reviews_on_business
     query_reviews_on_business='{
@@ -1,33 +1,125 @@
 use cosmwasm_std::{
-    to_binary, Api, Binary, Env, Extern, HandleResponse, HumanAddr, InitResponse, Querier,
-    QueryResult, StdError, StdResult, Storage, Uint128,
+    from_binary, to_binary, Api, BankMsg, Binary, BlockInfo, Coin, CosmosMsg, Env, Extern,
+    HandleResponse, HumanAddr, InitResponse, Querier, QueryResult, ReadonlyStorage, StdError,
+    StdResult, Storage, Uint128,
 };
 
-use crate::msg::{DisplayedBusiness, HandleAnswer, HandleMsg, InitMsg, QueryAnswer, QueryMsg};
-use crate::snip_20_query::query_snip20_tx;
+use crate::msg::{
+    AggregatesDrift, DisplayedBrand, DisplayedBusiness, DisplayedDeposit, DisplayedDimensionRating,
+    DisplayedDispute, DisplayedFlaggedReview, DisplayedItem, DisplayedRevision, HandleAnswer,
+    HandleMsg, InitMsg, NewVoucher, QueryAnswer, QueryMsg, Receipt, ReceiveMsg,
+};
+use crate::rating::RatingScale;
+use crate::snip_20_query::{
+    find_snip20_tx, query_snip20_history_page, BLOCK_SIZE, SSCRT_ADDRESS, SSCRT_HASH,
+};
 use crate::state::{
-    apply_review_on_business, create_business, create_review, get_business_by_address,
-    get_businesses_page, get_reviews_on_business, may_load_review, Business, Review,
+    add_associated_address, append_revision, create_brand, create_business, create_flag,
+    create_native_receipt, create_review, get_all_categories, get_branches_page,
+    get_business_by_address, get_businesses_page, get_flagged_reviews_page, get_items_page,
+    get_reviews_on_business, get_reviews_page, get_revisions_page, is_associated_address,
+    load_config, may_load_aggregate_rebuild, may_load_attestation_nonce, may_load_brand,
+    may_load_category, may_load_deposit, may_load_dispute, may_load_flagged_review, may_load_item,
+    may_load_native_receipt, may_load_review, may_load_voucher, migrate_legacy_businesses,
+    migrate_legacy_reviews, rating_scale_of, remove_aggregate_rebuild, remove_associated_address,
+    remove_flagged_review, save_aggregate_rebuild, save_category, save_config, save_deposit,
+    save_dispute, save_flagged_review, save_item, save_voucher, take_deposit, update_business,
+    use_attestation_nonce, AggregateRebuild, Brand, Business, BusinessAggregates, BusinessStatus,
+    Category, Config, DimensionAggregate, DimensionRating, Dispute, DisputeOutcome, DisputeState,
+    Flag, FlagWeight, FlaggedReview, HeldDeposit, Hidden, Item, ModerationReason, NativeReceipt,
+    ReceiptAge, ReceiptAmount, ReceiptId, RegistrationDeposit, RegistrationMode, Review,
+    Snip20Token, TimeDecay, Voucher, WeightStrategy,
+};
+use k256::ecdsa::signature::Verifier;
+use k256::ecdsa::{Signature, VerifyingKey};
+use secret_toolkit::snip20::{register_receive_msg, transfer_msg, Tx};
+use sha2::{Digest, Sha256};
+use std::convert::TryFrom;
+
+use crate::utils::{
+    apply_decay, apply_weight_strategy, decay_factor, decayed_contribution, result_add, result_mul,
+    result_sub,
 };
-use crate::utils::{recalculate_weighted_average, result_add};
 
 // constants:
 const MAX_DESCRIPTION_LENGTH: u8 = 40;
 const MAX_NAME_LENGTH: u8 = 20;
-const MAX_RATING: u8 = 5;
+const NATIVE_DENOM: &str = "uscrt";
+const MAX_DIMENSIONS: usize = 8;
+const MAX_DISPUTE_TEXT_LENGTH: usize = 280;
 
-struct Receipt {
-    tx_id: u64,
-    tx_page: u32,
-    viewing_key: String,
+struct VerifiedReceipt {
+    amount: u128,
+    block_time: Option<u64>,
+    block_height: Option<u64>,
+}
+
+// a review as it is sent in ReviewBusiness, before its rating is normalized
+struct ReviewSubmission {
+    title: String,
+    content: String,
+    rating: u8,
+    receipts: Vec<Receipt>,
+    dimension_ratings: Vec<DimensionRating>,
+    item: Option<String>,
 }
 
 pub fn init<S: Storage, A: Api, Q: Querier>(
-    _deps: &mut Extern<S, A, Q>,
-    _env: Env,
-    _msg: InitMsg,
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    msg: InitMsg,
 ) -> StdResult<InitResponse> {
-    Ok(InitResponse::default())
+    if let Some(TimeDecay {
+        half_life_seconds: 0,
+    }) = msg.time_decay
+    {
+        return Err(StdError::generic_err("The half life must be positive"));
+    }
+
+    let registration_deposit = validate_registration_deposit(msg.registration_deposit)?;
+    let messages = register_deposit_token(&env, &registration_deposit)?;
+
+    let accepted_tokens = msg.accepted_tokens.unwrap_or_else(|| {
+        vec![Snip20Token {
+            address: HumanAddr(SSCRT_ADDRESS.to_string()),
+            code_hash: SSCRT_HASH.to_string(),
+            min_receipt_amount: Uint128::zero(),
+        }]
+    });
+
+    save_config(
+        &mut deps.storage,
+        &Config {
+            admin: env.message.sender,
+            accepted_tokens,
+            min_native_receipt_amount: msg.min_native_receipt_amount.unwrap_or_default(),
+            refund_netting: msg.refund_netting.unwrap_or(false),
+            max_receipt_age: msg.max_receipt_age,
+            allow_stale_edits: msg.allow_stale_edits.unwrap_or(true),
+            weight_strategy: validate_weight_strategy(
+                msg.weight_strategy.unwrap_or(WeightStrategy::Linear),
+            )?,
+            time_decay: msg.time_decay,
+            rating_scale: msg.rating_scale.unwrap_or_default(),
+            moderators: msg.moderators.unwrap_or_default(),
+            count_hidden_reviews: msg.count_hidden_reviews.unwrap_or(true),
+            flag_threshold: msg.flag_threshold.unwrap_or_default(),
+            arbiter: msg.arbiter,
+            dispute_bond: msg.dispute_bond.unwrap_or_default(),
+            min_edit_interval_seconds: msg.min_edit_interval_seconds.unwrap_or_default(),
+            review_lock_seconds: msg.review_lock_seconds.unwrap_or_default(),
+            registration_deposit,
+            registration_mode: msg.registration_mode.unwrap_or_default(),
+            registrars: msg.registrars.unwrap_or_default(),
+            voucher_weight_budget: msg.voucher_weight_budget.unwrap_or_default(),
+            max_attested_amount: msg.max_attested_amount.unwrap_or_default(),
+        },
+    )?;
+
+    Ok(InitResponse {
+        messages,
+        log: vec![],
+    })
 }
 
 pub fn handle<S: Storage, A: Api, Q: Querier>(
@@ -35,33 +127,250 @@ pub fn handle<S: Storage, A: Api, Q: Querier>(
     env: Env,
     msg: HandleMsg,
 ) -> StdResult<HandleResponse> {
+    let mut messages = vec![];
     let answer = match msg {
         HandleMsg::RegisterBusiness {
             name,
             address,
             description,
-        } => register_business(deps, env, name, HumanAddr(address.to_string()), description)?,
+            category,
+        } => {
+            let config = load_config(&deps.storage)?;
+            let deposit = native_registration_deposit(&config, &env)?;
+            register_business(
+                deps,
+                env.message.sender,
+                name,
+                HumanAddr(address.to_string()),
+                description,
+                category,
+                deposit,
+            )?
+        }
+
+        HandleMsg::Receive {
+            from, amount, msg, ..
+        } => receive(deps, env, from, amount, msg)?,
 
         HandleMsg::ReviewBusiness {
             address,
             content,
             rating,
             title,
-            tx_id,
+            receipts,
+            dimension_ratings,
+            item,
+        } => review_business(
+            deps,
+            env,
+            address,
+            ReviewSubmission {
+                title,
+                content,
+                rating,
+                receipts,
+                dimension_ratings,
+                item,
+            },
+        )?,
+
+        HandleMsg::PayBusiness { address } => {
+            let (pay_messages, answer) = pay_business(deps, env, address)?;
+            messages.extend(pay_messages);
+            answer
+        }
+
+        HandleMsg::MigrateBusinesses { limit } => migrate_businesses(deps, env, limit)?,
+
+        HandleMsg::MigrateReviews {
+            business_address,
+            reviewers,
+        } => migrate_reviews(deps, env, business_address, reviewers)?,
+
+        HandleMsg::ReverifyRefunds {
+            business_address,
+            reviewer,
+            token,
             tx_page,
             viewing_key,
+        } => reverify_refunds(
+            deps,
+            env,
+            business_address,
+            reviewer,
+            token,
+            tx_page,
+            viewing_key,
+        )?,
+
+        HandleMsg::SetWeightStrategy { strategy } => set_weight_strategy(deps, env, strategy)?,
+
+        HandleMsg::RecomputeWeights {
+            business_address,
+            page,
+            page_size,
+        } => recompute_weights(deps, env, business_address, page, page_size)?,
+
+        HandleMsg::RecomputeBusiness {
+            business_address,
+            page_size,
+        } => recompute_business(deps, env, business_address, page_size)?,
+
+        HandleMsg::SetCategory {
+            name,
+            rating_scale,
+            dimensions,
+        } => set_category(deps, env, name, rating_scale, dimensions)?,
+
+        HandleMsg::ClaimBusiness { address, owner } => {
+            let (deposit_messages, answer) = claim_business(deps, env, address, owner)?;
+            messages.extend(deposit_messages);
+            answer
+        }
+
+        HandleMsg::RegisterItem {
+            business_address,
+            sku,
+            name,
+        } => register_item(deps, env, business_address, sku, name)?,
+
+        HandleMsg::AssociateAddresses {
+            business_address,
+            addresses,
+        } => associate_addresses(deps, env, business_address, addresses)?,
+
+        HandleMsg::ConfirmAssociation { business_address } => {
+            confirm_association(deps, env, business_address)?
+        }
+
+        HandleMsg::DissociateAddresses {
+            business_address,
+            addresses,
+        } => dissociate_addresses(deps, env, business_address, addresses)?,
+
+        HandleMsg::MintVouchers {
+            business_address,
+            vouchers,
+        } => mint_vouchers(deps, env, business_address, vouchers)?,
+
+        HandleMsg::SetAttestationKey {
+            business_address,
+            pubkey,
+        } => set_attestation_key(deps, env, business_address, pubkey)?,
+
+        HandleMsg::DeactivateBusiness { address } => {
+            set_business_status(deps, env, address, BusinessStatus::Closed)?
+        }
+
+        HandleMsg::ReactivateBusiness { address } => {
+            set_business_status(deps, env, address, BusinessStatus::Active)?
+        }
+
+        HandleMsg::SetModeration {
+            moderators,
+            count_hidden_reviews,
+            flag_threshold,
+        } => set_moderation(deps, env, moderators, count_hidden_reviews, flag_threshold)?,
+
+        HandleMsg::HideReview {
+            business_address,
+            reviewer,
+            reason,
+        } => set_review_hidden(deps, env, business_address, reviewer, Some(reason))?,
+
+        HandleMsg::UnhideReview {
+            business_address,
+            reviewer,
+        } => set_review_hidden(deps, env, business_address, reviewer, None)?,
+
+        HandleMsg::HideBusiness { address, reason } => {
+            set_business_hidden(deps, env, address, Some(reason))?
+        }
+
+        HandleMsg::UnhideBusiness { address } => set_business_hidden(deps, env, address, None)?,
+
+        HandleMsg::FlagReview {
+            business_address,
+            reviewer,
+            reason,
+        } => flag_review(deps, env, business_address, reviewer, reason)?,
+
+        HandleMsg::DismissFlags {
+            business_address,
+            reviewer,
+        } => dismiss_flags(deps, env, business_address, reviewer)?,
+
+        HandleMsg::RegisterBrand {
+            name,
+            owner,
+            rating_scale,
+        } => register_brand(deps, env, name, owner, rating_scale)?,
+
+        HandleMsg::AddBranch {
+            brand,
+            business_address,
+        } => add_branch(deps, env, brand, business_address)?,
+
+        HandleMsg::RemoveBranch { business_address } => remove_branch(deps, env, business_address)?,
+
+        HandleMsg::SetArbitration {
+            arbiter,
+            dispute_bond,
+        } => set_arbitration(deps, env, arbiter, dispute_bond)?,
+
+        HandleMsg::SetRegistrationDeposit { deposit } => {
+            let (register_messages, answer) = set_registration_deposit(deps, env, deposit)?;
+            messages.extend(register_messages);
+            answer
+        }
+
+        HandleMsg::SweepDeposits { business_addresses } => {
+            let (sweep_messages, answer) = sweep_deposits(deps, env, business_addresses)?;
+            messages.extend(sweep_messages);
+            answer
+        }
+
+        HandleMsg::SetRegistrationMode { mode } => set_registration_mode(deps, env, mode)?,
+
+        HandleMsg::SetVoucherBudget {
+            voucher_weight_budget,
+            max_attested_amount,
+        } => set_voucher_budget(deps, env, voucher_weight_budget, max_attested_amount)?,
+
+        HandleMsg::AddRegistrars { registrars } => set_registrars(deps, env, registrars, false)?,
+
+        HandleMsg::RemoveRegistrars { registrars } => set_registrars(deps, env, registrars, true)?,
+
+        HandleMsg::SetEditLimits {
+            min_edit_interval_seconds,
+            review_lock_seconds,
+        } => set_edit_limits(deps, env, min_edit_interval_seconds, review_lock_seconds)?,
+
+        HandleMsg::OpenDispute {
+            business_address,
+            reviewer,
+            reason,
+        } => open_dispute(deps, env, business_address, reviewer, reason)?,
+
+        HandleMsg::RespondToDispute {
+            business_address,
+            response,
+        } => respond_to_dispute(deps, env, business_address, response)?,
+
+        HandleMsg::ResolveDispute {
+            business_address,
+            reviewer,
+            outcome,
         } => {
-            let receipt = Receipt {
-                tx_id,
-                tx_page,
-                viewing_key,
-            };
-            review_business(deps, env, address, content, rating, title, receipt)?
+            let (bond_messages, answer) =
+                resolve_dispute(deps, env, business_address, reviewer, outcome)?;
+            messages.extend(bond_messages);
+            answer
         }
     };
 
     Ok(HandleResponse {
-        messages: vec![],
+        messages,
         log: vec![],
         data: Some(to_binary(&answer)?),
     })
@@ -69,35 +378,76 @@ pub fn handle<S: Storage, A: Api, Q: Querier>(
 
 /* This handle variant is used both for reviewing and updating previous reviews.
 If a review already exists for that reviewer on that business, then the review
-and rating get updated. If the function was triggered with a new receipt, its
-weight is added to the previous reviewer's weight. If the reviewer wants to
-edit the review but has no new transactions with the business, then simply
-sending the same tx_id will update the review. */
+and rating get updated. Every new receipt in the list is verified and its weight
+is added to the previous reviewer's weight, while receipts that were already
+used are skipped. If the reviewer wants to edit the review but has no new
+transactions with the business, then simply sending the same receipts (or none)
+will update the review. */
 fn review_business<S: Storage, A: Api, Q: Querier>(
     deps: &mut Extern<S, A, Q>,
     env: Env,
     address: HumanAddr,
-    content: String,
-    rating: u8,
-    title: String,
-    receipt: Receipt,
+    submission: ReviewSubmission,
 ) -> StdResult<HandleAnswer> {
-    if rating > MAX_RATING {
-        return Err(StdError::generic_err(format!(
-            "ratings must be between 0 and {} stars",
-            MAX_RATING
-        )));
+    let ReviewSubmission {
+        title,
+        content,
+        rating,
+        receipts,
+        dimension_ratings,
+        item,
+    } = submission;
+    let mut status;
+
+    let mut existing_business =
+        get_business_by_address(&deps.storage, &address)?.ok_or_else(|| {
+            StdError::generic_err(
+                "There is no business registered on that address. You can register it instead.",
+            )
+        })?;
+    // the reviews of a closed business are kept as they were
+    if existing_business.status == BusinessStatus::Closed {
+        return Err(StdError::generic_err(
+            "The business is closed and can no longer be reviewed",
+        ));
+    }
+    if env.message.sender == address {
+        return Err(StdError::generic_err("A business can't review itself"));
+    }
+    if existing_business.owner.as_ref() == Some(&env.message.sender) {
+        return Err(StdError::generic_err(
+            "The owner of a business can't review it",
+        ));
+    }
+    if is_associated_address(&deps.storage, &address, &env.message.sender) {
+        return Err(StdError::generic_err(
+            "Addresses that are associated with the business can't review it",
+        ));
     }
 
-    let mut status;
+    let config = load_config(&deps.storage)?;
+    let rating_scale = rating_scale_of(&deps.storage, &config, &existing_business);
+    let rating = rating_scale.normalize(rating)?;
+    let dimension_ratings = normalize_dimension_ratings(
+        &deps.storage,
+        &existing_business,
+        &rating_scale,
+        dimension_ratings,
+    )?;
 
-    let existing_business = get_business_by_address(&deps.storage, &address)?.ok_or_else(|| {
-        StdError::generic_err(
-            "There is no business registered on that address. You can register it instead.",
-        )
-    })?;
+    if let Some(sku) = &item {
+        if may_load_item(&deps.storage, &address, sku).is_none() {
+            return Err(StdError::generic_err(format!(
+                "There is no item with sku {} in the business' catalog",
+                sku
+            )));
+        }
+    }
 
     let previous_review = may_load_review(&deps.storage, &address, &env.message.sender);
+    if let Some(previous_review) = &previous_review {
+        check_edit_limits(&config, env.block.time, previous_review)?;
+    }
 
     let mut increment_count: u8 = 0;
     if previous_review.is_none() {
@@ -109,484 +459,6731 @@ fn review_business<S: Storage, A: Api, Q: Querier>(
 
     // this review will get overriden but it is useful as
     // a starting point for tx and weight accumulation
-    let mut base_review = previous_review.unwrap_or(Review {
+    let mut base_review = previous_review.clone().unwrap_or(Review {
+        reviewer: env.message.sender.clone(),
         title: "".to_string(),
         content: "".to_string(),
         rating: 0,
+        dimension_ratings: vec![],
+        item: None,
         weight: Uint128(0),
+        applied_weight: Uint128(0),
+        counted: false,
         tx_ids: vec![],
+        receipt_amounts: vec![],
+        refund_ids: vec![],
+        refunded_receipts: vec![],
+        latest_receipt_time: None,
+        latest_receipt_height: None,
         last_update_timestamp: env.block.time,
+        created_timestamp: Some(env.block.time),
+        hidden: None,
+        dispute_outcome: None,
+        associated: false,
+        revisions_count: 0,
     });
 
-    let previous_weight = base_review.weight.u128();
-    let previous_rating = base_review.rating;
-
     let mut new_weight_from_tx = 0;
-    if !base_review.tx_ids.contains(&receipt.tx_id) {
-        status.push_str(", receipt was accounted for");
-
-        let tx = query_snip20_tx(
-            &deps.querier,
-            receipt.tx_id,
-            receipt.viewing_key,
-            receipt.tx_page,
-            &env.message.sender,
-        )?;
+    let mut accounted_receipts = 0;
+    // the pages of transfer history that receipts were verified on, to look for refunds on them
+    let mut refund_pages = vec![];
+    let mut used_receipts = 0;
+    for receipt in receipts {
+        // receipts that are cited twice in the same list are also skipped here
+        let receipt_id = receipt_id_of(&receipt);
+        if base_review.tx_ids.contains(&receipt_id) {
+            used_receipts += 1;
+            continue;
+        }
 
-        if tx.sender != env.message.sender {
-            return Err(StdError::generic_err(
-                "The specified transfer was not spent by the authenticated account",
-            ));
+        let (verified, min_amount, unit) = match receipt {
+            Receipt::Snip20 {
+                token,
+                tx_id,
+                tx_page,
+                viewing_key,
+            } => {
+                let token = accepted_token(&config, &token_or_sscrt(token))?;
+                let txs = query_snip20_history_page(
+                    &deps.querier,
+                    token,
+                    viewing_key,
+                    tx_page,
+                    &env.message.sender,
+                )?;
+                let verified = verify_snip20_receipt(&txs, &env.message.sender, &address, tx_id)?;
+                if config.refund_netting {
+                    refund_pages.push((token.address.clone(), txs));
+                }
+                (
+                    verified,
+                    token.min_receipt_amount,
+                    token.address.to_string(),
+                )
+            }
+            Receipt::Native { receipt_id } => {
+                let verified = verify_native_receipt(
+                    &deps.storage,
+                    &env.message.sender,
+                    &address,
+                    receipt_id,
+                )?;
+                (
+                    verified,
+                    config.min_native_receipt_amount,
+                    NATIVE_DENOM.to_string(),
+                )
+            }
+            Receipt::Voucher { secret } => {
+                let verified = redeem_voucher(&mut deps.storage, &env.block, &address, &secret)?;
+                (verified, Uint128::zero(), "voucher".to_string())
+            }
+            Receipt::Attestation {
+                amount,
+                nonce,
+                signature,
+            } => {
+                let verified = verify_attestation(
+                    &mut deps.storage,
+                    &env,
+                    &config,
+                    &mut existing_business,
+                    amount,
+                    nonce,
+                    &signature,
+                )?;
+                (verified, Uint128::zero(), "attestation".to_string())
+            }
+        };
+
+        if verified.amount < min_amount.u128() {
+            return Err(StdError::generic_err(format!(
+                "Receipts must be of at least {} {}",
+                min_amount, unit
+            )));
         }
+        check_receipt_age(
+            &config,
+            &env.block,
+            verified.block_time,
+            verified.block_height,
+        )?;
 
-        if tx.receiver != address {
-            return Err(StdError::generic_err(
-                "The specified transfer's recipient is not the specified business",
-            ));
+        new_weight_from_tx = result_add(new_weight_from_tx, verified.amount)?;
+        base_review.latest_receipt_time =
+            std::cmp::max(base_review.latest_receipt_time, verified.block_time);
+        base_review.latest_receipt_height =
+            std::cmp::max(base_review.latest_receipt_height, verified.block_height);
+        if let ReceiptId::Snip20 { .. } = receipt_id {
+            base_review.receipt_amounts.push(ReceiptAmount {
+                receipt: receipt_id.clone(),
+                amount: Uint128::from(verified.amount),
+            });
         }
-        new_weight_from_tx = tx.coins.amount.u128();
+        base_review.tx_ids.push(receipt_id);
+        accounted_receipts += 1;
+    }
 
-        base_review.weight =
-            Uint128::from(result_add(base_review.weight.u128(), new_weight_from_tx)?);
-        base_review.tx_ids.push(receipt.tx_id);
-    } else {
-        status.push_str(", specified receipt was already used");
+    // reviews that were migrated from the legacy layout don't know when their receipts were
+    // made, so their last update stands in for the time, and their height isn't checked
+    let (receipt_time, receipt_height) = match (
+        base_review.latest_receipt_time,
+        base_review.latest_receipt_height,
+    ) {
+        (None, None) => (
+            Some(base_review.last_update_timestamp),
+            Some(env.block.height),
+        ),
+        known => known,
+    };
+    if increment_count == 0
+        && !config.allow_stale_edits
+        && check_receipt_age(&config, &env.block, receipt_time, receipt_height).is_err()
+    {
+        return Err(StdError::generic_err(
+            "All the receipts of this review are too old for it to be edited",
+        ));
+    }
+
+    if increment_count == 1 && accounted_receipts == 0 {
+        return Err(StdError::generic_err(
+            "A new review must provide at least one receipt",
+        ));
+    }
+
+    match accounted_receipts {
+        0 => {}
+        1 => status.push_str(", receipt was accounted for"),
+        n => status.push_str(&format!(", {} receipts were accounted for", n)),
+    }
+    match used_receipts {
+        0 => {}
+        1 => status.push_str(", specified receipt was already used"),
+        n => status.push_str(&format!(", {} specified receipts were already used", n)),
+    }
+
+    base_review.weight = Uint128::from(result_add(base_review.weight.u128(), new_weight_from_tx)?);
+
+    let mut netted_refunds = 0;
+    for (token, txs) in &refund_pages {
+        netted_refunds += net_refunds(&mut base_review, &address, token, txs)?;
+    }
+    match netted_refunds {
+        0 => {}
+        1 => status.push_str(", a refund was netted from its weight"),
+        n => status.push_str(&format!(", {} refunds were netted from its weight", n)),
     }
 
     base_review.title = title;
     base_review.content = content;
     base_review.rating = rating;
+    base_review.dimension_ratings = dimension_ratings;
+    base_review.item = item;
+    base_review.last_update_timestamp = env.block.time;
 
-    create_review(
+    // reviews that were written before histories were kept start theirs with their last version
+    if let Some(previous_review) = previous_review.as_ref() {
+        if previous_review.revisions_count == 0 {
+            append_revision(
+                &mut deps.storage,
+                &address,
+                &env.message.sender,
+                previous_review.revision(),
+            )?;
+        }
+    }
+    base_review.revisions_count = append_revision(
         &mut deps.storage,
         &address,
         &env.message.sender,
-        base_review,
-    )?;
-
-    let (new_average, new_weight) = recalculate_weighted_average(
-        new_weight_from_tx,
-        previous_weight,
-        rating as u128,
-        previous_rating as u128,
-        existing_business.total_weight.u128(),
-        existing_business.average_rating.u128(),
+        base_review.revision(),
     )?;
 
-    apply_review_on_business(
+    save_review_and_aggregates(
         &mut deps.storage,
-        address,
-        new_weight,
-        new_average,
-        increment_count,
+        &config,
+        env.block.time,
+        &existing_business,
+        previous_review.as_ref(),
+        base_review,
     )?;
 
     Ok(HandleAnswer::ReviewBusiness { status })
 }
 
-fn register_business<S: Storage, A: Api, Q: Querier>(
-    deps: &mut Extern<S, A, Q>,
-    _env: Env,
-    name: String,
-    address: HumanAddr,
-    description: String,
-) -> StdResult<HandleAnswer> {
-    if description.chars().count() as u8 > MAX_DESCRIPTION_LENGTH {
+/* Editing a review over and over would move the business' average with every
+edit, so edits are spaced by the minimum interval, and reviews are locked once
+they are old enough. */
+fn check_edit_limits(config: &Config, now: u64, review: &Review) -> StdResult<()> {
+    let created_timestamp = review
+        .created_timestamp
+        .unwrap_or(review.last_update_timestamp);
+    if config.review_lock_seconds > 0
+        && now >= created_timestamp.saturating_add(config.review_lock_seconds)
+    {
+        return Err(StdError::generic_err(
+            "The review is locked and can no longer be edited",
+        ));
+    }
+
+    let next_edit_time = review
+        .last_update_timestamp
+        .saturating_add(config.min_edit_interval_seconds);
+    if now < next_edit_time {
         return Err(StdError::generic_err(format!(
-            "Description length can't be bigger than {}",
-            MAX_DESCRIPTION_LENGTH
+            "A review can only be edited once every {} seconds, the next edit is possible in {} seconds",
+            config.min_edit_interval_seconds,
+            next_edit_time - now
         )));
     }
 
-    if name.chars().count() as u8 > MAX_NAME_LENGTH {
+    Ok(())
+}
+
+/* A review must rate every dimension of its business' category, once. The ratings
+are kept sorted by dimension, like the business' aggregates. */
+fn normalize_dimension_ratings<S: Storage>(
+    store: &S,
+    business: &Business,
+    rating_scale: &RatingScale,
+    mut dimension_ratings: Vec<DimensionRating>,
+) -> StdResult<Vec<DimensionRating>> {
+    let dimensions = business
+        .category
+        .as_ref()
+        .and_then(|name| may_load_category(store, name))
+        .map(|category| category.dimensions)
+        .unwrap_or_default();
+
+    dimension_ratings.sort_by(|a, b| a.dimension.cmp(&b.dimension));
+    let mut rated: Vec<&String> = dimension_ratings.iter().map(|r| &r.dimension).collect();
+    rated.dedup();
+    let mut expected: Vec<&String> = dimensions.iter().collect();
+    expected.sort();
+    if rated.len() != dimension_ratings.len() || rated != expected {
         return Err(StdError::generic_err(format!(
-            "Name length can't be bigger than {}",
-            MAX_NAME_LENGTH
+            "A rating must be given for each of the dimensions: {}",
+            dimensions.join(", ")
         )));
     }
 
-    // check that a correctly formatted address was given
-    deps.api.canonical_address(&address)?;
+    for dimension_rating in dimension_ratings.iter_mut() {
+        dimension_rating.rating = rating_scale.normalize(dimension_rating.rating)?;
+    }
+    Ok(dimension_ratings)
+}
 
-    let new_business = Business {
-        name,
-        description,
-        address: HumanAddr(address.to_string()),
-        average_rating: Uint128::from(0u128),
-        reviews_count: 0,
-        total_weight: Uint128::from(0u128),
-    };
+/* Adds the weighted dimension ratings of a review to a business' aggregates, or
+removes them. Dimensions that no review rates anymore are dropped. */
+fn apply_dimension_ratings(
+    aggregates: &mut Vec<DimensionAggregate>,
+    dimension_ratings: &[DimensionRating],
+    weight: u128,
+    remove: bool,
+) -> StdResult<()> {
+    for dimension_rating in dimension_ratings {
+        let position =
+            match aggregates.binary_search_by(|a| a.dimension.cmp(&dimension_rating.dimension)) {
+                Ok(position) => position,
+                Err(position) => {
+                    aggregates.insert(
+                        position,
+                        DimensionAggregate {
+                            dimension: dimension_rating.dimension.clone(),
+                            total_weight: Uint128::zero(),
+                            rating_sum: Uint128::zero(),
+                        },
+                    );
+                    position
+                }
+            };
 
-    create_business(&mut deps.storage, new_business)?;
+        let aggregate = &mut aggregates[position];
+        let weighted_rating = result_mul(weight, dimension_rating.rating as u128)?;
+        let (total_weight, rating_sum) = if remove {
+            (
+                result_sub(aggregate.total_weight.u128(), weight)?,
+                result_sub(aggregate.rating_sum.u128(), weighted_rating)?,
+            )
+        } else {
+            (
+                result_add(aggregate.total_weight.u128(), weight)?,
+                result_add(aggregate.rating_sum.u128(), weighted_rating)?,
+            )
+        };
+        aggregate.total_weight = Uint128::from(total_weight);
+        aggregate.rating_sum = Uint128::from(rating_sum);
+    }
 
-    Ok(HandleAnswer::RegisterBusiness {
-        status: "successfully called register business".to_string(),
-    })
+    aggregates.retain(|a| !a.total_weight.is_zero() || !a.rating_sum.is_zero());
+    Ok(())
 }
 
-pub fn query<S: Storage, A: Api, Q: Querier>(deps: &Extern<S, A, Q>, msg: QueryMsg) -> QueryResult {
-    match msg {
-        QueryMsg::GetBusinesses { page, page_size } => {
-            query_businesses(&deps.storage, page, page_size)
-        }
-        QueryMsg::GetSingleBusiness { address } => query_business(&deps.storage, address),
-        QueryMsg::GetReviewsOnBusiness {
-            business_address,
-            page,
-            page_size,
-        } => query_reviews(&deps.storage, business_address, page, page_size),
+/* Saves a review and replaces the contribution of its previous version in the
+business' aggregates with the contribution of the new one. The weight strategy is
+applied on the review's raw weight here, so that it is applied the same way by
+every handle that changes a review. */
+fn save_review_and_aggregates<S: Storage>(
+    store: &mut S,
+    config: &Config,
+    now: u64,
+    business: &Business,
+    previous_review: Option<&Review>,
+    mut review: Review,
+) -> StdResult<()> {
+    // a rebuild that is in progress would miss the change on the pages that it already went
+    // through, and overwrite it once it is done
+    if may_load_aggregate_rebuild(store, &business.address).is_some() {
+        return Err(StdError::generic_err(
+            "The aggregates of the business are being rebuilt, its reviews can change again once the rebuild is done",
+        ));
     }
-}
 
-pub fn query_businesses<S: Storage>(
-    store: &S,
-    page: Option<u32>,
-    page_size: u32,
-) -> StdResult<Binary> {
-    let (businesses_in_range, total) = get_businesses_page(store, page, page_size)?;
-    let displayed_businesses = businesses_in_range
-        .iter()
-        .map(|b| DisplayedBusiness {
-            name: b.name.clone(),
-            description: b.description.clone(),
-            address: b.address.clone(),
-            average_rating: b.average_rating,
-            reviews_count: b.reviews_count,
-        })
-        .collect();
+    let mut updated_business = business.clone();
+    updated_business.hidden_reviews_count += review.hidden.is_some() as u32;
+    updated_business.hidden_reviews_count -=
+        previous_review.map_or(false, |r| r.hidden.is_some()) as u32;
 
-    to_binary(&QueryAnswer::Businesses {
-        businesses: displayed_businesses,
-        total,
-    })
-}
+    // reviews that don't count toward the aggregates, e.g. hidden ones, contribute nothing. The
+    // previous version is taken out as it was counted, even if the config changed since.
+    let previous_review = previous_review.filter(|r| r.counted);
+    let counted = review.counts_toward_aggregates(config);
+    review.counted = counted;
 
-pub fn query_business<S: Storage>(store: &S, address: HumanAddr) -> StdResult<Binary> {
-    let business = get_business_by_address(store, &address)?;
+    let (previous_raw_weight, previous_applied_weight, previous_rating) = previous_review
+        .map(|r| (r.weight.u128(), r.applied_weight.u128(), r.rating))
+        .unwrap_or((0, 0, 0));
 
-    let status = match business {
-        None => "No business is registered on that address".to_string(),
-        Some(..) => "Successfully retrieved business by address".to_string(),
-    };
+    let new_total_raw_weight = result_add(
+        result_sub(business.total_raw_weight.u128(), previous_raw_weight)?,
+        if counted { review.weight.u128() } else { 0 },
+    )?;
+    let applied_weight = apply_weight_strategy(
+        &config.weight_strategy,
+        review.weight.u128(),
+        new_total_raw_weight,
+    )?;
+    review.applied_weight = Uint128::from(applied_weight);
+    let counted_weight = if counted { applied_weight } else { 0 };
 
-    to_binary(&QueryAnswer::SingleBusiness {
-        business: business.map(|b| DisplayedBusiness {
-            name: b.name,
-            description: b.description,
-            address: b.address,
-            average_rating: b.average_rating,
-            reviews_count: b.reviews_count,
-        }),
-        status,
-    })
-}
+    let new_total_weight = result_add(
+        result_sub(business.total_weight.u128(), previous_applied_weight)?,
+        counted_weight,
+    )?;
+    let new_rating_sum = result_add(
+        result_sub(
+            business.rating_sum.u128(),
+            result_mul(previous_applied_weight, previous_rating as u128)?,
+        )?,
+        result_mul(counted_weight, review.rating as u128)?,
+    )?;
 
-pub fn query_reviews<S: Storage>(
-    store: &S,
-    business_address: HumanAddr,
-    page: Option<u32>,
-    page_size: u32,
-) -> StdResult<Binary> {
-    let (reviews_page, total) = get_reviews_on_business(store, &business_address, page, page_size)?;
+    updated_business.total_weight = Uint128::from(new_total_weight);
+    updated_business.rating_sum = Uint128::from(new_rating_sum);
+    updated_business.total_raw_weight = Uint128::from(new_total_raw_weight);
+    updated_business.reviews_count += counted as u32;
+    updated_business.reviews_count -= previous_review.is_some() as u32;
+    if let Some(previous_review) = previous_review {
+        apply_dimension_ratings(
+            &mut updated_business.dimensions,
+            &previous_review.dimension_ratings,
+            previous_applied_weight,
+            true,
+        )?;
+    }
+    if counted {
+        apply_dimension_ratings(
+            &mut updated_business.dimensions,
+            &review.dimension_ratings,
+            applied_weight,
+            false,
+        )?;
+    }
+    if let Some(time_decay) = &config.time_decay {
+        apply_review_on_decayed_sums(
+            &mut updated_business,
+            time_decay,
+            now,
+            previous_review,
+            Some(&review).filter(|_| counted),
+        )?;
+    }
 
-    to_binary(&QueryAnswer::Reviews {
-        reviews: reviews_page,
-        total,
-    })
+    if let Some(previous_review) = previous_review {
+        apply_review_on_item(store, &business.address, previous_review, true)?;
+    }
+    if counted {
+        apply_review_on_item(store, &business.address, &review, false)?;
+    }
+
+    let reviewer = review.reviewer.clone();
+    create_review(store, &business.address, &reviewer, review)?;
+    update_business(store, updated_business)
 }
 
-#[cfg(test)]
+// adds the applied weight and rating of a review to the aggregates of its item, or removes them
+fn apply_review_on_item<S: Storage>(
+    store: &mut S,
+    business_address: &HumanAddr,
+    review: &Review,
+    remove: bool,
+) -> StdResult<()> {
+    let sku = match &review.item {
+        Some(sku) => sku,
+        None => return Ok(()),
+    };
+    let mut item = may_load_item(store, business_address, sku)
+        .ok_or_else(|| StdError::generic_err(format!("There is no item with sku {}", sku)))?;
+
+    let weight = review.applied_weight.u128();
+    let weighted_rating = result_mul(weight, review.rating as u128)?;
+    if remove {
+        item.reviews_count = item
+            .reviews_count
+            .checked_sub(1)
+            .ok_or_else(|| StdError::generic_err("underflow in subtraction"))?;
+        item.total_weight = Uint128::from(result_sub(item.total_weight.u128(), weight)?);
+        item.rating_sum = Uint128::from(result_sub(item.rating_sum.u128(), weighted_rating)?);
+    } else {
+        item.reviews_count += 1;
+        item.total_weight = Uint128::from(result_add(item.total_weight.u128(), weight)?);
+        item.rating_sum = Uint128::from(result_add(item.rating_sum.u128(), weighted_rating)?);
+    }
+
+    save_item(store, business_address, item)
+}
+
+/* Brings the business' decayed sums up to the current time, and replaces the
+decayed contribution of the previous version of a review with that of the new
+one, if they count. A review's contribution decays from its last update. */
+fn apply_review_on_decayed_sums(
+    business: &mut Business,
+    time_decay: &TimeDecay,
+    now: u64,
+    previous_review: Option<&Review>,
+    review: Option<&Review>,
+) -> StdResult<()> {
+    let half_life = time_decay.half_life_seconds;
+    let factor = decay_factor(now.saturating_sub(business.decay_timestamp), half_life);
+    let mut decayed_weight = apply_decay(business.decayed_weight.u128(), factor);
+    let mut decayed_rating_sum = apply_decay(business.decayed_rating_sum.u128(), factor);
+
+    if let Some(previous_review) = previous_review {
+        let (weight, rating_sum) = decayed_contribution(
+            previous_review.applied_weight.u128(),
+            previous_review.rating as u128,
+            now.saturating_sub(previous_review.last_update_timestamp),
+            half_life,
+        )?;
+        // the sums were rounded down at every decay, so they can fall slightly short of the
+        // contribution that is removed
+        decayed_weight = decayed_weight.saturating_sub(weight);
+        decayed_rating_sum = decayed_rating_sum.saturating_sub(rating_sum);
+    }
+
+    if let Some(review) = review {
+        let (weight, rating_sum) = decayed_contribution(
+            review.applied_weight.u128(),
+            review.rating as u128,
+            now.saturating_sub(review.last_update_timestamp),
+            half_life,
+        )?;
+        decayed_weight = result_add(decayed_weight, weight)?;
+        decayed_rating_sum = result_add(decayed_rating_sum, rating_sum)?;
+    }
+    business.decayed_weight = Uint128::from(decayed_weight);
+    business.decayed_rating_sum = Uint128::from(decayed_rating_sum);
+    business.decay_timestamp = now;
+
+    Ok(())
+}
+
+fn token_or_sscrt(token: Option<HumanAddr>) -> HumanAddr {
+    token.unwrap_or_else(|| HumanAddr(SSCRT_ADDRESS.to_string()))
+}
+
+fn receipt_id_of(receipt: &Receipt) -> ReceiptId {
+    match receipt {
+        Receipt::Snip20 { token, tx_id, .. } => ReceiptId::Snip20 {
+            token: token_or_sscrt(token.clone()),
+            tx_id: *tx_id,
+        },
+        Receipt::Native { receipt_id } => ReceiptId::Native {
+            receipt_id: *receipt_id,
+        },
+        Receipt::Voucher { secret } => ReceiptId::Voucher {
+            secret_hash: voucher_hash(secret),
+        },
+        Receipt::Attestation { nonce, .. } => ReceiptId::Attestation { nonce: *nonce },
+    }
+}
+
+fn voucher_hash(secret: &str) -> Binary {
+    Binary(Sha256::digest(secret.as_bytes()).to_vec())
+}
+
+fn accepted_token<'a>(config: &'a Config, address: &HumanAddr) -> StdResult<&'a Snip20Token> {
+    config
+        .accepted_tokens
+        .iter()
+        .find(|token| token.address == *address)
+        .ok_or_else(|| StdError::generic_err(format!("{} is not an accepted token", address)))
+}
+
+fn verify_snip20_receipt(
+    txs: &[Tx],
+    reviewer: &HumanAddr,
+    business_address: &HumanAddr,
+    tx_id: u64,
+) -> StdResult<VerifiedReceipt> {
+    let tx = find_snip20_tx(txs, tx_id)?;
+
+    if tx.sender != *reviewer {
+        return Err(StdError::generic_err(
+            "The specified transfer was not spent by the authenticated account",
+        ));
+    }
+
+    if tx.receiver != *business_address {
+        return Err(StdError::generic_err(
+            "The specified transfer's recipient is not the specified business",
+        ));
+    }
+
+    Ok(VerifiedReceipt {
+        amount: tx.coins.amount.u128(),
+        block_time: tx.block_time,
+        block_height: tx.block_height,
+    })
+}
+
+fn check_receipt_age(
+    config: &Config,
+    block: &BlockInfo,
+    receipt_time: Option<u64>,
+    receipt_height: Option<u64>,
+) -> StdResult<()> {
+    let (current, receipt, max_age, unit) = match config.max_receipt_age {
+        None => return Ok(()),
+        Some(ReceiptAge::Seconds(max_age)) => (block.time, receipt_time, max_age, "seconds"),
+        Some(ReceiptAge::Blocks(max_age)) => (block.height, receipt_height, max_age, "blocks"),
+    };
+
+    match receipt {
+        None => Err(StdError::generic_err(
+            "The age of the receipt can't be verified",
+        )),
+        Some(receipt) if current.saturating_sub(receipt) > max_age => {
+            Err(StdError::generic_err(format!(
+                "The receipt is older than the maximum receipt age of {} {}",
+                max_age, unit
+            )))
+        }
+        Some(..) => Ok(()),
+    }
+}
+
+/* A transfer from the business back to the reviewer is taken for the refund of a
+receipt of the review in the same token when its memo is the receipt's tx id, or
+when it is of the receipt's amount. Every receipt is netted once, and a refund
+can't take more weight than the review has left. */
+fn net_refunds(
+    review: &mut Review,
+    business_address: &HumanAddr,
+    token: &HumanAddr,
+    txs: &[Tx],
+) -> StdResult<u32> {
+    let mut netted_refunds = 0;
+    for tx in txs {
+        if tx.from != *business_address || tx.receiver != review.reviewer {
+            continue;
+        }
+
+        let refund_id = ReceiptId::Snip20 {
+            token: token.clone(),
+            tx_id: tx.id,
+        };
+        if review.refund_ids.contains(&refund_id) {
+            continue;
+        }
+
+        let memo = tx.memo.as_deref().map(str::trim);
+        let refunded_receipt = review.tx_ids.iter().find(|receipt| {
+            let receipt_tx_id = match receipt {
+                ReceiptId::Snip20 {
+                    token: receipt_token,
+                    tx_id,
+                } if receipt_token == token => *tx_id,
+                _ => return false,
+            };
+            !review.refunded_receipts.contains(receipt)
+                && (memo == Some(receipt_tx_id.to_string().as_str())
+                    || review.receipt_amounts.iter().any(|receipt_amount| {
+                        receipt_amount.receipt == **receipt
+                            && receipt_amount.amount == tx.coins.amount
+                    }))
+        });
+        let refunded_receipt = match refunded_receipt {
+            Some(receipt) => receipt.clone(),
+            None => continue,
+        };
+
+        let netted = std::cmp::min(review.weight.u128(), tx.coins.amount.u128());
+        review.weight = Uint128::from(result_sub(review.weight.u128(), netted)?);
+        review.refund_ids.push(refund_id);
+        review.refunded_receipts.push(refunded_receipt);
+        netted_refunds += 1;
+    }
+
+    Ok(netted_refunds)
+}
+
+/* When refund netting is enabled, refunds that were made after a review was
+written can be netted from it by scanning a page of transfer history. The
+reviewer can scan their own history, and since reviewers have little reason to
+do so, the owner of the business and the admin can scan the business' history
+instead. */
+fn reverify_refunds<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    business_address: HumanAddr,
+    reviewer: Option<HumanAddr>,
+    token: Option<HumanAddr>,
+    tx_page: u32,
+    viewing_key: String,
+) -> StdResult<HandleAnswer> {
+    let config = load_config(&deps.storage)?;
+    if !config.refund_netting {
+        return Err(StdError::generic_err(
+            "Refund netting is not enabled in this deployment",
+        ));
+    }
+
+    let existing_business =
+        get_business_by_address(&deps.storage, &business_address)?.ok_or_else(|| {
+            StdError::generic_err(
+                "There is no business registered on that address. You can register it instead.",
+            )
+        })?;
+
+    let reviewer = reviewer.unwrap_or_else(|| env.message.sender.clone());
+    let history_owner = if reviewer == env.message.sender {
+        &reviewer
+    } else if existing_business.owner.as_ref() == Some(&env.message.sender)
+        || env.message.sender == config.admin
+    {
+        &business_address
+    } else {
+        return Err(StdError::unauthorized());
+    };
+
+    let mut review =
+        may_load_review(&deps.storage, &business_address, &reviewer).ok_or_else(|| {
+            StdError::generic_err("There is no review of that reviewer on that business")
+        })?;
+
+    let token = accepted_token(&config, &token_or_sscrt(token))?;
+    let txs = query_snip20_history_page(&deps.querier, token, viewing_key, tx_page, history_owner)?;
+
+    let previous_review = review.clone();
+    let netted_refunds = net_refunds(&mut review, &business_address, &token.address, &txs)?;
+
+    save_review_and_aggregates(
+        &mut deps.storage,
+        &config,
+        env.block.time,
+        &existing_business,
+        Some(&previous_review),
+        review,
+    )?;
+
+    Ok(HandleAnswer::ReverifyRefunds {
+        status: format!("Netted {} refunds from the review's weight", netted_refunds),
+    })
+}
+
+fn verify_native_receipt<S: Storage>(
+    store: &S,
+    reviewer: &HumanAddr,
+    business_address: &HumanAddr,
+    receipt_id: u64,
+) -> StdResult<VerifiedReceipt> {
+    let receipt = may_load_native_receipt(store, receipt_id).ok_or_else(|| {
+        StdError::generic_err(format!("there is no native receipt with id {}", receipt_id))
+    })?;
+
+    if receipt.payer != *reviewer {
+        return Err(StdError::generic_err(
+            "The specified native payment was not paid by the authenticated account",
+        ));
+    }
+
+    if receipt.business != *business_address {
+        return Err(StdError::generic_err(
+            "The specified native payment's recipient is not the specified business",
+        ));
+    }
+
+    Ok(VerifiedReceipt {
+        amount: receipt.amount.u128(),
+        block_time: Some(receipt.timestamp),
+        block_height: Some(receipt.block_height),
+    })
+}
+
+/* A voucher is verified by the hash of its secret, and can only be redeemed once,
+by whoever knows the secret. Its weight was already counted against the business'
+budget when it was minted. */
+fn redeem_voucher<S: Storage>(
+    store: &mut S,
+    block: &BlockInfo,
+    business_address: &HumanAddr,
+    secret: &str,
+) -> StdResult<VerifiedReceipt> {
+    let secret_hash = voucher_hash(secret);
+    let mut voucher = may_load_voucher(store, business_address, secret_hash.as_slice())
+        .ok_or_else(|| StdError::generic_err("There is no voucher with that secret"))?;
+    if voucher.redeemed {
+        return Err(StdError::generic_err("The voucher was already redeemed"));
+    }
+    if voucher
+        .expires_at
+        .map_or(false, |expires_at| block.time >= expires_at)
+    {
+        return Err(StdError::generic_err("The voucher expired"));
+    }
+
+    voucher.redeemed = true;
+    let verified = VerifiedReceipt {
+        amount: voucher.weight.u128(),
+        block_time: Some(voucher.minted_timestamp),
+        block_height: Some(voucher.minted_height),
+    };
+    save_voucher(store, business_address, secret_hash.as_slice(), voucher)?;
+
+    Ok(verified)
+}
+
+/// The message that a business signs to attest to a purchase. It names the contract and the
+/// business, so that an attestation can't be replayed against another deployment or business.
+fn attestation_message(
+    contract_address: &HumanAddr,
+    business_address: &HumanAddr,
+    reviewer: &HumanAddr,
+    amount: Uint128,
+    nonce: u64,
+) -> String {
+    format!(
+        "purchase_attestation:{}:{}:{}:{}:{}",
+        contract_address, business_address, reviewer, amount, nonce
+    )
+}
+
+/* An attestation is verified against the business' attestation key, and its nonce
+can only be used once per business, by whichever reviewer it was signed for. The
+business vouches for the amount itself, so it counts against the same budget as
+its vouchers once it is cited. The chain doesn't know when the purchase happened,
+so its age is counted from the time it is cited. */
+fn verify_attestation<S: Storage>(
+    store: &mut S,
+    env: &Env,
+    config: &Config,
+    business: &mut Business,
+    amount: Uint128,
+    nonce: u64,
+    signature: &Binary,
+) -> StdResult<VerifiedReceipt> {
+    let pubkey = business.attestation_key.as_ref().ok_or_else(|| {
+        StdError::generic_err("The business doesn't accept purchase attestations")
+    })?;
+    if amount.is_zero() || amount > config.max_attested_amount {
+        return Err(StdError::generic_err(format!(
+            "Attested amounts must be between 1 and {}",
+            config.max_attested_amount
+        )));
+    }
+    if may_load_attestation_nonce(store, &business.address, nonce).is_some() {
+        return Err(StdError::generic_err(
+            "The attestation nonce was already used",
+        ));
+    }
+
+    let message = attestation_message(
+        &env.contract.address,
+        &business.address,
+        &env.message.sender,
+        amount,
+        nonce,
+    );
+    let verifying_key = VerifyingKey::from_sec1_bytes(pubkey.as_slice())
+        .map_err(|_| StdError::generic_err("The attestation key of the business is invalid"))?;
+    Signature::try_from(signature.as_slice())
+        .and_then(|signature| verifying_key.verify(message.as_bytes(), &signature))
+        .map_err(|_| StdError::generic_err("The attestation signature is invalid"))?;
+
+    let vouchers_weight = result_add(business.vouchers_weight.u128(), amount.u128())?;
+    if vouchers_weight > config.voucher_weight_budget.u128() {
+        return Err(StdError::generic_err(format!(
+            "The attestation exceeds the voucher weight budget of {}, of which {} was already used",
+            config.voucher_weight_budget, business.vouchers_weight
+        )));
+    }
+    business.vouchers_weight = Uint128::from(vouchers_weight);
+    use_attestation_nonce(store, &business.address, nonce, env.block.time)?;
+
+    Ok(VerifiedReceipt {
+        amount: amount.u128(),
+        block_time: Some(env.block.time),
+        block_height: Some(env.block.height),
+    })
+}
+
+/* Native coins can't be verified like a SNIP-20 transfer history, so payments in
+uscrt are routed through the contract: the sent funds are forwarded to the business
+and a receipt is saved, which can later be cited in ReviewBusiness. */
+fn pay_business<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    address: HumanAddr,
+) -> StdResult<(Vec<CosmosMsg>, HandleAnswer)> {
+    if get_business_by_address(&deps.storage, &address)?.is_none() {
+        return Err(StdError::generic_err(
+            "There is no business registered on that address. You can register it instead.",
+        ));
+    }
+
+    let sent_funds = env.message.sent_funds;
+    if sent_funds.len() != 1 || sent_funds[0].denom != NATIVE_DENOM {
+        return Err(StdError::generic_err(format!(
+            "Payments must be made in {} only",
+            NATIVE_DENOM
+        )));
+    }
+
+    let amount = sent_funds[0].amount;
+    if amount.is_zero() {
+        return Err(StdError::generic_err("Payment amount must be positive"));
+    }
+
+    let receipt_id = create_native_receipt(
+        &mut deps.storage,
+        NativeReceipt {
+            payer: env.message.sender,
+            business: address.clone(),
+            amount,
+            timestamp: env.block.time,
+            block_height: env.block.height,
+        },
+    )?;
+
+    let messages = vec![CosmosMsg::Bank(BankMsg::Send {
+        from_address: env.contract.address,
+        to_address: address,
+        amount: sent_funds,
+    })];
+
+    Ok((
+        messages,
+        HandleAnswer::PayBusiness {
+            status: "Successfully paid business".to_string(),
+            receipt_id,
+        },
+    ))
+}
+
+/* Reviews used to be saved with plain sSCRT tx ids in a different namespace. The
+reviewer addresses are not kept inside the reviews, so the admin has to provide
+them in order to move those reviews into the current namespace. */
+fn migrate_reviews<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    business_address: HumanAddr,
+    reviewers: Vec<HumanAddr>,
+) -> StdResult<HandleAnswer> {
+    let config = load_config(&deps.storage)?;
+    if env.message.sender != config.admin {
+        return Err(StdError::unauthorized());
+    }
+
+    let (migrated, superseded) = migrate_legacy_reviews(
+        &mut deps.storage,
+        &business_address,
+        &reviewers,
+        &HumanAddr(SSCRT_ADDRESS.to_string()),
+    )?;
+
+    Ok(HandleAnswer::MigrateReviews {
+        status: format!(
+            "Migrated {} reviews, dropped {} that were replaced by newer ones",
+            migrated, superseded
+        ),
+    })
+}
+
+fn migrate_businesses<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    limit: u32,
+) -> StdResult<HandleAnswer> {
+    let config = load_config(&deps.storage)?;
+    if env.message.sender != config.admin {
+        return Err(StdError::unauthorized());
+    }
+
+    let (migrated, left) = migrate_legacy_businesses(&mut deps.storage, limit)?;
+
+    Ok(HandleAnswer::MigrateBusinesses {
+        status: format!("Migrated {} businesses, {} are left", migrated, left),
+    })
+}
+
+fn validate_weight_strategy(strategy: WeightStrategy) -> StdResult<WeightStrategy> {
+    if let WeightStrategy::Capped { max_share_percent } = strategy {
+        if max_share_percent == 0 || max_share_percent > 100 {
+            return Err(StdError::generic_err(
+                "The maximum share of a reviewer must be between 1 and 100 percent",
+            ));
+        }
+    }
+
+    Ok(strategy)
+}
+
+fn set_weight_strategy<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    strategy: WeightStrategy,
+) -> StdResult<HandleAnswer> {
+    let mut config = load_config(&deps.storage)?;
+    if env.message.sender != config.admin {
+        return Err(StdError::unauthorized());
+    }
+
+    config.weight_strategy = validate_weight_strategy(strategy)?;
+    save_config(&mut deps.storage, &config)?;
+
+    Ok(HandleAnswer::SetWeightStrategy {
+        status: "Weight strategy was updated, existing reviews keep their weight until they are recomputed".to_string(),
+    })
+}
+
+/* Changing the weight strategy, or the totals that a capped strategy depends on,
+leaves the applied weights of existing reviews as they were. This handle reapplies
+the current strategy on a page of a business' reviews. Recomputing a review twice
+has no further effect, so the pages can be processed in any order. */
+fn recompute_weights<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    business_address: HumanAddr,
+    page: u32,
+    page_size: u32,
+) -> StdResult<HandleAnswer> {
+    let config = load_config(&deps.storage)?;
+    if env.message.sender != config.admin {
+        return Err(StdError::unauthorized());
+    }
+
+    if get_business_by_address(&deps.storage, &business_address)?.is_none() {
+        return Err(StdError::generic_err(
+            "There is no business registered on that address. You can register it instead.",
+        ));
+    }
+
+    let (reviews, _) = get_reviews_page(&deps.storage, &business_address, page, page_size)?;
+    let recomputed = reviews.len();
+    for review in reviews {
+        // the aggregates change with every review, so the business is reloaded each time
+        let business = get_business_by_address(&deps.storage, &business_address)?
+            .ok_or_else(|| StdError::generic_err("business was removed during recompute"))?;
+        let previous_review = review.clone();
+        save_review_and_aggregates(
+            &mut deps.storage,
+            &config,
+            env.block.time,
+            &business,
+            Some(&previous_review),
+            review,
+        )?;
+    }
+
+    Ok(HandleAnswer::RecomputeWeights {
+        status: format!("Recomputed the weights of {} reviews", recomputed),
+    })
+}
+
+/* Rebuilds the aggregates of a business from its reviews, one page per call, and
+replaces the stored ones once the last page was processed. The progress is kept
+between calls, and the reviews of the business can't change until the rebuild is
+done, so that the rebuilt aggregates still match them when they are saved. */
+fn recompute_business<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    business_address: HumanAddr,
+    page_size: u32,
+) -> StdResult<HandleAnswer> {
+    let config = load_config(&deps.storage)?;
+    if env.message.sender != config.admin {
+        return Err(StdError::unauthorized());
+    }
+
+    let mut business =
+        get_business_by_address(&deps.storage, &business_address)?.ok_or_else(|| {
+            StdError::generic_err(
+                "There is no business registered on that address. You can register it instead.",
+            )
+        })?;
+
+    let mut rebuild = match may_load_aggregate_rebuild(&deps.storage, &business_address) {
+        Some(rebuild) => rebuild,
+        None => start_rebuild(&business, page_size)?,
+    };
+
+    if !continue_rebuild(&deps.storage, &config, &business_address, &mut rebuild)? {
+        let status = format!(
+            "Rebuilt page {} of the business' aggregates, call again to continue",
+            rebuild.next_page - 1
+        );
+        save_aggregate_rebuild(&mut deps.storage, &business_address, rebuild)?;
+        return Ok(HandleAnswer::RecomputeBusiness {
+            status,
+            drift: None,
+        });
+    }
+
+    let drift = aggregates_drift(&business, rebuild.aggregates);
+    business.set_aggregates(&drift.rebuilt);
+    business.decay_timestamp = rebuild.decay_timestamp;
+    update_business(&mut deps.storage, business)?;
+    remove_aggregate_rebuild(&mut deps.storage, &business_address)?;
+
+    Ok(HandleAnswer::RecomputeBusiness {
+        status: format!(
+            "Rebuilt the business' aggregates from {} reviews",
+            drift.rebuilt.reviews_count
+        ),
+        drift: Some(Box::new(drift)),
+    })
+}
+
+fn start_rebuild(business: &Business, page_size: u32) -> StdResult<AggregateRebuild> {
+    if page_size == 0 {
+        return Err(StdError::generic_err("Page size must be positive"));
+    }
+
+    Ok(AggregateRebuild {
+        next_page: 0,
+        page_size,
+        decay_timestamp: business.decay_timestamp,
+        aggregates: BusinessAggregates::default(),
+    })
+}
+
+// adds the next page of a business' reviews to a rebuild, and returns whether it was the last one
+fn continue_rebuild<S: ReadonlyStorage>(
+    store: &S,
+    config: &Config,
+    business_address: &HumanAddr,
+    rebuild: &mut AggregateRebuild,
+) -> StdResult<bool> {
+    let (reviews, total) = get_reviews_page(
+        store,
+        business_address,
+        rebuild.next_page,
+        rebuild.page_size,
+    )?;
+    aggregate_reviews(
+        config,
+        &reviews,
+        rebuild.decay_timestamp,
+        &mut rebuild.aggregates,
+    )?;
+
+    rebuild.next_page += 1;
+    Ok(rebuild.next_page.saturating_mul(rebuild.page_size) >= total)
+}
+
+// adds the contributions of reviews to aggregates, with the decayed sums as of the given time
+fn aggregate_reviews(
+    config: &Config,
+    reviews: &[Review],
+    decay_timestamp: u64,
+    aggregates: &mut BusinessAggregates,
+) -> StdResult<()> {
+    for review in reviews {
+        if !review.counted {
+            continue;
+        }
+
+        let applied_weight = review.applied_weight.u128();
+        aggregates.reviews_count += 1;
+        aggregates.total_weight =
+            Uint128::from(result_add(aggregates.total_weight.u128(), applied_weight)?);
+        aggregates.total_raw_weight = Uint128::from(result_add(
+            aggregates.total_raw_weight.u128(),
+            review.weight.u128(),
+        )?);
+        aggregates.rating_sum = Uint128::from(result_add(
+            aggregates.rating_sum.u128(),
+            result_mul(applied_weight, review.rating as u128)?,
+        )?);
+        apply_dimension_ratings(
+            &mut aggregates.dimensions,
+            &review.dimension_ratings,
+            applied_weight,
+            false,
+        )?;
+
+        if let Some(time_decay) = &config.time_decay {
+            let (weight, rating_sum) = decayed_contribution(
+                applied_weight,
+                review.rating as u128,
+                decay_timestamp.saturating_sub(review.last_update_timestamp),
+                time_decay.half_life_seconds,
+            )?;
+            aggregates.decayed_weight =
+                Uint128::from(result_add(aggregates.decayed_weight.u128(), weight)?);
+            aggregates.decayed_rating_sum = Uint128::from(result_add(
+                aggregates.decayed_rating_sum.u128(),
+                rating_sum,
+            )?);
+        }
+    }
+
+    Ok(())
+}
+
+fn aggregates_drift(business: &Business, rebuilt: BusinessAggregates) -> AggregatesDrift {
+    let stored = business.aggregates();
+    let consistent = stored.reviews_count == rebuilt.reviews_count
+        && stored.total_weight == rebuilt.total_weight
+        && stored.total_raw_weight == rebuilt.total_raw_weight
+        && stored.rating_sum == rebuilt.rating_sum
+        && stored.dimensions == rebuilt.dimensions;
+
+    AggregatesDrift {
+        stored,
+        rebuilt,
+        consistent,
+    }
+}
+
+/* A business is claimed by sending this from its own address, which proves the
+control of the address that receives its payments. The owner can be delegated to
+another address, and a later claim replaces it. */
+fn claim_business<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    address: HumanAddr,
+    owner: Option<HumanAddr>,
+) -> StdResult<(Vec<CosmosMsg>, HandleAnswer)> {
+    if env.message.sender != address {
+        return Err(StdError::generic_err(
+            "A business can only be claimed from its own address",
+        ));
+    }
+
+    let mut business = get_business_by_address(&deps.storage, &address)?.ok_or_else(|| {
+        StdError::generic_err(
+            "There is no business registered on that address. You can register it instead.",
+        )
+    })?;
+
+    let owner = owner.unwrap_or_else(|| env.message.sender.clone());
+    deps.api.canonical_address(&owner)?;
+    business.owner = Some(owner);
+    update_business(&mut deps.storage, business)?;
+
+    // a refundable deposit is returned to whoever paid it, and a fee is kept for the admin
+    let mut messages = vec![];
+    let mut status = "Successfully claimed business".to_string();
+    if let Some(deposit) = may_load_deposit(&deps.storage, &address) {
+        if deposit.refundable {
+            take_deposit(&mut deps.storage, &address)?;
+            let registrant = deposit.registrant.clone();
+            messages.push(deposit_payout(&env, deposit, registrant)?);
+            status.push_str(", its deposit was returned");
+        }
+    }
+
+    Ok((messages, HandleAnswer::ClaimBusiness { status }))
+}
+
+fn register_item<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    business_address: HumanAddr,
+    sku: String,
+    name: String,
+) -> StdResult<HandleAnswer> {
+    let business = get_business_by_address(&deps.storage, &business_address)?.ok_or_else(|| {
+        StdError::generic_err(
+            "There is no business registered on that address. You can register it instead.",
+        )
+    })?;
+    if business.owner.as_ref() != Some(&env.message.sender) {
+        return Err(StdError::generic_err(
+            "Only the owner of a claimed business can manage its items",
+        ));
+    }
+
+    for (field, value) in &[("Sku", &sku), ("Name", &name)] {
+        if value.is_empty() || value.chars().count() > MAX_NAME_LENGTH as usize {
+            return Err(StdError::generic_err(format!(
+                "{} length must be between 1 and {}",
+                field, MAX_NAME_LENGTH
+            )));
+        }
+    }
+
+    let item = match may_load_item(&deps.storage, &business_address, &sku) {
+        Some(existing) => Item { name, ..existing },
+        None => Item {
+            sku,
+            name,
+            reviews_count: 0,
+            total_weight: Uint128::zero(),
+            rating_sum: Uint128::zero(),
+        },
+    };
+    save_item(&mut deps.storage, &business_address, item)?;
+
+    Ok(HandleAnswer::RegisterItem {
+        status: "Successfully registered item".to_string(),
+    })
+}
+
+/* Associated addresses, e.g. other wallets of the owner, could pay the business
+and review it, so they can't review it anymore. Unlike what was first asked
+for, an association that the owner makes doesn't remove the existing reviews of
+the addresses from the aggregates, since an owner could associate unhappy
+customers to get rid of their reviews. Existing reviews are only left out when
+the admin or the arbiter associate an address, or when the address confirms the
+association itself. They are kept, but no longer count, like reviews whose
+rating was excluded by the arbiter. */
+fn associate_addresses<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    business_address: HumanAddr,
+    addresses: Vec<HumanAddr>,
+) -> StdResult<HandleAnswer> {
+    let config = load_config(&deps.storage)?;
+    let business = get_business_by_address(&deps.storage, &business_address)?
+        .ok_or_else(|| StdError::generic_err("No business is registered on that address"))?;
+    let moderated =
+        env.message.sender == config.admin || config.arbiter.as_ref() == Some(&env.message.sender);
+    if business.owner.as_ref() != Some(&env.message.sender) && !moderated {
+        return Err(StdError::unauthorized());
+    }
+
+    let mut excluded_reviews = 0;
+    for address in &addresses {
+        add_associated_address(&mut deps.storage, &business_address, address)?;
+        if moderated
+            && set_review_associated(
+                &mut deps.storage,
+                &config,
+                env.block.time,
+                &business_address,
+                address,
+                true,
+            )?
+        {
+            excluded_reviews += 1;
+        }
+    }
+
+    Ok(HandleAnswer::AssociateAddresses {
+        status: format!(
+            "Associated {} addresses with the business, {} of their reviews no longer count",
+            addresses.len(),
+            excluded_reviews
+        ),
+    })
+}
+
+fn confirm_association<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    business_address: HumanAddr,
+) -> StdResult<HandleAnswer> {
+    let config = load_config(&deps.storage)?;
+    if !is_associated_address(&deps.storage, &business_address, &env.message.sender) {
+        return Err(StdError::generic_err(
+            "The address is not associated with that business",
+        ));
+    }
+
+    let excluded = set_review_associated(
+        &mut deps.storage,
+        &config,
+        env.block.time,
+        &business_address,
+        &env.message.sender,
+        true,
+    )?;
+
+    Ok(HandleAnswer::ConfirmAssociation {
+        status: if excluded {
+            "Confirmed the association, the review no longer counts".to_string()
+        } else {
+            "Confirmed the association".to_string()
+        },
+    })
+}
+
+fn dissociate_addresses<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    business_address: HumanAddr,
+    addresses: Vec<HumanAddr>,
+) -> StdResult<HandleAnswer> {
+    let config = load_config(&deps.storage)?;
+    let business = get_business_by_address(&deps.storage, &business_address)?
+        .ok_or_else(|| StdError::generic_err("No business is registered on that address"))?;
+    let moderated =
+        env.message.sender == config.admin || config.arbiter.as_ref() == Some(&env.message.sender);
+    if business.owner.as_ref() != Some(&env.message.sender) && !moderated {
+        return Err(StdError::unauthorized());
+    }
+
+    let mut dissociated_addresses = 0;
+    let mut restored_reviews = 0;
+    for address in &addresses {
+        if !is_associated_address(&deps.storage, &business_address, address) {
+            continue;
+        }
+        let excluded = matches!(
+            may_load_review(&deps.storage, &business_address, address),
+            Some(review) if review.associated
+        );
+        if excluded && !moderated {
+            return Err(StdError::generic_err(format!(
+                "The review of {} no longer counts, only the admin or the arbiter can dissociate it",
+                address
+            )));
+        }
+
+        remove_associated_address(&mut deps.storage, &business_address, address)?;
+        dissociated_addresses += 1;
+        if set_review_associated(
+            &mut deps.storage,
+            &config,
+            env.block.time,
+            &business_address,
+            address,
+            false,
+        )? {
+            restored_reviews += 1;
+        }
+    }
+
+    Ok(HandleAnswer::DissociateAddresses {
+        status: format!(
+            "Dissociated {} addresses from the business, {} of their reviews count again",
+            dissociated_addresses, restored_reviews
+        ),
+    })
+}
+
+/// Leaves the review of the address out of the business' aggregates, or counts it again, and
+/// returns whether it changed
+fn set_review_associated<S: Storage>(
+    store: &mut S,
+    config: &Config,
+    now: u64,
+    business_address: &HumanAddr,
+    address: &HumanAddr,
+    associated: bool,
+) -> StdResult<bool> {
+    let previous_review = match may_load_review(store, business_address, address) {
+        Some(review) if review.associated != associated => review,
+        _ => return Ok(false),
+    };
+    let business = get_business_by_address(store, business_address)?
+        .ok_or_else(|| StdError::generic_err("No business is registered on that address"))?;
+
+    let mut review = previous_review.clone();
+    review.associated = associated;
+    save_review_and_aggregates(
+        store,
+        config,
+        now,
+        &business,
+        Some(&previous_review),
+        review,
+    )?;
+
+    Ok(true)
+}
+
+fn mint_vouchers<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    business_address: HumanAddr,
+    vouchers: Vec<NewVoucher>,
+) -> StdResult<HandleAnswer> {
+    let config = load_config(&deps.storage)?;
+    let mut business = get_business_by_address(&deps.storage, &business_address)?
+        .ok_or_else(|| StdError::generic_err("No business is registered on that address"))?;
+    if business.owner.as_ref() != Some(&env.message.sender) {
+        return Err(StdError::generic_err(
+            "Only the owner of the business can mint vouchers",
+        ));
+    }
+
+    let mut vouchers_weight = business.vouchers_weight.u128();
+    for new_voucher in &vouchers {
+        if new_voucher.secret_hash.len() != 32 {
+            return Err(StdError::generic_err(
+                "Voucher secrets must be hashed with sha256",
+            ));
+        }
+        if new_voucher.weight.is_zero() {
+            return Err(StdError::generic_err("Voucher weights must be positive"));
+        }
+        if may_load_voucher(
+            &deps.storage,
+            &business_address,
+            new_voucher.secret_hash.as_slice(),
+        )
+        .is_some()
+        {
+            return Err(StdError::generic_err(
+                "A voucher with that secret was already minted",
+            ));
+        }
+
+        vouchers_weight = result_add(vouchers_weight, new_voucher.weight.u128())?;
+        if vouchers_weight > config.voucher_weight_budget.u128() {
+            return Err(StdError::generic_err(format!(
+                "The vouchers exceed the voucher weight budget of {}, of which {} was already minted",
+                config.voucher_weight_budget, business.vouchers_weight
+            )));
+        }
+
+        save_voucher(
+            &mut deps.storage,
+            &business_address,
+            new_voucher.secret_hash.as_slice(),
+            Voucher {
+                weight: new_voucher.weight,
+                expires_at: new_voucher.expires_at,
+                minted_timestamp: env.block.time,
+                minted_height: env.block.height,
+                redeemed: false,
+            },
+        )?;
+    }
+
+    business.vouchers_weight = Uint128::from(vouchers_weight);
+    update_business(&mut deps.storage, business)?;
+
+    Ok(HandleAnswer::MintVouchers {
+        status: format!(
+            "Minted {} vouchers, {} of the voucher weight budget is left",
+            vouchers.len(),
+            // the budget can have been lowered below the weight that was already minted
+            config
+                .voucher_weight_budget
+                .u128()
+                .saturating_sub(vouchers_weight)
+        ),
+    })
+}
+
+fn set_attestation_key<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    business_address: HumanAddr,
+    pubkey: Option<Binary>,
+) -> StdResult<HandleAnswer> {
+    let mut business = get_business_by_address(&deps.storage, &business_address)?
+        .ok_or_else(|| StdError::generic_err("No business is registered on that address"))?;
+    if business.owner.as_ref() != Some(&env.message.sender) {
+        return Err(StdError::generic_err(
+            "Only the owner of the business can set its attestation key",
+        ));
+    }
+    if let Some(pubkey) = &pubkey {
+        VerifyingKey::from_sec1_bytes(pubkey.as_slice()).map_err(|_| {
+            StdError::generic_err("The attestation key must be a SEC1 encoded secp256k1 public key")
+        })?;
+    }
+
+    let status = if pubkey.is_some() {
+        "The attestation key was set"
+    } else {
+        "The business no longer accepts purchase attestations"
+    };
+    business.attestation_key = pubkey;
+    update_business(&mut deps.storage, business)?;
+
+    Ok(HandleAnswer::SetAttestationKey {
+        status: status.to_string(),
+    })
+}
+
+fn set_business_status<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    address: HumanAddr,
+    status: BusinessStatus,
+) -> StdResult<HandleAnswer> {
+    let mut business = get_business_by_address(&deps.storage, &address)?
+        .ok_or_else(|| StdError::generic_err("No business is registered on that address"))?;
+
+    let config = load_config(&deps.storage)?;
+    if env.message.sender != config.admin && business.owner.as_ref() != Some(&env.message.sender) {
+        return Err(StdError::unauthorized());
+    }
+
+    if business.status == status {
+        return Err(StdError::generic_err(match status {
+            BusinessStatus::Active => "The business is already active",
+            BusinessStatus::Closed => "The business is already closed",
+        }));
+    }
+    business.status = status;
+    update_business(&mut deps.storage, business)?;
+
+    Ok(match status {
+        BusinessStatus::Active => HandleAnswer::ReactivateBusiness {
+            status: "Successfully reactivated business".to_string(),
+        },
+        BusinessStatus::Closed => HandleAnswer::DeactivateBusiness {
+            status: "Successfully deactivated business".to_string(),
+        },
+    })
+}
+
+fn set_moderation<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    moderators: Option<Vec<HumanAddr>>,
+    count_hidden_reviews: Option<bool>,
+    flag_threshold: Option<Uint128>,
+) -> StdResult<HandleAnswer> {
+    let mut config = load_config(&deps.storage)?;
+    if env.message.sender != config.admin {
+        return Err(StdError::unauthorized());
+    }
+
+    if let Some(moderators) = moderators {
+        config.moderators = moderators;
+    }
+    if let Some(count_hidden_reviews) = count_hidden_reviews {
+        config.count_hidden_reviews = count_hidden_reviews;
+    }
+    if let Some(flag_threshold) = flag_threshold {
+        config.flag_threshold = flag_threshold;
+    }
+    save_config(&mut deps.storage, &config)?;
+
+    Ok(HandleAnswer::SetModeration {
+        status: "Moderation was updated".to_string(),
+    })
+}
+
+/* Hidden reviews are kept as they are, so that they can be restored on appeal.
+Whether they still count is up to the config, and the aggregates are updated
+just like when the review is edited. Either way the moderator looked at the
+review, so it leaves the moderators' queue. */
+fn set_review_hidden<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    business_address: HumanAddr,
+    reviewer: HumanAddr,
+    reason: Option<ModerationReason>,
+) -> StdResult<HandleAnswer> {
+    let config = load_config(&deps.storage)?;
+    if !config.is_moderator(&env.message.sender) {
+        return Err(StdError::unauthorized());
+    }
+
+    let business = get_business_by_address(&deps.storage, &business_address)?
+        .ok_or_else(|| StdError::generic_err("No business is registered on that address"))?;
+    let previous_review = may_load_review(&deps.storage, &business_address, &reviewer)
+        .ok_or_else(|| StdError::generic_err("The reviewer didn't review this business"))?;
+    let hidden_by_moderator = previous_review
+        .hidden
+        .as_ref()
+        .map_or(false, |hidden| hidden.moderator.is_some());
+    match reason {
+        Some(..) if hidden_by_moderator => {
+            return Err(StdError::generic_err("The review is already hidden"))
+        }
+        None if previous_review.hidden.is_none() => {
+            return Err(StdError::generic_err("The review isn't hidden"))
+        }
+        _ => {}
+    }
+
+    remove_flagged_review(&mut deps.storage, &business_address, &reviewer)?;
+    let mut review = previous_review.clone();
+    review.hidden = reason.map(|reason| Hidden {
+        moderator: Some(env.message.sender.clone()),
+        reason,
+        timestamp: env.block.time,
+    });
+    save_review_and_aggregates(
+        &mut deps.storage,
+        &config,
+        env.block.time,
+        &business,
+        Some(&previous_review),
+        review,
+    )?;
+
+    Ok(match reason {
+        Some(..) => HandleAnswer::HideReview {
+            status: "Successfully hid review".to_string(),
+        },
+        None => HandleAnswer::UnhideReview {
+            status: "Successfully unhid review".to_string(),
+        },
+    })
+}
+
+fn set_business_hidden<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    address: HumanAddr,
+    reason: Option<ModerationReason>,
+) -> StdResult<HandleAnswer> {
+    let config = load_config(&deps.storage)?;
+    if !config.is_moderator(&env.message.sender) {
+        return Err(StdError::unauthorized());
+    }
+
+    let mut business = get_business_by_address(&deps.storage, &address)?
+        .ok_or_else(|| StdError::generic_err("No business is registered on that address"))?;
+    if business.hidden.is_some() == reason.is_some() {
+        return Err(StdError::generic_err(if reason.is_some() {
+            "The business is already hidden"
+        } else {
+            "The business isn't hidden"
+        }));
+    }
+
+    business.hidden = reason.map(|reason| Hidden {
+        moderator: Some(env.message.sender),
+        reason,
+        timestamp: env.block.time,
+    });
+    update_business(&mut deps.storage, business)?;
+
+    Ok(match reason {
+        Some(..) => HandleAnswer::HideBusiness {
+            status: "Successfully hid business".to_string(),
+        },
+        None => HandleAnswer::UnhideBusiness {
+            status: "Successfully unhid business".to_string(),
+        },
+    })
+}
+
+/* Only customers who reviewed the business can flag its reviews, and their flags
+weigh as much as their own verified spend there. Once the flags since a moderator
+last looked at the review reach the threshold, it is hidden for the reason with
+the most weight, until a moderator looks at it. */
+fn flag_review<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    business_address: HumanAddr,
+    reviewer: HumanAddr,
+    reason: ModerationReason,
+) -> StdResult<HandleAnswer> {
+    if env.message.sender == reviewer {
+        return Err(StdError::generic_err("You can't flag your own review"));
+    }
+
+    let config = load_config(&deps.storage)?;
+    let business = get_business_by_address(&deps.storage, &business_address)?
+        .ok_or_else(|| StdError::generic_err("No business is registered on that address"))?;
+    let review = may_load_review(&deps.storage, &business_address, &reviewer)
+        .ok_or_else(|| StdError::generic_err("The reviewer didn't review this business"))?;
+    if review
+        .hidden
+        .as_ref()
+        .map_or(false, |hidden| hidden.moderator.is_some())
+    {
+        return Err(StdError::generic_err(
+            "The review was already hidden by a moderator",
+        ));
+    }
+
+    let weight = may_load_review(&deps.storage, &business_address, &env.message.sender)
+        .filter(|flagger_review| !flagger_review.associated)
+        .map(|flagger_review| flagger_review.weight)
+        .unwrap_or_default();
+    if weight.is_zero() {
+        return Err(StdError::generic_err(
+            "Only customers who reviewed the business can flag its reviews",
+        ));
+    }
+
+    create_flag(
+        &mut deps.storage,
+        &business_address,
+        &reviewer,
+        &env.message.sender,
+        Flag {
+            reason,
+            weight,
+            timestamp: env.block.time,
+        },
+    )?;
+
+    let mut flagged = may_load_flagged_review(&deps.storage, &business_address, &reviewer)
+        .unwrap_or(FlaggedReview {
+            business_address: business_address.clone(),
+            reviewer: reviewer.clone(),
+            flags_count: 0,
+            flagged_weight: Uint128::zero(),
+            reasons: vec![],
+        });
+    flagged.flags_count += 1;
+    flagged.flagged_weight =
+        Uint128::from(result_add(flagged.flagged_weight.u128(), weight.u128())?);
+    match flagged.reasons.iter_mut().find(|r| r.reason == reason) {
+        Some(reason_weight) => {
+            reason_weight.weight =
+                Uint128::from(result_add(reason_weight.weight.u128(), weight.u128())?)
+        }
+        None => flagged.reasons.push(FlagWeight { reason, weight }),
+    }
+
+    let mut status = "Successfully flagged review".to_string();
+    if review.hidden.is_none()
+        && !config.flag_threshold.is_zero()
+        && flagged.flagged_weight >= config.flag_threshold
+    {
+        let heaviest_reason = flagged
+            .reasons
+            .iter()
+            .max_by_key(|r| r.weight)
+            .map_or(reason, |r| r.reason);
+        let mut hidden_review = review.clone();
+        hidden_review.hidden = Some(Hidden {
+            moderator: None,
+            reason: heaviest_reason,
+            timestamp: env.block.time,
+        });
+        save_review_and_aggregates(
+            &mut deps.storage,
+            &config,
+            env.block.time,
+            &business,
+            Some(&review),
+            hidden_review,
+        )?;
+        status =
+            "Successfully flagged review, it is hidden until a moderator looks at it".to_string();
+    }
+    save_flagged_review(&mut deps.storage, flagged)?;
+
+    Ok(HandleAnswer::FlagReview { status })
+}
+
+fn dismiss_flags<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    business_address: HumanAddr,
+    reviewer: HumanAddr,
+) -> StdResult<HandleAnswer> {
+    let config = load_config(&deps.storage)?;
+    if !config.is_moderator(&env.message.sender) {
+        return Err(StdError::unauthorized());
+    }
+
+    if !remove_flagged_review(&mut deps.storage, &business_address, &reviewer)? {
+        return Err(StdError::generic_err(
+            "The review isn't in the moderators' queue",
+        ));
+    }
+
+    let business = get_business_by_address(&deps.storage, &business_address)?
+        .ok_or_else(|| StdError::generic_err("No business is registered on that address"))?;
+    let review = may_load_review(&deps.storage, &business_address, &reviewer)
+        .ok_or_else(|| StdError::generic_err("The reviewer didn't review this business"))?;
+    if review
+        .hidden
+        .as_ref()
+        .map_or(false, |hidden| hidden.moderator.is_none())
+    {
+        let mut shown_review = review.clone();
+        shown_review.hidden = None;
+        save_review_and_aggregates(
+            &mut deps.storage,
+            &config,
+            env.block.time,
+            &business,
+            Some(&review),
+            shown_review,
+        )?;
+    }
+
+    Ok(HandleAnswer::DismissFlags {
+        status: "Successfully dismissed the flags of the review".to_string(),
+    })
+}
+
+fn set_arbitration<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    arbiter: Option<HumanAddr>,
+    dispute_bond: Option<Uint128>,
+) -> StdResult<HandleAnswer> {
+    let mut config = load_config(&deps.storage)?;
+    if env.message.sender != config.admin {
+        return Err(StdError::unauthorized());
+    }
+
+    config.arbiter = arbiter;
+    if let Some(dispute_bond) = dispute_bond {
+        config.dispute_bond = dispute_bond;
+    }
+    save_config(&mut deps.storage, &config)?;
+
+    Ok(HandleAnswer::SetArbitration {
+        status: "Arbitration was updated".to_string(),
+    })
+}
+
+fn set_edit_limits<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    min_edit_interval_seconds: Option<u64>,
+    review_lock_seconds: Option<u64>,
+) -> StdResult<HandleAnswer> {
+    let mut config = load_config(&deps.storage)?;
+    if env.message.sender != config.admin {
+        return Err(StdError::unauthorized());
+    }
+
+    if let Some(min_edit_interval_seconds) = min_edit_interval_seconds {
+        config.min_edit_interval_seconds = min_edit_interval_seconds;
+    }
+    if let Some(review_lock_seconds) = review_lock_seconds {
+        config.review_lock_seconds = review_lock_seconds;
+    }
+    save_config(&mut deps.storage, &config)?;
+
+    Ok(HandleAnswer::SetEditLimits {
+        status: "Edit limits were updated".to_string(),
+    })
+}
+
+fn validate_dispute_text(text: &str) -> StdResult<()> {
+    if text.is_empty() || text.chars().count() > MAX_DISPUTE_TEXT_LENGTH {
+        return Err(StdError::generic_err(format!(
+            "Disputes and responses must be between 1 and {} characters long",
+            MAX_DISPUTE_TEXT_LENGTH
+        )));
+    }
+    Ok(())
+}
+
+/* The owner of a business can dispute a review by putting up the bond, which is
+held by the contract until the arbiter rules. A review can only be disputed again
+once the previous dispute on it was resolved, and not after a ruling against it. */
+fn open_dispute<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    business_address: HumanAddr,
+    reviewer: HumanAddr,
+    reason: String,
+) -> StdResult<HandleAnswer> {
+    let config = load_config(&deps.storage)?;
+    if config.arbiter.is_none() {
+        return Err(StdError::generic_err(
+            "Disputes are disabled until the admin sets an arbiter",
+        ));
+    }
+    validate_dispute_text(&reason)?;
+
+    let business = get_business_by_address(&deps.storage, &business_address)?
+        .ok_or_else(|| StdError::generic_err("No business is registered on that address"))?;
+    if business.owner.as_ref() != Some(&env.message.sender) {
+        return Err(StdError::generic_err(
+            "Only the owner of the business can dispute its reviews",
+        ));
+    }
+    let review = may_load_review(&deps.storage, &business_address, &reviewer)
+        .ok_or_else(|| StdError::generic_err("The reviewer didn't review this business"))?;
+    if review.dispute_outcome.is_some() {
+        return Err(StdError::generic_err(
+            "An arbiter already ruled against this review",
+        ));
+    }
+    if let Some(Dispute {
+        state: DisputeState::Open,
+        ..
+    }) = may_load_dispute(&deps.storage, &business_address, &reviewer)
+    {
+        return Err(StdError::generic_err("The review is already disputed"));
+    }
+
+    let sent_funds = env.message.sent_funds;
+    let bond_is_sent = if config.dispute_bond.is_zero() {
+        sent_funds.is_empty()
+    } else {
+        sent_funds.len() == 1
+            && sent_funds[0].denom == NATIVE_DENOM
+            && sent_funds[0].amount == config.dispute_bond
+    };
+    if !bond_is_sent {
+        return Err(StdError::generic_err(format!(
+            "Opening a dispute requires a bond of exactly {} {}",
+            config.dispute_bond, NATIVE_DENOM
+        )));
+    }
+
+    save_dispute(
+        &mut deps.storage,
+        Dispute {
+            business_address,
+            reviewer,
+            owner: env.message.sender,
+            bond: config.dispute_bond,
+            reason,
+            response: None,
+            state: DisputeState::Open,
+            opened_timestamp: env.block.time,
+        },
+    )?;
+
+    Ok(HandleAnswer::OpenDispute {
+        status: "Successfully opened dispute".to_string(),
+    })
+}
+
+fn respond_to_dispute<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    business_address: HumanAddr,
+    response: String,
+) -> StdResult<HandleAnswer> {
+    validate_dispute_text(&response)?;
+
+    let mut dispute = may_load_dispute(&deps.storage, &business_address, &env.message.sender)
+        .filter(|dispute| dispute.state == DisputeState::Open)
+        .ok_or_else(|| StdError::generic_err("Your review has no open dispute"))?;
+    dispute.response = Some(response);
+    save_dispute(&mut deps.storage, dispute)?;
+
+    Ok(HandleAnswer::RespondToDispute {
+        status: "Successfully responded to dispute".to_string(),
+    })
+}
+
+/* The ruling is kept in the review and applied on the aggregates like any other
+change of the review. A slashed bond goes to the admin. */
+fn resolve_dispute<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    business_address: HumanAddr,
+    reviewer: HumanAddr,
+    outcome: DisputeOutcome,
+) -> StdResult<(Vec<CosmosMsg>, HandleAnswer)> {
+    let config = load_config(&deps.storage)?;
+    if config.arbiter.as_ref() != Some(&env.message.sender) {
+        return Err(StdError::unauthorized());
+    }
+
+    let mut dispute = may_load_dispute(&deps.storage, &business_address, &reviewer)
+        .filter(|dispute| dispute.state == DisputeState::Open)
+        .ok_or_else(|| StdError::generic_err("The review has no open dispute"))?;
+
+    if outcome != DisputeOutcome::Upheld {
+        let business = get_business_by_address(&deps.storage, &business_address)?
+            .ok_or_else(|| StdError::generic_err("No business is registered on that address"))?;
+        let previous_review = may_load_review(&deps.storage, &business_address, &reviewer)
+            .ok_or_else(|| StdError::generic_err("The reviewer didn't review this business"))?;
+        let mut review = previous_review.clone();
+        review.dispute_outcome = Some(outcome);
+        save_review_and_aggregates(
+            &mut deps.storage,
+            &config,
+            env.block.time,
+            &business,
+            Some(&previous_review),
+            review,
+        )?;
+    }
+
+    let bond_recipient = match outcome {
+        DisputeOutcome::Upheld => config.admin,
+        DisputeOutcome::HideContent | DisputeOutcome::ExcludeRating => dispute.owner.clone(),
+    };
+    let mut messages = vec![];
+    if !dispute.bond.is_zero() {
+        messages.push(CosmosMsg::Bank(BankMsg::Send {
+            from_address: env.contract.address,
+            to_address: bond_recipient,
+            amount: vec![Coin {
+                denom: NATIVE_DENOM.to_string(),
+                amount: dispute.bond,
+            }],
+        }));
+    }
+
+    dispute.state = DisputeState::Resolved {
+        outcome,
+        timestamp: env.block.time,
+    };
+    save_dispute(&mut deps.storage, dispute)?;
+
+    Ok((
+        messages,
+        HandleAnswer::ResolveDispute {
+            status: "Successfully resolved dispute".to_string(),
+        },
+    ))
+}
+
+fn register_brand<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    name: String,
+    owner: HumanAddr,
+    rating_scale: Option<RatingScale>,
+) -> StdResult<HandleAnswer> {
+    // anyone could otherwise take the name of a chain before the chain does
+    let config = load_config(&deps.storage)?;
+    if !config.is_registrar(&env.message.sender) {
+        return Err(StdError::generic_err(
+            "Only the registrars can register brands",
+        ));
+    }
+
+    if name.is_empty() || name.chars().count() > MAX_NAME_LENGTH as usize {
+        return Err(StdError::generic_err(format!(
+            "Name length must be between 1 and {}",
+            MAX_NAME_LENGTH
+        )));
+    }
+
+    create_brand(
+        &mut deps.storage,
+        Brand {
+            name,
+            owner,
+            rating_scale: rating_scale.unwrap_or(config.rating_scale),
+            reviews_count: 0,
+            total_weight: Uint128::zero(),
+            rating_sum: Uint128::zero(),
+        },
+    )?;
+
+    Ok(HandleAnswer::RegisterBrand {
+        status: "Successfully registered brand".to_string(),
+    })
+}
+
+/* A branch keeps its own reviews and aggregates, since receipts are verified against its own
+address. Its aggregates are also added to the brand's, and follow it from then on. */
+fn add_branch<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    brand: String,
+    business_address: HumanAddr,
+) -> StdResult<HandleAnswer> {
+    let existing_brand = may_load_brand(&deps.storage, &brand)
+        .ok_or_else(|| StdError::generic_err(format!("There is no brand named {}", brand)))?;
+    let mut business = get_business_by_address(&deps.storage, &business_address)?
+        .ok_or_else(|| StdError::generic_err("No business is registered on that address"))?;
+
+    if existing_brand.owner != env.message.sender
+        || business.owner.as_ref() != Some(&env.message.sender)
+    {
+        return Err(StdError::generic_err(
+            "Only the owner of both the brand and the claimed business can add it as a branch",
+        ));
+    }
+    if let Some(current_brand) = &business.brand {
+        return Err(StdError::generic_err(format!(
+            "The business is already a branch of {}",
+            current_brand
+        )));
+    }
+
+    business.brand = Some(brand);
+    update_business(&mut deps.storage, business)?;
+
+    Ok(HandleAnswer::AddBranch {
+        status: "Successfully added branch".to_string(),
+    })
+}
+
+fn remove_branch<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    business_address: HumanAddr,
+) -> StdResult<HandleAnswer> {
+    let mut business = get_business_by_address(&deps.storage, &business_address)?
+        .ok_or_else(|| StdError::generic_err("No business is registered on that address"))?;
+    let brand = business
+        .brand
+        .as_ref()
+        .and_then(|brand| may_load_brand(&deps.storage, brand))
+        .ok_or_else(|| StdError::generic_err("The business is not a branch of any brand"))?;
+
+    if brand.owner != env.message.sender && business.owner.as_ref() != Some(&env.message.sender) {
+        return Err(StdError::generic_err(
+            "Only the owner of the brand or of the business can remove a branch",
+        ));
+    }
+
+    business.brand = None;
+    update_business(&mut deps.storage, business)?;
+
+    Ok(HandleAnswer::RemoveBranch {
+        status: "Successfully removed branch".to_string(),
+    })
+}
+
+/* Categories are managed by the admin. Since ratings are kept normalized, the
+scale of a category can be changed even after its businesses were reviewed. */
+fn set_category<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    name: String,
+    rating_scale: Option<RatingScale>,
+    dimensions: Vec<String>,
+) -> StdResult<HandleAnswer> {
+    let config = load_config(&deps.storage)?;
+    if env.message.sender != config.admin {
+        return Err(StdError::unauthorized());
+    }
+
+    if name.is_empty() || name.chars().count() > MAX_NAME_LENGTH as usize {
+        return Err(StdError::generic_err(format!(
+            "Name length must be between 1 and {}",
+            MAX_NAME_LENGTH
+        )));
+    }
+
+    if dimensions.len() > MAX_DIMENSIONS {
+        return Err(StdError::generic_err(format!(
+            "A category can't have more than {} dimensions",
+            MAX_DIMENSIONS
+        )));
+    }
+    for (i, dimension) in dimensions.iter().enumerate() {
+        if dimension.is_empty() || dimension.chars().count() > MAX_NAME_LENGTH as usize {
+            return Err(StdError::generic_err(format!(
+                "Dimension names must be between 1 and {} characters long",
+                MAX_NAME_LENGTH
+            )));
+        }
+        if dimensions[..i].contains(dimension) {
+            return Err(StdError::generic_err(format!(
+                "Dimension {} is listed twice",
+                dimension
+            )));
+        }
+    }
+
+    save_category(
+        &mut deps.storage,
+        Category {
+            name,
+            rating_scale,
+            dimensions,
+        },
+    )?;
+
+    Ok(HandleAnswer::SetCategory {
+        status: "Successfully set category".to_string(),
+    })
+}
+
+fn validate_registration_deposit(
+    deposit: Option<RegistrationDeposit>,
+) -> StdResult<Option<RegistrationDeposit>> {
+    if deposit
+        .as_ref()
+        .map_or(false, |deposit| deposit.amount.is_zero())
+    {
+        return Err(StdError::generic_err(
+            "The registration deposit must be positive",
+        ));
+    }
+    Ok(deposit)
+}
+
+// a SNIP-20 only calls Receive on contracts that registered with it
+fn register_deposit_token(
+    env: &Env,
+    deposit: &Option<RegistrationDeposit>,
+) -> StdResult<Vec<CosmosMsg>> {
+    match deposit.as_ref().and_then(|deposit| deposit.token.as_ref()) {
+        Some(token) => Ok(vec![register_receive_msg(
+            env.contract_code_hash.clone(),
+            None,
+            BLOCK_SIZE,
+            token.code_hash.clone(),
+            token.address.clone(),
+        )?]),
+        None => Ok(vec![]),
+    }
+}
+
+fn describe_registration_deposit(deposit: &RegistrationDeposit) -> String {
+    match &deposit.token {
+        Some(token) => format!(
+            "Registering a business requires a deposit of exactly {} of the token {}, sent through its Send",
+            deposit.amount, token.address
+        ),
+        None => format!(
+            "Registering a business requires a deposit of exactly {} {}",
+            deposit.amount, NATIVE_DENOM
+        ),
+    }
+}
+
+/* The funds that are sent with RegisterBusiness must be exactly the deposit when
+it is in uscrt. Otherwise no funds are taken, so they would be kept by the
+contract for nothing. */
+fn native_registration_deposit(config: &Config, env: &Env) -> StdResult<Option<HeldDeposit>> {
+    let sent_funds = &env.message.sent_funds;
+    let terms = match &config.registration_deposit {
+        Some(terms) => terms,
+        None if sent_funds.is_empty() => return Ok(None),
+        None => {
+            return Err(StdError::generic_err(
+                "Registering a business doesn't require any funds",
+            ))
+        }
+    };
+
+    if terms.token.is_some()
+        || sent_funds.len() != 1
+        || sent_funds[0].denom != NATIVE_DENOM
+        || sent_funds[0].amount != terms.amount
+    {
+        return Err(StdError::generic_err(describe_registration_deposit(terms)));
+    }
+
+    Ok(Some(HeldDeposit {
+        registrant: env.message.sender.clone(),
+        amount: terms.amount,
+        token: None,
+        refundable: terms.refundable,
+    }))
+}
+
+/* Tokens can only be sent to the contract as the registration deposit, when it is
+in the token that calls Receive. The deposit is returned to the owner of the
+tokens, rather than to whoever sent them on their behalf. */
+fn receive<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    from: HumanAddr,
+    amount: Uint128,
+    msg: Option<Binary>,
+) -> StdResult<HandleAnswer> {
+    let config = load_config(&deps.storage)?;
+    let terms = config
+        .registration_deposit
+        .filter(|terms| {
+            terms
+                .token
+                .as_ref()
+                .map_or(false, |token| token.address == env.message.sender)
+        })
+        .ok_or_else(|| StdError::generic_err("The contract doesn't accept this token"))?;
+    if amount != terms.amount {
+        return Err(StdError::generic_err(describe_registration_deposit(&terms)));
+    }
+
+    let msg = msg.ok_or_else(|| StdError::generic_err("Tokens must be sent with a message"))?;
+    match from_binary(&msg)? {
+        ReceiveMsg::RegisterBusiness {
+            name,
+            address,
+            description,
+            category,
+        } => register_business(
+            deps,
+            from.clone(),
+            name,
+            address,
+            description,
+            category,
+            Some(HeldDeposit {
+                registrant: from,
+                amount,
+                token: terms.token,
+                refundable: terms.refundable,
+            }),
+        ),
+    }
+}
+
+fn deposit_payout(env: &Env, deposit: HeldDeposit, recipient: HumanAddr) -> StdResult<CosmosMsg> {
+    match deposit.token {
+        Some(token) => transfer_msg(
+            recipient,
+            deposit.amount,
+            None,
+            None,
+            BLOCK_SIZE,
+            token.code_hash,
+            token.address,
+        ),
+        None => Ok(CosmosMsg::Bank(BankMsg::Send {
+            from_address: env.contract.address.clone(),
+            to_address: recipient,
+            amount: vec![Coin {
+                denom: NATIVE_DENOM.to_string(),
+                amount: deposit.amount,
+            }],
+        })),
+    }
+}
+
+fn set_registration_deposit<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    deposit: Option<RegistrationDeposit>,
+) -> StdResult<(Vec<CosmosMsg>, HandleAnswer)> {
+    let mut config = load_config(&deps.storage)?;
+    if env.message.sender != config.admin {
+        return Err(StdError::unauthorized());
+    }
+
+    config.registration_deposit = validate_registration_deposit(deposit)?;
+    let messages = register_deposit_token(&env, &config.registration_deposit)?;
+    save_config(&mut deps.storage, &config)?;
+
+    Ok((
+        messages,
+        HandleAnswer::SetRegistrationDeposit {
+            status: "The registration deposit was updated".to_string(),
+        },
+    ))
+}
+
+fn sweep_deposits<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    business_addresses: Vec<HumanAddr>,
+) -> StdResult<(Vec<CosmosMsg>, HandleAnswer)> {
+    let config = load_config(&deps.storage)?;
+    if env.message.sender != config.admin {
+        return Err(StdError::unauthorized());
+    }
+
+    let mut messages = vec![];
+    for address in &business_addresses {
+        if let Some(deposit) = take_deposit(&mut deps.storage, address)? {
+            messages.push(deposit_payout(&env, deposit, config.admin.clone())?);
+        }
+    }
+
+    let status = format!("Swept {} deposits", messages.len());
+    Ok((messages, HandleAnswer::SweepDeposits { status }))
+}
+
+fn set_registration_mode<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    mode: RegistrationMode,
+) -> StdResult<HandleAnswer> {
+    let mut config = load_config(&deps.storage)?;
+    if env.message.sender != config.admin {
+        return Err(StdError::unauthorized());
+    }
+
+    config.registration_mode = mode;
+    save_config(&mut deps.storage, &config)?;
+
+    Ok(HandleAnswer::SetRegistrationMode {
+        status: "The registration mode was updated".to_string(),
+    })
+}
+
+fn set_voucher_budget<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    voucher_weight_budget: Uint128,
+    max_attested_amount: Option<Uint128>,
+) -> StdResult<HandleAnswer> {
+    let mut config = load_config(&deps.storage)?;
+    if env.message.sender != config.admin {
+        return Err(StdError::unauthorized());
+    }
+
+    config.voucher_weight_budget = voucher_weight_budget;
+    if let Some(max_attested_amount) = max_attested_amount {
+        config.max_attested_amount = max_attested_amount;
+    }
+    save_config(&mut deps.storage, &config)?;
+
+    Ok(HandleAnswer::SetVoucherBudget {
+        status: "The voucher weight budget was updated".to_string(),
+    })
+}
+
+fn set_registrars<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    registrars: Vec<HumanAddr>,
+    remove: bool,
+) -> StdResult<HandleAnswer> {
+    let mut config = load_config(&deps.storage)?;
+    if env.message.sender != config.admin {
+        return Err(StdError::unauthorized());
+    }
+
+    if remove {
+        config
+            .registrars
+            .retain(|registrar| !registrars.contains(registrar));
+    } else {
+        for registrar in registrars {
+            if !config.registrars.contains(&registrar) {
+                config.registrars.push(registrar);
+            }
+        }
+    }
+    save_config(&mut deps.storage, &config)?;
+
+    Ok(if remove {
+        HandleAnswer::RemoveRegistrars {
+            status: "Successfully removed registrars".to_string(),
+        }
+    } else {
+        HandleAnswer::AddRegistrars {
+            status: "Successfully added registrars".to_string(),
+        }
+    })
+}
+
+fn register_business<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    registrant: HumanAddr,
+    name: String,
+    address: HumanAddr,
+    description: String,
+    category: Option<String>,
+    deposit: Option<HeldDeposit>,
+) -> StdResult<HandleAnswer> {
+    let config = load_config(&deps.storage)?;
+    match config.registration_mode {
+        RegistrationMode::OwnerOnly if registrant != address => {
+            return Err(StdError::generic_err(
+                "Businesses can only be registered from their own address",
+            ))
+        }
+        RegistrationMode::Allowlisted if !config.is_registrar(&registrant) => {
+            return Err(StdError::generic_err(
+                "Only the registrars can register businesses",
+            ))
+        }
+        _ => {}
+    }
+
+    if description.chars().count() as u8 > MAX_DESCRIPTION_LENGTH {
+        return Err(StdError::generic_err(format!(
+            "Description length can't be bigger than {}",
+            MAX_DESCRIPTION_LENGTH
+        )));
+    }
+
+    if name.chars().count() as u8 > MAX_NAME_LENGTH {
+        return Err(StdError::generic_err(format!(
+            "Name length can't be bigger than {}",
+            MAX_NAME_LENGTH
+        )));
+    }
+
+    // check that a correctly formatted address was given
+    deps.api.canonical_address(&address)?;
+
+    if let Some(category) = &category {
+        if may_load_category(&deps.storage, category).is_none() {
+            return Err(StdError::generic_err(format!(
+                "There is no category named {}",
+                category
+            )));
+        }
+    }
+
+    let new_business = Business {
+        name,
+        description,
+        address: HumanAddr(address.to_string()),
+        category,
+        owner: None,
+        brand: None,
+        status: BusinessStatus::Active,
+        hidden: None,
+        hidden_reviews_count: 0,
+        vouchers_weight: Uint128::zero(),
+        attestation_key: None,
+        reviews_count: 0,
+        total_weight: Uint128::from(0u128),
+        rating_sum: Uint128::from(0u128),
+        dimensions: vec![],
+        total_raw_weight: Uint128::from(0u128),
+        decayed_weight: Uint128::from(0u128),
+        decayed_rating_sum: Uint128::from(0u128),
+        decay_timestamp: 0,
+    };
+
+    create_business(&mut deps.storage, new_business)?;
+    if let Some(deposit) = deposit {
+        save_deposit(&mut deps.storage, &address, deposit)?;
+    }
+
+    Ok(HandleAnswer::RegisterBusiness {
+        status: "successfully called register business".to_string(),
+    })
+}
+
+pub fn query<S: Storage, A: Api, Q: Querier>(deps: &Extern<S, A, Q>, msg: QueryMsg) -> QueryResult {
+    match msg {
+        QueryMsg::GetBusinesses {
+            page,
+            page_size,
+            include_closed,
+        } => query_businesses(&deps.storage, page, page_size, include_closed),
+        QueryMsg::GetSingleBusiness { address } => query_business(&deps.storage, address),
+        QueryMsg::GetReviewsOnBusiness {
+            business_address,
+            page,
+            page_size,
+        } => query_reviews(&deps.storage, business_address, page, page_size),
+        QueryMsg::CheckBusinessAggregates {
+            business_address,
+            page_size,
+            rebuild,
+        } => query_business_aggregates(&deps.storage, business_address, page_size, rebuild),
+        QueryMsg::GetCategories {} => query_categories(&deps.storage),
+        QueryMsg::GetItems {
+            business_address,
+            page,
+            page_size,
+        } => query_items(&deps.storage, business_address, page, page_size),
+        QueryMsg::GetBrand { name } => query_brand(&deps.storage, name),
+        QueryMsg::GetFlaggedReviews { page, page_size } => {
+            query_flagged_reviews(&deps.storage, page, page_size)
+        }
+        QueryMsg::GetBranches {
+            brand,
+            page,
+            page_size,
+        } => query_branches(&deps.storage, brand, page, page_size),
+        QueryMsg::GetReviewHistory {
+            business_address,
+            reviewer,
+            page,
+            page_size,
+        } => query_review_history(&deps.storage, business_address, reviewer, page, page_size),
+        QueryMsg::GetRegistrationDeposit {} => query_registration_deposit(&deps.storage),
+        QueryMsg::GetBusinessDeposit { business_address } => {
+            query_business_deposit(&deps.storage, business_address)
+        }
+        QueryMsg::GetDispute {
+            business_address,
+            reviewer,
+        } => query_dispute(&deps.storage, business_address, reviewer),
+    }
+}
+
+pub fn query_businesses<S: Storage>(
+    store: &S,
+    page: Option<u32>,
+    page_size: u32,
+    include_closed: bool,
+) -> StdResult<Binary> {
+    let config = load_config(store)?;
+    let (businesses_in_range, total) = get_businesses_page(store, page, page_size, include_closed)?;
+    let displayed_businesses = businesses_in_range
+        .iter()
+        .map(|b| displayed_business(store, &config, b))
+        .collect::<StdResult<_>>()?;
+
+    to_binary(&QueryAnswer::Businesses {
+        businesses: displayed_businesses,
+        total,
+    })
+}
+
+fn displayed_business<S: Storage>(
+    store: &S,
+    config: &Config,
+    business: &Business,
+) -> StdResult<DisplayedBusiness> {
+    let rating_scale = rating_scale_of(store, config, business);
+    Ok(DisplayedBusiness {
+        name: business.name.clone(),
+        description: business.description.clone(),
+        address: business.address.clone(),
+        category: business.category.clone(),
+        claimed: business.owner.is_some(),
+        brand: business.brand.clone(),
+        status: business.status,
+        rating_scale,
+        average_rating: business.average_rating(&rating_scale)?,
+        decayed_average_rating: business.decayed_average_rating(&rating_scale)?,
+        dimension_ratings: business
+            .dimensions
+            .iter()
+            .map(|d| {
+                Ok(DisplayedDimensionRating {
+                    dimension: d.dimension.clone(),
+                    rating: rating_scale.average(d.rating_sum.u128(), d.total_weight.u128())?,
+                })
+            })
+            .collect::<StdResult<_>>()?,
+        reviews_count: business.reviews_count,
+    })
+}
+
+pub fn query_business<S: Storage>(store: &S, address: HumanAddr) -> StdResult<Binary> {
+    let config = load_config(store)?;
+    let business = get_business_by_address(store, &address)?;
+
+    let status = match &business {
+        None => "No business is registered on that address".to_string(),
+        Some(business) if business.hidden.is_some() => {
+            "The business was hidden by a moderator".to_string()
+        }
+        Some(..) => "Successfully retrieved business by address".to_string(),
+    };
+    let business = business.filter(|business| business.hidden.is_none());
+
+    to_binary(&QueryAnswer::SingleBusiness {
+        business: business
+            .as_ref()
+            .map(|b| displayed_business(store, &config, b))
+            .transpose()?,
+        status,
+    })
+}
+
+/// Queries are limited in gas as well, so the check goes through one page of reviews per call,
+/// and the caller carries the rebuild between the calls
+pub fn query_business_aggregates<S: Storage>(
+    store: &S,
+    business_address: HumanAddr,
+    page_size: u32,
+    rebuild: Option<AggregateRebuild>,
+) -> StdResult<Binary> {
+    let config = load_config(store)?;
+    let business = get_business_by_address(store, &business_address)?
+        .ok_or_else(|| StdError::generic_err("No business is registered on that address"))?;
+
+    let mut rebuild = match rebuild {
+        Some(rebuild) => rebuild,
+        None => start_rebuild(&business, page_size)?,
+    };
+    if !continue_rebuild(store, &config, &business_address, &mut rebuild)? {
+        return to_binary(&QueryAnswer::BusinessAggregates {
+            rebuild: Some(rebuild),
+            drift: None,
+        });
+    }
+
+    to_binary(&QueryAnswer::BusinessAggregates {
+        rebuild: None,
+        drift: Some(aggregates_drift(&business, rebuild.aggregates)),
+    })
+}
+
+pub fn query_reviews<S: Storage>(
+    store: &S,
+    business_address: HumanAddr,
+    page: Option<u32>,
+    page_size: u32,
+) -> StdResult<Binary> {
+    let config = load_config(store)?;
+    let (rating_scale, hidden_reviews_count) =
+        match get_business_by_address(store, &business_address)? {
+            Some(business) => (
+                rating_scale_of(store, &config, &business),
+                business.hidden_reviews_count,
+            ),
+            None => (config.rating_scale, 0),
+        };
+
+    let (reviews_page, total) =
+        get_reviews_on_business(store, &business_address, &rating_scale, page, page_size)?;
+    // a drifted counter shouldn't keep the reviews from being listed
+    let total = total.saturating_sub(hidden_reviews_count);
+
+    to_binary(&QueryAnswer::Reviews {
+        reviews: reviews_page,
+        total,
+        rating_scale,
+    })
+}
+
+pub fn query_items<S: Storage>(
+    store: &S,
+    business_address: HumanAddr,
+    page: Option<u32>,
+    page_size: u32,
+) -> StdResult<Binary> {
+    let config = load_config(store)?;
+    let business = get_business_by_address(store, &business_address)?
+        .ok_or_else(|| StdError::generic_err("No business is registered on that address"))?;
+    let rating_scale = rating_scale_of(store, &config, &business);
+
+    let (items, total) = get_items_page(store, &business_address, page.unwrap_or(0), page_size)?;
+    let items = items
+        .into_iter()
+        .map(|item| {
+            Ok(DisplayedItem {
+                average_rating: rating_scale
+                    .average(item.rating_sum.u128(), item.total_weight.u128())?,
+                sku: item.sku,
+                name: item.name,
+                reviews_count: item.reviews_count,
+            })
+        })
+        .collect::<StdResult<_>>()?;
+
+    to_binary(&QueryAnswer::Items {
+        items,
+        total,
+        rating_scale,
+    })
+}
+
+pub fn query_brand<S: Storage>(store: &S, name: String) -> StdResult<Binary> {
+    let brand = match may_load_brand(store, &name) {
+        Some(brand) => {
+            let (_, branches_count) = get_branches_page(store, &name, 0, 0)?;
+            Some(DisplayedBrand {
+                average_rating: brand
+                    .rating_scale
+                    .average(brand.rating_sum.u128(), brand.total_weight.u128())?,
+                name: brand.name,
+                owner: brand.owner,
+                rating_scale: brand.rating_scale,
+                reviews_count: brand.reviews_count,
+                branches_count,
+            })
+        }
+        None => None,
+    };
+
+    to_binary(&QueryAnswer::Brand { brand })
+}
+
+pub fn query_branches<S: Storage>(
+    store: &S,
+    brand: String,
+    page: Option<u32>,
+    page_size: u32,
+) -> StdResult<Binary> {
+    let config = load_config(store)?;
+    let (addresses, total) = get_branches_page(store, &brand, page.unwrap_or(0), page_size)?;
+
+    let mut branches = vec![];
+    for address in addresses {
+        let business = get_business_by_address(store, &address)?
+            .ok_or_else(|| StdError::generic_err("Critical failure loading a branch"))?;
+        if business.hidden.is_none() {
+            branches.push(displayed_business(store, &config, &business)?);
+        }
+    }
+
+    to_binary(&QueryAnswer::Branches { branches, total })
+}
+
+pub fn query_flagged_reviews<S: Storage>(
+    store: &S,
+    page: Option<u32>,
+    page_size: u32,
+) -> StdResult<Binary> {
+    let config = load_config(store)?;
+    let (flagged_reviews, total) = get_flagged_reviews_page(store, page.unwrap_or(0), page_size)?;
+
+    let mut reviews = vec![];
+    for flagged in flagged_reviews {
+        let business = get_business_by_address(store, &flagged.business_address)?
+            .ok_or_else(|| StdError::generic_err("Critical failure loading a flagged business"))?;
+        let review = may_load_review(store, &flagged.business_address, &flagged.reviewer)
+            .ok_or_else(|| StdError::generic_err("Critical failure loading a flagged review"))?;
+        reviews.push(DisplayedFlaggedReview {
+            hidden: review.hidden.is_some(),
+            review: review.displayed(&rating_scale_of(store, &config, &business))?,
+            business_address: flagged.business_address,
+            reviewer: flagged.reviewer,
+            flags_count: flagged.flags_count,
+            flagged_weight: flagged.flagged_weight,
+            reasons: flagged.reasons,
+        });
+    }
+
+    to_binary(&QueryAnswer::FlaggedReviews { reviews, total })
+}
+
+/* The history of a review is left out with the review when it is hidden, and
+its content is left out when an arbiter hid the content of the review. */
+pub fn query_review_history<S: Storage>(
+    store: &S,
+    business_address: HumanAddr,
+    reviewer: HumanAddr,
+    page: Option<u32>,
+    page_size: u32,
+) -> StdResult<Binary> {
+    let config = load_config(store)?;
+    let business = get_business_by_address(store, &business_address)?
+        .ok_or_else(|| StdError::generic_err("No business is registered on that address"))?;
+    let rating_scale = rating_scale_of(store, &config, &business);
+
+    let review = may_load_review(store, &business_address, &reviewer)
+        .filter(|review| review.hidden.is_none());
+    let review = match review {
+        Some(review) => review,
+        None => {
+            return to_binary(&QueryAnswer::ReviewHistory {
+                revisions: vec![],
+                total: 0,
+                rating_scale,
+            })
+        }
+    };
+    let content_hidden = review.dispute_outcome == Some(DisputeOutcome::HideContent);
+
+    let (revisions, total) = get_revisions_page(
+        store,
+        &business_address,
+        &reviewer,
+        page.unwrap_or(0),
+        page_size,
+    )?;
+    let revisions = revisions
+        .into_iter()
+        .map(|revision| {
+            let (title, content) = if content_hidden {
+                (String::new(), String::new())
+            } else {
+                (revision.title, revision.content)
+            };
+            Ok(DisplayedRevision {
+                title,
+                content,
+                rating: rating_scale.average(revision.rating as u128, 1)?,
+                item: revision.item,
+                timestamp: revision.timestamp,
+            })
+        })
+        .collect::<StdResult<_>>()?;
+
+    to_binary(&QueryAnswer::ReviewHistory {
+        revisions,
+        total,
+        rating_scale,
+    })
+}
+
+pub fn query_registration_deposit<S: Storage>(store: &S) -> StdResult<Binary> {
+    to_binary(&QueryAnswer::RegistrationDeposit {
+        deposit: load_config(store)?.registration_deposit,
+    })
+}
+
+pub fn query_business_deposit<S: Storage>(
+    store: &S,
+    business_address: HumanAddr,
+) -> StdResult<Binary> {
+    // whoever paid the deposit is kept private
+    let deposit = may_load_deposit(store, &business_address).map(|deposit| DisplayedDeposit {
+        amount: deposit.amount,
+        token: deposit.token,
+        refundable: deposit.refundable,
+    });
+
+    to_binary(&QueryAnswer::BusinessDeposit { deposit })
+}
+
+pub fn query_dispute<S: Storage>(
+    store: &S,
+    business_address: HumanAddr,
+    reviewer: HumanAddr,
+) -> StdResult<Binary> {
+    // the owner who opened the dispute is kept private, like the owner of the business
+    let dispute =
+        may_load_dispute(store, &business_address, &reviewer).map(|dispute| DisplayedDispute {
+            business_address: dispute.business_address,
+            reviewer: dispute.reviewer,
+            bond: dispute.bond,
+            reason: dispute.reason,
+            response: dispute.response,
+            state: dispute.state,
+            opened_timestamp: dispute.opened_timestamp,
+        });
+
+    to_binary(&QueryAnswer::Dispute { dispute })
+}
+
+pub fn query_categories<S: Storage>(store: &S) -> StdResult<Binary> {
+    to_binary(&QueryAnswer::Categories {
+        categories: get_all_categories(store),
+    })
+}
+
+#[cfg(test)]
 mod tests {
-    use cosmwasm_std::testing::{mock_dependencies, mock_env};
-    use cosmwasm_std::{coins, from_binary};
+    use cosmwasm_std::testing::{
+        mock_dependencies, mock_env, MockApi, MockStorage, MOCK_CONTRACT_ADDR,
+    };
+    use cosmwasm_std::{
+        coins, from_binary, from_slice, Coin, Empty, QuerierResult, QueryRequest, WasmQuery,
+    };
     use secret_toolkit::incubator::CashMap;
+    use secret_toolkit::snip20::Tx;
+    use serde::{Deserialize, Serialize};
+
+    use super::*;
+    use crate::rating::Rating;
+    use crate::state::{get_business_by_address, DepositToken, LegacyBusiness, LegacyReview};
+
+    /// Answers SNIP-20 transfer history queries from a fixed list of (token, transfer)
+    struct Snip20Querier {
+        transfers: Vec<(HumanAddr, Tx)>,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(rename_all = "snake_case")]
+    enum Snip20QueryMsg {
+        TransferHistory {
+            address: HumanAddr,
+            page: Option<u32>,
+            page_size: u32,
+        },
+    }
+
+    #[derive(Serialize)]
+    #[serde(rename_all = "snake_case")]
+    enum Snip20QueryAnswer {
+        TransferHistory { txs: Vec<Tx>, total: Option<u64> },
+    }
+
+    impl Querier for Snip20Querier {
+        fn raw_query(&self, bin_request: &[u8]) -> QuerierResult {
+            let (contract_addr, msg) = match from_slice(bin_request).unwrap() {
+                QueryRequest::<Empty>::Wasm(WasmQuery::Smart {
+                    contract_addr, msg, ..
+                }) => (contract_addr, msg),
+                _ => panic!("only smart queries are supported"),
+            };
+
+            let Snip20QueryMsg::TransferHistory {
+                address,
+                page,
+                page_size,
+            } = from_binary(&msg).unwrap();
+
+            let txs = self
+                .transfers
+                .iter()
+                .filter(|(token, tx)| {
+                    *token == contract_addr && (tx.sender == address || tx.receiver == address)
+                })
+                .map(|(_, tx)| tx.clone())
+                .skip((page.unwrap_or(0) * page_size) as usize)
+                .take(page_size as usize)
+                .collect();
+
+            Ok(to_binary(&Snip20QueryAnswer::TransferHistory {
+                txs,
+                total: None,
+            }))
+        }
+    }
+
+    fn mock_dependencies_with_transfers(
+        transfers: Vec<(HumanAddr, Tx)>,
+    ) -> Extern<MockStorage, MockApi, Snip20Querier> {
+        Extern {
+            storage: MockStorage::default(),
+            api: MockApi::new(20),
+            querier: Snip20Querier { transfers },
+        }
+    }
+
+    fn transfer(id: u64, from: &str, to: &str, amount: u128) -> Tx {
+        Tx {
+            id,
+            from: HumanAddr(from.to_string()),
+            sender: HumanAddr(from.to_string()),
+            receiver: HumanAddr(to.to_string()),
+            coins: Coin {
+                denom: "TKN".to_string(),
+                amount: Uint128(amount),
+            },
+            memo: None,
+            block_time: None,
+            block_height: None,
+        }
+    }
+
+    /// Goes through the pages of the aggregates check of a business, and returns its drift
+    fn check_aggregates<Q: Querier>(
+        deps: &Extern<MockStorage, MockApi, Q>,
+        business_address: &str,
+    ) -> StdResult<AggregatesDrift> {
+        let mut rebuild = None;
+        loop {
+            let msg = QueryMsg::CheckBusinessAggregates {
+                business_address: HumanAddr(business_address.to_string()),
+                page_size: 2,
+                rebuild,
+            };
+            match from_binary(&query(deps, msg)?)? {
+                QueryAnswer::BusinessAggregates {
+                    drift: Some(drift), ..
+                } => return Ok(drift),
+                QueryAnswer::BusinessAggregates { rebuild: next, .. } => rebuild = next,
+                _ => panic!("got wrong answer variant"),
+            }
+        }
+    }
+
+    /// A business that was just registered on the given address
+    fn unreviewed_business(address: &str, name: &str, description: &str) -> Business {
+        Business {
+            address: HumanAddr(address.to_string()),
+            category: None,
+            owner: None,
+            brand: None,
+            status: BusinessStatus::Active,
+            hidden: None,
+            hidden_reviews_count: 0,
+            vouchers_weight: Uint128::zero(),
+            attestation_key: None,
+            name: name.to_string(),
+            description: description.to_string(),
+            reviews_count: 0,
+            total_weight: Uint128::zero(),
+            rating_sum: Uint128::zero(),
+            dimensions: vec![],
+            total_raw_weight: Uint128::zero(),
+            decayed_weight: Uint128::zero(),
+            decayed_rating_sum: Uint128::zero(),
+            decay_timestamp: 0,
+        }
+    }
+
+    #[test]
+    fn register_business() -> StdResult<()> {
+        let mut deps = mock_dependencies(20, &coins(2, "token"));
+
+        let msg = InitMsg::default();
+        let env = mock_env("creator", &coins(2, "token"));
+        let _res = init(&mut deps, env, msg).unwrap();
+
+        let env = mock_env("anyone", &[]);
+        let msg = HandleMsg::RegisterBusiness {
+            name: "Starbucks".to_string(),
+            description: "a place to eat".to_string(),
+            address: HumanAddr("mock-address".to_string()),
+            category: None,
+        };
+        let res = handle(&mut deps, env, msg)?;
+        match from_binary(&res.data.unwrap())? {
+            HandleAnswer::RegisterBusiness { status } => {
+                assert_eq!("successfully called register business", status)
+            }
+            _ => panic!("got wrong answer variant"),
+        }
+
+        // check that the business was indeed saved
+        let saved = get_business_by_address(&deps.storage, &HumanAddr("mock-address".to_string()));
+
+        assert_eq!(
+            saved.unwrap().unwrap(),
+            unreviewed_business("mock-address", "Starbucks", "a place to eat")
+        );
+
+        create_business(
+            &mut deps.storage,
+            unreviewed_business("second", "second", "second"),
+        )?;
+
+        create_business(
+            &mut deps.storage,
+            unreviewed_business("third", "third", "third"),
+        )?;
+
+        create_business(
+            &mut deps.storage,
+            unreviewed_business("arthur", "arthur", "arthur the third"),
+        )?;
+
+        // QUERY
+        let msg = QueryMsg::GetBusinesses {
+            page: Some(1),
+            page_size: 2,
+            include_closed: false,
+        };
+
+        let res = query(&deps, msg);
+        let res_unpacked: QueryAnswer = from_binary(&res.unwrap()).unwrap();
+        match res_unpacked {
+            QueryAnswer::Businesses { businesses, total } => {
+                assert_eq!(total, 4);
+                assert_eq!(businesses[0].address.as_str(), "third")
+            }
+            _ => panic!("wrong query variant"),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn register_existing_business() {
+        let mut deps = mock_dependencies(20, &coins(2, "token"));
+
+        let msg = InitMsg::default();
+        let env = mock_env("creator", &coins(2, "token"));
+        let _res = init(&mut deps, env, msg).unwrap();
+
+        let env = mock_env("anyone", &[]);
+        let msg = HandleMsg::RegisterBusiness {
+            name: "Starbucks".to_string(),
+            description: "a place to eat".to_string(),
+            address: HumanAddr("mock-address".to_string()),
+            category: None,
+        };
+        let res = handle(&mut deps, env, msg);
+        let res_unpacked = from_binary::<HandleAnswer>(&res.unwrap().data.unwrap()).unwrap();
+        match res_unpacked {
+            HandleAnswer::RegisterBusiness { status } => {
+                assert_eq!("successfully called register business", status)
+            }
+            _ => panic!("got wrong answer variant"),
+        }
+
+        // another business, should succeed
+        let env = mock_env("anyone", &[]);
+        let msg = HandleMsg::RegisterBusiness {
+            name: "Starbucks".to_string(),
+            description: "a place to eat".to_string(),
+            address: HumanAddr("another-address".to_string()),
+            category: None,
+        };
+        let res = handle(&mut deps, env, msg);
+        let res_unpacked = from_binary::<HandleAnswer>(&res.unwrap().data.unwrap()).unwrap();
+        match res_unpacked {
+            HandleAnswer::RegisterBusiness { status } => {
+                assert_eq!("successfully called register business", status)
+            }
+            _ => panic!("got wrong answer variant"),
+        }
+
+        // again, should fail
+        let env = mock_env("anyone", &[]);
+        let msg = HandleMsg::RegisterBusiness {
+            name: "Starbucks".to_string(),
+            description: "a place to eat".to_string(),
+            address: HumanAddr("mock-address".to_string()),
+            category: None,
+        };
+        let res = handle(&mut deps, env, msg).unwrap_err();
+        assert_eq!(
+            res,
+            StdError::generic_err("A business is already registered on that address")
+        );
+    }
+
+    #[test]
+    fn review_unregistered_business() -> StdResult<()> {
+        let mut deps = mock_dependencies(20, &coins(2, "token"));
+
+        let msg = InitMsg::default();
+        let env = mock_env("creator", &coins(2, "token"));
+        let _res = init(&mut deps, env, msg).unwrap();
+
+        let env = mock_env("anyone", &[]);
+        let msg = HandleMsg::ReviewBusiness {
+            address: HumanAddr("mock-address".to_string()),
+            content: "very enjoyable time at this place".to_string(),
+            rating: 5,
+            title: "Fantastic!".to_string(),
+            receipts: vec![Receipt::Snip20 {
+                token: None,
+                tx_id: 0,
+                tx_page: 0,
+                viewing_key: "vk".to_string(),
+            }],
+            dimension_ratings: vec![],
+            item: None,
+        };
+
+        let res = handle(&mut deps, env, msg);
+        let error = res.unwrap_err();
+
+        if let StdError::GenericErr { msg, .. } = error {
+            assert_eq!(
+                "There is no business registered on that address. You can register it instead.",
+                msg
+            )
+        } else {
+            panic!("there should be a generic error here")
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn review_rating_out_of_bounds() -> StdResult<()> {
+        let mut deps = mock_dependencies(20, &coins(2, "token"));
+
+        let msg = InitMsg::default();
+        let env = mock_env("creator", &coins(2, "token"));
+        init(&mut deps, env, msg).unwrap();
+
+        let env = mock_env("anyone", &[]);
+        let msg = HandleMsg::RegisterBusiness {
+            name: "Starbucks".to_string(),
+            description: "a place to eat".to_string(),
+            address: HumanAddr("mock-address".to_string()),
+            category: None,
+        };
+        handle(&mut deps, env, msg)?;
+
+        // 1st review
+        let env = mock_env("anyone", &[]);
+        let msg = HandleMsg::ReviewBusiness {
+            address: HumanAddr("mock-address".to_string()),
+            content: "very enjoyable time at this place".to_string(),
+            rating: 6,
+            title: "Fantastic!".to_string(),
+            receipts: vec![Receipt::Snip20 {
+                token: None,
+                tx_id: 0,
+                tx_page: 0,
+                viewing_key: "vk".to_string(),
+            }],
+            dimension_ratings: vec![],
+            item: None,
+        };
+
+        let res = handle(&mut deps, env, msg);
+        let error = res.unwrap_err();
+
+        if let StdError::GenericErr { msg, .. } = error {
+            assert_eq!("ratings must be between 0 and 5 stars", msg);
+        } else {
+            panic!("there should be a generic error here");
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn register_business_long_name() {
+        let mut deps = mock_dependencies(20, &coins(2, "token"));
+
+        let msg = InitMsg::default();
+        let env = mock_env("creator", &coins(2, "token"));
+        let _res = init(&mut deps, env, msg).unwrap();
+
+        let env = mock_env("anyone", &[]);
+        let msg = HandleMsg::RegisterBusiness {
+            name: "NameIs21Characters...".to_string(),
+            description: "a place to eat".to_string(),
+            address: HumanAddr("mock-address".to_string()),
+            category: None,
+        };
+
+        let res = handle(&mut deps, env, msg);
+        let error = res.unwrap_err();
+
+        if let StdError::GenericErr { msg, .. } = error {
+            assert_eq!("Name length can't be bigger than 20", msg)
+        } else {
+            panic!("there should be a generic error here")
+        }
+    }
+
+    #[test]
+    fn register_business_long_description() {
+        let mut deps = mock_dependencies(20, &coins(2, "token"));
+
+        let msg = InitMsg::default();
+        let env = mock_env("creator", &coins(2, "token"));
+        let _res = init(&mut deps, env, msg).unwrap();
+
+        let env = mock_env("anyone", &[]);
+        let msg = HandleMsg::RegisterBusiness {
+            name: "Scrt Labs".to_string(),
+            description: "DescriptionIs43CharactersLongWhichIsTooMuch".to_string(),
+            address: HumanAddr("mock-address".to_string()),
+            category: None,
+        };
+
+        let res = handle(&mut deps, env, msg);
+        let error = res.unwrap_err();
+
+        if let StdError::GenericErr { msg, .. } = error {
+            assert_eq!("Description length can't be bigger than 40", msg)
+        } else {
+            panic!("there should be a generic error here")
+        }
+    }
+
+    #[test]
+    fn review_with_native_receipt() -> StdResult<()> {
+        let mut deps = mock_dependencies(20, &coins(2, "token"));
+
+        let env = mock_env("creator", &coins(2, "token"));
+        init(&mut deps, env, InitMsg::default())?;
+
+        let env = mock_env("anyone", &[]);
+        let msg = HandleMsg::RegisterBusiness {
+            name: "Starbucks".to_string(),
+            description: "a place to eat".to_string(),
+            address: HumanAddr("mock-address".to_string()),
+            category: None,
+        };
+        handle(&mut deps, env, msg)?;
+
+        // paying in another denom should fail
+        let env = mock_env("customer", &coins(100, "token"));
+        let msg = HandleMsg::PayBusiness {
+            address: HumanAddr("mock-address".to_string()),
+        };
+        let error = handle(&mut deps, env, msg).unwrap_err();
+        assert_eq!(
+            error,
+            StdError::generic_err("Payments must be made in uscrt only")
+        );
+
+        let env = mock_env("customer", &coins(100, "uscrt"));
+        let msg = HandleMsg::PayBusiness {
+            address: HumanAddr("mock-address".to_string()),
+        };
+        let res = handle(&mut deps, env, msg)?;
+        assert_eq!(
+            res.messages,
+            vec![CosmosMsg::Bank(BankMsg::Send {
+                from_address: HumanAddr(MOCK_CONTRACT_ADDR.to_string()),
+                to_address: HumanAddr("mock-address".to_string()),
+                amount: coins(100, "uscrt"),
+            })]
+        );
+        let receipt_id = match from_binary(&res.data.unwrap())? {
+            HandleAnswer::PayBusiness { receipt_id, .. } => receipt_id,
+            _ => panic!("got wrong answer variant"),
+        };
+
+        // the receipt can only be cited by its payer
+        let env = mock_env("someone-else", &[]);
+        let msg = HandleMsg::ReviewBusiness {
+            address: HumanAddr("mock-address".to_string()),
+            content: "very enjoyable time at this place".to_string(),
+            rating: 4,
+            title: "Fantastic!".to_string(),
+            receipts: vec![Receipt::Native { receipt_id }],
+            dimension_ratings: vec![],
+            item: None,
+        };
+        let error = handle(&mut deps, env, msg.clone()).unwrap_err();
+        assert_eq!(
+            error,
+            StdError::generic_err(
+                "The specified native payment was not paid by the authenticated account"
+            )
+        );
+
+        let env = mock_env("customer", &[]);
+        let res = handle(&mut deps, env, msg.clone())?;
+        match from_binary(&res.data.unwrap())? {
+            HandleAnswer::ReviewBusiness { status } => assert_eq!(
+                status,
+                "Successfully added a new review on business, receipt was accounted for"
+            ),
+            _ => panic!("got wrong answer variant"),
+        }
+
+        // citing the same receipt again only updates the review
+        let env = mock_env("customer", &[]);
+        let res = handle(&mut deps, env, msg)?;
+        match from_binary(&res.data.unwrap())? {
+            HandleAnswer::ReviewBusiness { status } => assert_eq!(
+                status,
+                "Successfully updated a previous review on business, specified receipt was already used"
+            ),
+            _ => panic!("got wrong answer variant"),
+        }
+
+        let business =
+            get_business_by_address(&deps.storage, &HumanAddr("mock-address".to_string()))?
+                .unwrap();
+        assert_eq!(
+            business.average_rating(&RatingScale::Stars)?,
+            "4".parse::<Rating>()?
+        );
+        assert_eq!(business.total_weight, Uint128(100));
+        assert_eq!(business.reviews_count, 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn review_with_multiple_receipts() -> StdResult<()> {
+        let mut deps = mock_dependencies(20, &coins(2, "token"));
+
+        let env = mock_env("creator", &coins(2, "token"));
+        init(&mut deps, env, InitMsg::default())?;
+
+        let env = mock_env("anyone", &[]);
+        let msg = HandleMsg::RegisterBusiness {
+            name: "Starbucks".to_string(),
+            description: "a place to eat".to_string(),
+            address: HumanAddr("mock-address".to_string()),
+            category: None,
+        };
+        handle(&mut deps, env, msg)?;
+
+        for amount in &[10, 20, 30] {
+            let env = mock_env("customer", &coins(*amount, "uscrt"));
+            let msg = HandleMsg::PayBusiness {
+                address: HumanAddr("mock-address".to_string()),
+            };
+            handle(&mut deps, env, msg)?;
+        }
+
+        // a new review can't be posted without a receipt
+        let env = mock_env("customer", &[]);
+        let msg = HandleMsg::ReviewBusiness {
+            address: HumanAddr("mock-address".to_string()),
+            content: "great coffee".to_string(),
+            rating: 5,
+            title: "Loyal customer".to_string(),
+            receipts: vec![],
+            dimension_ratings: vec![],
+            item: None,
+        };
+        let error = handle(&mut deps, env, msg).unwrap_err();
+        assert_eq!(
+            error,
+            StdError::generic_err("A new review must provide at least one receipt")
+        );
+
+        // the duplicate receipt in the list is only accounted for once
+        let env = mock_env("customer", &[]);
+        let msg = HandleMsg::ReviewBusiness {
+            address: HumanAddr("mock-address".to_string()),
+            content: "great coffee".to_string(),
+            rating: 5,
+            title: "Loyal customer".to_string(),
+            receipts: vec![
+                Receipt::Native { receipt_id: 0 },
+                Receipt::Native { receipt_id: 1 },
+                Receipt::Native { receipt_id: 0 },
+            ],
+            dimension_ratings: vec![],
+            item: None,
+        };
+        let res = handle(&mut deps, env, msg)?;
+        match from_binary(&res.data.unwrap())? {
+            HandleAnswer::ReviewBusiness { status } => assert_eq!(
+                status,
+                "Successfully added a new review on business, 2 receipts were accounted for, \
+                specified receipt was already used"
+            ),
+            _ => panic!("got wrong answer variant"),
+        }
+
+        let env = mock_env("customer", &[]);
+        let msg = HandleMsg::ReviewBusiness {
+            address: HumanAddr("mock-address".to_string()),
+            content: "still great".to_string(),
+            rating: 3,
+            title: "Loyal customer".to_string(),
+            receipts: vec![
+                Receipt::Native { receipt_id: 0 },
+                Receipt::Native { receipt_id: 1 },
+                Receipt::Native { receipt_id: 2 },
+            ],
+            dimension_ratings: vec![],
+            item: None,
+        };
+        let res = handle(&mut deps, env, msg)?;
+        match from_binary(&res.data.unwrap())? {
+            HandleAnswer::ReviewBusiness { status } => assert_eq!(
+                status,
+                "Successfully updated a previous review on business, receipt was accounted for, \
+                2 specified receipts were already used"
+            ),
+            _ => panic!("got wrong answer variant"),
+        }
+
+        let business =
+            get_business_by_address(&deps.storage, &HumanAddr("mock-address".to_string()))?
+                .unwrap();
+        assert_eq!(business.total_weight, Uint128(60));
+        assert_eq!(
+            business.average_rating(&RatingScale::Stars)?,
+            "3".parse::<Rating>()?
+        );
+
+        let review = may_load_review(
+            &deps.storage,
+            &HumanAddr("mock-address".to_string()),
+            &HumanAddr("customer".to_string()),
+        )
+        .unwrap();
+        assert_eq!(review.weight, Uint128(60));
+        assert_eq!(
+            review.tx_ids,
+            vec![
+                ReceiptId::Native { receipt_id: 0 },
+                ReceiptId::Native { receipt_id: 1 },
+                ReceiptId::Native { receipt_id: 2 }
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn receipts_are_qualified_by_token() -> StdResult<()> {
+        let token_a = HumanAddr("token-a".to_string());
+        let token_b = HumanAddr("token-b".to_string());
+        let mut deps = mock_dependencies_with_transfers(vec![
+            (token_a.clone(), transfer(8, "customer", "mock-address", 10)),
+            (token_b.clone(), transfer(8, "customer", "mock-address", 30)),
+        ]);
+
+        let env = mock_env("creator", &[]);
+        let msg = InitMsg {
+            accepted_tokens: Some(vec![
+                Snip20Token {
+                    address: token_a.clone(),
+                    code_hash: "hash-a".to_string(),
+                    min_receipt_amount: Uint128(5),
+                },
+                Snip20Token {
+                    address: token_b.clone(),
+                    code_hash: "hash-b".to_string(),
+                    min_receipt_amount: Uint128(5),
+                },
+            ]),
+            ..InitMsg::default()
+        };
+        init(&mut deps, env, msg)?;
+
+        let env = mock_env("anyone", &[]);
+        let msg = HandleMsg::RegisterBusiness {
+            name: "Starbucks".to_string(),
+            description: "a place to eat".to_string(),
+            address: HumanAddr("mock-address".to_string()),
+            category: None,
+        };
+        handle(&mut deps, env, msg)?;
+
+        let snip20_receipt = |token: &HumanAddr| Receipt::Snip20 {
+            token: Some(token.clone()),
+            tx_id: 8,
+            tx_page: 0,
+            viewing_key: "vk".to_string(),
+        };
+
+        // sSCRT is not accepted in this deployment
+        let env = mock_env("customer", &[]);
+        let msg = HandleMsg::ReviewBusiness {
+            address: HumanAddr("mock-address".to_string()),
+            content: "great coffee".to_string(),
+            rating: 4,
+            title: "Fantastic!".to_string(),
+            receipts: vec![Receipt::Snip20 {
+                token: None,
+                tx_id: 8,
+                tx_page: 0,
+                viewing_key: "vk".to_string(),
+            }],
+            dimension_ratings: vec![],
+            item: None,
+        };
+        let error = handle(&mut deps, env, msg).unwrap_err();
+        assert_eq!(
+            error,
+            StdError::generic_err(format!("{} is not an accepted token", SSCRT_ADDRESS))
+        );
+
+        let env = mock_env("customer", &[]);
+        let msg = HandleMsg::ReviewBusiness {
+            address: HumanAddr("mock-address".to_string()),
+            content: "great coffee".to_string(),
+            rating: 4,
+            title: "Fantastic!".to_string(),
+            receipts: vec![snip20_receipt(&token_a)],
+            dimension_ratings: vec![],
+            item: None,
+        };
+        handle(&mut deps, env, msg)?;
+
+        // the same tx id in another token is a different receipt
+        let env = mock_env("customer", &[]);
+        let msg = HandleMsg::ReviewBusiness {
+            address: HumanAddr("mock-address".to_string()),
+            content: "great coffee".to_string(),
+            rating: 4,
+            title: "Fantastic!".to_string(),
+            receipts: vec![snip20_receipt(&token_a), snip20_receipt(&token_b)],
+            dimension_ratings: vec![],
+            item: None,
+        };
+        let res = handle(&mut deps, env, msg)?;
+        match from_binary(&res.data.unwrap())? {
+            HandleAnswer::ReviewBusiness { status } => assert_eq!(
+                status,
+                "Successfully updated a previous review on business, receipt was accounted for, \
+                specified receipt was already used"
+            ),
+            _ => panic!("got wrong answer variant"),
+        }
+
+        let business =
+            get_business_by_address(&deps.storage, &HumanAddr("mock-address".to_string()))?
+                .unwrap();
+        assert_eq!(business.total_weight, Uint128(40));
+
+        Ok(())
+    }
+
+    #[test]
+    fn migrate_reviews_from_legacy_namespace() -> StdResult<()> {
+        let sscrt = HumanAddr(SSCRT_ADDRESS.to_string());
+        let mut deps = mock_dependencies_with_transfers(vec![(
+            sscrt,
+            transfer(1, "regular", "mock-address", 100),
+        )]);
+
+        let env = mock_env("creator", &[]);
+        init(&mut deps, env, InitMsg::default())?;
+
+        // the business and its reviews, as they were saved before
+        let mut legacy_businesses = CashMap::init(b"businesses", &mut deps.storage);
+        legacy_businesses.insert(
+            b"mock-address",
+            LegacyBusiness {
+                name: "Starbucks".to_string(),
+                description: "a place to eat".to_string(),
+                address: HumanAddr("mock-address".to_string()),
+                average_rating: Uint128(4250),
+                reviews_count: 2,
+                total_weight: Uint128(40),
+            },
+        )?;
+        let mut legacy_reviews = CashMap::init(b"reviewsmock-address", &mut deps.storage);
+        for (reviewer, rating, weight, tx_id) in &[("customer", 4, 30, 8), ("regular", 5, 10, 9)] {
+            legacy_reviews.insert(
+                reviewer.as_bytes(),
+                LegacyReview {
+                    title: "Fantastic!".to_string(),
+                    content: "great coffee".to_string(),
+                    rating: *rating,
+                    last_update_timestamp: 1,
+                    weight: Uint128(*weight),
+                    tx_ids: vec![*tx_id],
+                },
+            )?;
+        }
+        let mut garbled_reviews = CashMap::init(b"reviewsother-address", &mut deps.storage);
+        garbled_reviews.insert(b"garbled", "not a review".to_string())?;
+
+        let env = mock_env("creator", &[]);
+        handle(&mut deps, env, HandleMsg::MigrateBusinesses { limit: 1 })?;
+
+        // a reviewer that reviews again before their legacy review was migrated
+        let env = mock_env("regular", &[]);
+        let msg = HandleMsg::ReviewBusiness {
+            address: HumanAddr("mock-address".to_string()),
+            content: "content".to_string(),
+            rating: 5,
+            title: "title".to_string(),
+            receipts: vec![Receipt::Snip20 {
+                token: None,
+                tx_id: 1,
+                tx_page: 0,
+                viewing_key: "vk".to_string(),
+            }],
+            dimension_ratings: vec![],
+            item: None,
+        };
+        handle(&mut deps, env, msg)?;
+
+        let msg = HandleMsg::MigrateReviews {
+            business_address: HumanAddr("mock-address".to_string()),
+            reviewers: vec![
+                HumanAddr("customer".to_string()),
+                HumanAddr("regular".to_string()),
+                HumanAddr("stranger".to_string()),
+            ],
+        };
+
+        let env = mock_env("anyone", &[]);
+        let error = handle(&mut deps, env, msg.clone()).unwrap_err();
+        assert_eq!(error, StdError::unauthorized());
+
+        let env = mock_env("creator", &[]);
+        let res = handle(&mut deps, env, msg)?;
+        match from_binary(&res.data.unwrap())? {
+            HandleAnswer::MigrateReviews { status } => assert_eq!(
+                status,
+                "Migrated 1 reviews, dropped 1 that were replaced by newer ones"
+            ),
+            _ => panic!("got wrong answer variant"),
+        }
+
+        let review = may_load_review(
+            &deps.storage,
+            &HumanAddr("mock-address".to_string()),
+            &HumanAddr("customer".to_string()),
+        )
+        .unwrap();
+        assert_eq!(review.weight, Uint128(30));
+        assert_eq!(review.rating, 80);
+        assert!(!review.associated);
+        assert_eq!(review.revisions_count, 0);
+        assert_eq!(review.created_timestamp, None);
+        assert_eq!(
+            review.tx_ids,
+            vec![ReceiptId::Snip20 {
+                token: HumanAddr(SSCRT_ADDRESS.to_string()),
+                tx_id: 8
+            }]
+        );
+
+        // the legacy contribution of the replaced review was taken out of the aggregates
+        let business =
+            get_business_by_address(&deps.storage, &HumanAddr("mock-address".to_string()))?
+                .unwrap();
+        assert_eq!(business.reviews_count, 2);
+        assert_eq!(business.total_weight, Uint128(130));
+        assert_eq!(business.rating_sum, Uint128(30 * 80 + 100 * 100));
+
+        let msg = HandleMsg::MigrateReviews {
+            business_address: HumanAddr("other-address".to_string()),
+            reviewers: vec![HumanAddr("garbled".to_string())],
+        };
+        let env = mock_env("creator", &[]);
+        match handle(&mut deps, env, msg) {
+            Err(StdError::GenericErr { msg, .. }) => {
+                assert_eq!(msg, "The legacy review of garbled can't be decoded")
+            }
+            _ => panic!("an undecodable legacy review was skipped"),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn refunds_are_netted_from_weight() -> StdResult<()> {
+        let sscrt = HumanAddr(SSCRT_ADDRESS.to_string());
+        let mut deps = mock_dependencies_with_transfers(vec![
+            (sscrt.clone(), transfer(1, "customer", "mock-address", 100)),
+            (sscrt.clone(), transfer(2, "customer", "mock-address", 50)),
+            (
+                sscrt.clone(),
+                transfer(3, "other-customer", "mock-address", 100),
+            ),
+            // a payment that doesn't match any receipt isn't a refund
+            (sscrt.clone(), transfer(4, "mock-address", "customer", 25)),
+            (
+                sscrt.clone(),
+                Tx {
+                    memo: Some("3".to_string()),
+                    ..transfer(5, "mock-address", "other-customer", 60)
+                },
+            ),
+        ]);
+
+        let env = mock_env("creator", &[]);
+        let msg = InitMsg {
+            refund_netting: Some(true),
+            ..InitMsg::default()
+        };
+        init(&mut deps, env, msg)?;
+
+        let env = mock_env("anyone", &[]);
+        let msg = HandleMsg::RegisterBusiness {
+            name: "Starbucks".to_string(),
+            description: "a place to eat".to_string(),
+            address: HumanAddr("mock-address".to_string()),
+            category: None,
+        };
+        handle(&mut deps, env, msg)?;
+
+        // refunds on the page that a receipt is verified on are netted right away
+        for (reviewer, tx_ids, rating, expected_status) in &[
+            (
+                "customer",
+                vec![1, 2],
+                5,
+                "Successfully added a new review on business, 2 receipts were accounted for",
+            ),
+            (
+                "other-customer",
+                vec![3],
+                0,
+                "Successfully added a new review on business, receipt was accounted for, a refund was netted from its weight",
+            ),
+        ] {
+            let env = mock_env(*reviewer, &[]);
+            let msg = HandleMsg::ReviewBusiness {
+                address: HumanAddr("mock-address".to_string()),
+                content: "content".to_string(),
+                rating: *rating,
+                title: "title".to_string(),
+                receipts: tx_ids
+                    .iter()
+                    .map(|tx_id| Receipt::Snip20 {
+                        token: None,
+                        tx_id: *tx_id,
+                        tx_page: 0,
+                        viewing_key: "vk".to_string(),
+                    })
+                    .collect(),
+                dimension_ratings: vec![],
+                item: None,
+            };
+            let res = handle(&mut deps, env, msg)?;
+            match from_binary(&res.data.unwrap())? {
+                HandleAnswer::ReviewBusiness { status } => assert_eq!(status, *expected_status),
+                _ => panic!("got wrong answer variant"),
+            }
+        }
+
+        let business =
+            get_business_by_address(&deps.storage, &HumanAddr("mock-address".to_string()))?
+                .unwrap();
+        assert_eq!(business.total_weight, Uint128(190));
+
+        // a refund that names the receipt in its memo, made after the review
+        deps.querier.transfers.push((
+            sscrt.clone(),
+            Tx {
+                memo: Some("1".to_string()),
+                ..transfer(6, "mock-address", "customer", 40)
+            },
+        ));
+        let msg = HandleMsg::ReverifyRefunds {
+            business_address: HumanAddr("mock-address".to_string()),
+            reviewer: None,
+            token: None,
+            tx_page: 0,
+            viewing_key: "vk".to_string(),
+        };
+        let env = mock_env("customer", &[]);
+        let res = handle(&mut deps, env, msg.clone())?;
+        match from_binary(&res.data.unwrap())? {
+            HandleAnswer::ReverifyRefunds { status } => {
+                assert_eq!(status, "Netted 1 refunds from the review's weight")
+            }
+            _ => panic!("got wrong answer variant"),
+        }
+
+        // the same refund is not netted twice, and neither is the same receipt
+        deps.querier.transfers.push((
+            sscrt.clone(),
+            Tx {
+                memo: Some("1".to_string()),
+                ..transfer(7, "mock-address", "customer", 40)
+            },
+        ));
+        let env = mock_env("customer", &[]);
+        let res = handle(&mut deps, env, msg)?;
+        match from_binary(&res.data.unwrap())? {
+            HandleAnswer::ReverifyRefunds { status } => {
+                assert_eq!(status, "Netted 0 refunds from the review's weight")
+            }
+            _ => panic!("got wrong answer variant"),
+        }
+
+        // anyone other than the reviewer, the owner of the business and the admin is refused
+        let msg = HandleMsg::ReverifyRefunds {
+            business_address: HumanAddr("mock-address".to_string()),
+            reviewer: Some(HumanAddr("customer".to_string())),
+            token: None,
+            tx_page: 0,
+            viewing_key: "vk".to_string(),
+        };
+        let env = mock_env("other-customer", &[]);
+        match handle(&mut deps, env, msg.clone()) {
+            Err(StdError::Unauthorized { .. }) => {}
+            _ => panic!("someone else reverified the refunds of a review"),
+        }
+
+        // the admin scans the business' history, where a refund matches the other receipt's amount
+        deps.querier
+            .transfers
+            .push((sscrt, transfer(8, "mock-address", "customer", 50)));
+        let env = mock_env("creator", &[]);
+        let res = handle(&mut deps, env, msg)?;
+        match from_binary(&res.data.unwrap())? {
+            HandleAnswer::ReverifyRefunds { status } => {
+                assert_eq!(status, "Netted 1 refunds from the review's weight")
+            }
+            _ => panic!("got wrong answer variant"),
+        }
+
+        let review = may_load_review(
+            &deps.storage,
+            &HumanAddr("mock-address".to_string()),
+            &HumanAddr("customer".to_string()),
+        )
+        .unwrap();
+        assert_eq!(review.weight, Uint128(60));
+
+        let business =
+            get_business_by_address(&deps.storage, &HumanAddr("mock-address".to_string()))?
+                .unwrap();
+        assert_eq!(business.total_weight, Uint128(100));
+        assert_eq!(
+            business.average_rating(&RatingScale::Stars)?,
+            "3".parse::<Rating>()?
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn receipt_age_is_limited() -> StdResult<()> {
+        let now = mock_env("anyone", &[]).block.time;
+        let sscrt = HumanAddr(SSCRT_ADDRESS.to_string());
+        let mut deps = mock_dependencies_with_transfers(vec![
+            (
+                sscrt.clone(),
+                Tx {
+                    block_time: Some(now - 500),
+                    ..transfer(1, "customer", "mock-address", 10)
+                },
+            ),
+            (sscrt.clone(), transfer(2, "customer", "mock-address", 10)),
+            (
+                sscrt.clone(),
+                Tx {
+                    block_time: Some(now - 50),
+                    ..transfer(3, "customer", "mock-address", 10)
+                },
+            ),
+        ]);
+
+        let env = mock_env("creator", &[]);
+        let msg = InitMsg {
+            max_receipt_age: Some(ReceiptAge::Seconds(100)),
+            allow_stale_edits: Some(false),
+            ..InitMsg::default()
+        };
+        init(&mut deps, env, msg)?;
+
+        let env = mock_env("anyone", &[]);
+        let msg = HandleMsg::RegisterBusiness {
+            name: "Starbucks".to_string(),
+            description: "a place to eat".to_string(),
+            address: HumanAddr("mock-address".to_string()),
+            category: None,
+        };
+        handle(&mut deps, env, msg)?;
+
+        let review_with_receipt = |tx_id: u64| HandleMsg::ReviewBusiness {
+            address: HumanAddr("mock-address".to_string()),
+            content: "content".to_string(),
+            rating: 5,
+            title: "title".to_string(),
+            receipts: vec![Receipt::Snip20 {
+                token: None,
+                tx_id,
+                tx_page: 0,
+                viewing_key: "vk".to_string(),
+            }],
+            dimension_ratings: vec![],
+            item: None,
+        };
+
+        let env = mock_env("customer", &[]);
+        let error = handle(&mut deps, env, review_with_receipt(1)).unwrap_err();
+        assert_eq!(
+            error,
+            StdError::generic_err(
+                "The receipt is older than the maximum receipt age of 100 seconds"
+            )
+        );
+
+        let env = mock_env("customer", &[]);
+        let error = handle(&mut deps, env, review_with_receipt(2)).unwrap_err();
+        assert_eq!(
+            error,
+            StdError::generic_err("The age of the receipt can't be verified")
+        );
+
+        let env = mock_env("customer", &[]);
+        handle(&mut deps, env, review_with_receipt(3))?;
+
+        // once the receipt gets old, the review can't be edited with it anymore
+        let mut env = mock_env("customer", &[]);
+        env.block.time += 100;
+        let error = handle(&mut deps, env, review_with_receipt(3)).unwrap_err();
+        assert_eq!(
+            error,
+            StdError::generic_err(
+                "All the receipts of this review are too old for it to be edited"
+            )
+        );
+
+        // migrated reviews don't know the time of their receipts, so their last update counts
+        for (reviewer, last_update_timestamp) in &[("recent", now - 50), ("stale", now - 500)] {
+            let legacy_review = LegacyReview {
+                title: "title".to_string(),
+                content: "content".to_string(),
+                rating: 5,
+                last_update_timestamp: *last_update_timestamp,
+                weight: Uint128(10),
+                tx_ids: vec![],
+            };
+            let reviewer = HumanAddr(reviewer.to_string());
+            create_review(
+                &mut deps.storage,
+                &HumanAddr("mock-address".to_string()),
+                &reviewer,
+                legacy_review.migrate(&reviewer, &sscrt),
+            )?;
+        }
+        let edit = HandleMsg::ReviewBusiness {
+            address: HumanAddr("mock-address".to_string()),
+            content: "content".to_string(),
+            rating: 4,
+            title: "title".to_string(),
+            receipts: vec![],
+            dimension_ratings: vec![],
+            item: None,
+        };
+        let env = mock_env("recent", &[]);
+        handle(&mut deps, env, edit.clone())?;
+        let env = mock_env("stale", &[]);
+        let error = handle(&mut deps, env, edit).unwrap_err();
+        assert_eq!(
+            error,
+            StdError::generic_err(
+                "All the receipts of this review are too old for it to be edited"
+            )
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn dust_receipts_are_rejected() -> StdResult<()> {
+        let mut deps = mock_dependencies(20, &[]);
+
+        let env = mock_env("creator", &[]);
+        let msg = InitMsg {
+            min_native_receipt_amount: Some(Uint128(50)),
+            ..InitMsg::default()
+        };
+        init(&mut deps, env, msg)?;
+
+        let env = mock_env("anyone", &[]);
+        let msg = HandleMsg::RegisterBusiness {
+            name: "Starbucks".to_string(),
+            description: "a place to eat".to_string(),
+            address: HumanAddr("mock-address".to_string()),
+            category: None,
+        };
+        handle(&mut deps, env, msg)?;
+
+        for amount in &[1, 60] {
+            let env = mock_env("customer", &coins(*amount, "uscrt"));
+            let msg = HandleMsg::PayBusiness {
+                address: HumanAddr("mock-address".to_string()),
+            };
+            handle(&mut deps, env, msg)?;
+        }
+
+        let review_with_receipt = |receipt_id: u64| HandleMsg::ReviewBusiness {
+            address: HumanAddr("mock-address".to_string()),
+            content: "content".to_string(),
+            rating: 0,
+            title: "title".to_string(),
+            receipts: vec![Receipt::Native { receipt_id }],
+            dimension_ratings: vec![],
+            item: None,
+        };
+
+        let env = mock_env("customer", &[]);
+        let error = handle(&mut deps, env, review_with_receipt(0)).unwrap_err();
+        assert_eq!(
+            error,
+            StdError::generic_err("Receipts must be of at least 50 uscrt")
+        );
+
+        let business =
+            get_business_by_address(&deps.storage, &HumanAddr("mock-address".to_string()))?
+                .unwrap();
+        assert_eq!(business.reviews_count, 0);
+
+        let env = mock_env("customer", &[]);
+        handle(&mut deps, env, review_with_receipt(1))?;
+
+        let business =
+            get_business_by_address(&deps.storage, &HumanAddr("mock-address".to_string()))?
+                .unwrap();
+        assert_eq!(business.reviews_count, 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn weight_strategy_is_applied_and_recomputed() -> StdResult<()> {
+        let sscrt = HumanAddr(SSCRT_ADDRESS.to_string());
+        let mut deps = mock_dependencies_with_transfers(vec![
+            (sscrt.clone(), transfer(1, "whale", "mock-address", 10000)),
+            (sscrt.clone(), transfer(2, "customer", "mock-address", 100)),
+        ]);
+
+        let env = mock_env("creator", &[]);
+        let msg = InitMsg {
+            weight_strategy: Some(WeightStrategy::SquareRoot),
+            ..InitMsg::default()
+        };
+        init(&mut deps, env, msg)?;
+
+        let env = mock_env("anyone", &[]);
+        let msg = HandleMsg::RegisterBusiness {
+            name: "Starbucks".to_string(),
+            description: "a place to eat".to_string(),
+            address: HumanAddr("mock-address".to_string()),
+            category: None,
+        };
+        handle(&mut deps, env, msg)?;
+
+        for (reviewer, tx_id, rating) in &[("whale", 1, 5), ("customer", 2, 0)] {
+            let env = mock_env(*reviewer, &[]);
+            let msg = HandleMsg::ReviewBusiness {
+                address: HumanAddr("mock-address".to_string()),
+                content: "content".to_string(),
+                rating: *rating,
+                title: "title".to_string(),
+                receipts: vec![Receipt::Snip20 {
+                    token: None,
+                    tx_id: *tx_id,
+                    tx_page: 0,
+                    viewing_key: "vk".to_string(),
+                }],
+                dimension_ratings: vec![],
+                item: None,
+            };
+            handle(&mut deps, env, msg)?;
+        }
+
+        // the weights are 100 and 10 instead of 10000 and 100
+        let business =
+            get_business_by_address(&deps.storage, &HumanAddr("mock-address".to_string()))?
+                .unwrap();
+        assert_eq!(business.total_weight, Uint128(110));
+        assert_eq!(business.total_raw_weight, Uint128(10100));
+        assert_eq!(
+            business.average_rating(&RatingScale::Stars)?,
+            "4.545455".parse::<Rating>()?
+        );
+
+        let env = mock_env("anyone", &[]);
+        let msg = HandleMsg::SetWeightStrategy {
+            strategy: WeightStrategy::Linear,
+        };
+        assert_eq!(handle(&mut deps, env, msg), Err(StdError::unauthorized()));
+
+        let env = mock_env("creator", &[]);
+        let msg = HandleMsg::SetWeightStrategy {
+            strategy: WeightStrategy::Capped {
+                max_share_percent: 101,
+            },
+        };
+        assert!(handle(&mut deps, env, msg).is_err());
+
+        let env = mock_env("creator", &[]);
+        let msg = HandleMsg::SetWeightStrategy {
+            strategy: WeightStrategy::Capped {
+                max_share_percent: 50,
+            },
+        };
+        handle(&mut deps, env, msg)?;
+
+        // the aggregates only change once the reviews are recomputed
+        let env = mock_env("creator", &[]);
+        let msg = HandleMsg::RecomputeWeights {
+            business_address: HumanAddr("mock-address".to_string()),
+            page: 0,
+            page_size: 10,
+        };
+        let res = handle(&mut deps, env, msg.clone())?;
+        match from_binary(&res.data.unwrap())? {
+            HandleAnswer::RecomputeWeights { status } => {
+                assert_eq!(status, "Recomputed the weights of 2 reviews")
+            }
+            _ => panic!("got wrong answer variant"),
+        }
+
+        // the whale's weight is capped at half of the business' total
+        let review = may_load_review(
+            &deps.storage,
+            &HumanAddr("mock-address".to_string()),
+            &HumanAddr("whale".to_string()),
+        )
+        .unwrap();
+        assert_eq!(review.weight, Uint128(10000));
+        assert_eq!(review.applied_weight, Uint128(5050));
+
+        let business =
+            get_business_by_address(&deps.storage, &HumanAddr("mock-address".to_string()))?
+                .unwrap();
+        assert_eq!(business.total_weight, Uint128(5150));
+        assert_eq!(
+            business.average_rating(&RatingScale::Stars)?,
+            "4.902913".parse::<Rating>()?
+        );
+
+        // recomputing again changes nothing
+        let env = mock_env("creator", &[]);
+        handle(&mut deps, env, msg)?;
+        assert_eq!(
+            get_business_by_address(&deps.storage, &HumanAddr("mock-address".to_string()))?
+                .unwrap(),
+            business
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn ratings_decay_with_time() -> StdResult<()> {
+        let sscrt = HumanAddr(SSCRT_ADDRESS.to_string());
+        let mut deps = mock_dependencies_with_transfers(vec![
+            (
+                sscrt.clone(),
+                transfer(1, "old-customer", "mock-address", 100),
+            ),
+            (
+                sscrt.clone(),
+                transfer(2, "new-customer", "mock-address", 100),
+            ),
+        ]);
+
+        let env = mock_env("creator", &[]);
+        let msg = InitMsg {
+            time_decay: Some(TimeDecay {
+                half_life_seconds: 100,
+            }),
+            ..InitMsg::default()
+        };
+        init(&mut deps, env, msg)?;
+
+        let env = mock_env("anyone", &[]);
+        let msg = HandleMsg::RegisterBusiness {
+            name: "Starbucks".to_string(),
+            description: "a place to eat".to_string(),
+            address: HumanAddr("mock-address".to_string()),
+            category: None,
+        };
+        handle(&mut deps, env, msg)?;
+
+        let start = mock_env("anyone", &[]).block.time;
+        for (reviewer, tx_id, rating, time) in &[
+            ("old-customer", 1, 5, start),
+            ("new-customer", 2, 0, start + 100),
+        ] {
+            let mut env = mock_env(*reviewer, &[]);
+            env.block.time = *time;
+            let msg = HandleMsg::ReviewBusiness {
+                address: HumanAddr("mock-address".to_string()),
+                content: "content".to_string(),
+                rating: *rating,
+                title: "title".to_string(),
+                receipts: vec![Receipt::Snip20 {
+                    token: None,
+                    tx_id: *tx_id,
+                    tx_page: 0,
+                    viewing_key: "vk".to_string(),
+                }],
+                dimension_ratings: vec![],
+                item: None,
+            };
+            handle(&mut deps, env, msg)?;
+        }
+
+        // the older review counts half as much as the newer one
+        let msg = QueryMsg::GetSingleBusiness {
+            address: HumanAddr("mock-address".to_string()),
+        };
+        match from_binary(&query(&deps, msg)?)? {
+            QueryAnswer::SingleBusiness { business, .. } => {
+                let business = business.unwrap();
+                assert_eq!(business.average_rating, "2.5".parse::<Rating>()?);
+                assert_eq!(
+                    business.decayed_average_rating,
+                    Some("1.666667".parse::<Rating>()?)
+                );
+            }
+            _ => panic!("got wrong answer variant"),
+        }
+
+        // editing the old review refreshes it
+        let mut env = mock_env("old-customer", &[]);
+        env.block.time = start + 200;
+        let msg = HandleMsg::ReviewBusiness {
+            address: HumanAddr("mock-address".to_string()),
+            content: "content".to_string(),
+            rating: 5,
+            title: "title".to_string(),
+            receipts: vec![],
+            dimension_ratings: vec![],
+            item: None,
+        };
+        handle(&mut deps, env, msg)?;
+
+        let business =
+            get_business_by_address(&deps.storage, &HumanAddr("mock-address".to_string()))?
+                .unwrap();
+        assert_eq!(
+            business.decayed_average_rating(&RatingScale::Stars)?,
+            Some("3.333333".parse::<Rating>()?)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn large_amounts_decay_without_overflowing() -> StdResult<()> {
+        // amounts of a token with 18 decimals
+        let token = 1_000_000_000_000_000_000;
+        let sscrt = HumanAddr(SSCRT_ADDRESS.to_string());
+        let mut deps = mock_dependencies_with_transfers(vec![
+            (
+                sscrt.clone(),
+                transfer(1, "old-customer", "mock-address", 2_000_000 * token),
+            ),
+            (
+                sscrt.clone(),
+                transfer(2, "new-customer", "mock-address", 1_000_000 * token),
+            ),
+        ]);
+
+        let env = mock_env("creator", &[]);
+        let msg = InitMsg {
+            time_decay: Some(TimeDecay {
+                half_life_seconds: 100,
+            }),
+            ..InitMsg::default()
+        };
+        init(&mut deps, env, msg)?;
+
+        let env = mock_env("anyone", &[]);
+        let msg = HandleMsg::RegisterBusiness {
+            name: "Starbucks".to_string(),
+            description: "a place to eat".to_string(),
+            address: HumanAddr("mock-address".to_string()),
+            category: None,
+        };
+        handle(&mut deps, env, msg)?;
+
+        let start = mock_env("anyone", &[]).block.time;
+        for (reviewer, tx_id, rating, time) in &[
+            ("old-customer", 1, 5, start),
+            ("new-customer", 2, 0, start + 100),
+        ] {
+            let mut env = mock_env(*reviewer, &[]);
+            env.block.time = *time;
+            let msg = HandleMsg::ReviewBusiness {
+                address: HumanAddr("mock-address".to_string()),
+                content: "content".to_string(),
+                rating: *rating,
+                title: "title".to_string(),
+                receipts: vec![Receipt::Snip20 {
+                    token: None,
+                    tx_id: *tx_id,
+                    tx_page: 0,
+                    viewing_key: "vk".to_string(),
+                }],
+                dimension_ratings: vec![],
+                item: None,
+            };
+            handle(&mut deps, env, msg)?;
+        }
+
+        // the older review counts half as much, which makes it weigh as much as the newer one
+        let msg = QueryMsg::GetSingleBusiness {
+            address: HumanAddr("mock-address".to_string()),
+        };
+        match from_binary(&query(&deps, msg)?)? {
+            QueryAnswer::SingleBusiness { business, .. } => {
+                let business = business.unwrap();
+                assert_eq!(business.average_rating, "3.333333".parse::<Rating>()?);
+                assert_eq!(
+                    business.decayed_average_rating,
+                    Some("2.5".parse::<Rating>()?)
+                );
+            }
+            _ => panic!("got wrong answer variant"),
+        }
+
+        let msg = QueryMsg::GetBusinesses {
+            page: None,
+            page_size: 10,
+            include_closed: false,
+        };
+        match from_binary(&query(&deps, msg)?)? {
+            QueryAnswer::Businesses { businesses, .. } => assert_eq!(
+                businesses[0].decayed_average_rating,
+                Some("2.5".parse::<Rating>()?)
+            ),
+            _ => panic!("got wrong answer variant"),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn average_rating_does_not_drift() -> StdResult<()> {
+        let sscrt = HumanAddr(SSCRT_ADDRESS.to_string());
+        let mut deps = mock_dependencies_with_transfers(vec![
+            (sscrt.clone(), transfer(1, "first", "mock-address", 1)),
+            (sscrt.clone(), transfer(2, "second", "mock-address", 1)),
+            (sscrt.clone(), transfer(3, "third", "mock-address", 1)),
+        ]);
+
+        let env = mock_env("creator", &[]);
+        init(&mut deps, env, InitMsg::default())?;
+
+        let env = mock_env("anyone", &[]);
+        let msg = HandleMsg::RegisterBusiness {
+            name: "Starbucks".to_string(),
+            description: "a place to eat".to_string(),
+            address: HumanAddr("mock-address".to_string()),
+            category: None,
+        };
+        handle(&mut deps, env, msg)?;
+
+        let review = |rating: u8, receipts: Vec<Receipt>| HandleMsg::ReviewBusiness {
+            address: HumanAddr("mock-address".to_string()),
+            content: "content".to_string(),
+            rating,
+            title: "title".to_string(),
+            receipts,
+            dimension_ratings: vec![],
+            item: None,
+        };
+        for (reviewer, tx_id, rating) in &[("first", 1, 5), ("second", 2, 4), ("third", 3, 4)] {
+            let env = mock_env(*reviewer, &[]);
+            let receipts = vec![Receipt::Snip20 {
+                token: None,
+                tx_id: *tx_id,
+                tx_page: 0,
+                viewing_key: "vk".to_string(),
+            }];
+            handle(&mut deps, env, review(*rating, receipts))?;
+        }
+
+        // every edit used to truncate the stored average
+        for rating in &[0, 3, 1, 2, 5] {
+            let env = mock_env("first", &[]);
+            handle(&mut deps, env, review(*rating, vec![]))?;
+        }
+
+        let msg = QueryMsg::GetSingleBusiness {
+            address: HumanAddr("mock-address".to_string()),
+        };
+        let res = String::from_utf8(query(&deps, msg)?.0).unwrap();
+        assert!(res.contains(r#""average_rating":"4.333333""#));
+
+        let env = mock_env("second", &[]);
+        handle(&mut deps, env, review(5, vec![]))?;
+
+        let business =
+            get_business_by_address(&deps.storage, &HumanAddr("mock-address".to_string()))?
+                .unwrap();
+        // ratings are kept in hundredths of the highest rating
+        assert_eq!(business.rating_sum, Uint128(280));
+        // 14 / 3 is rounded up to the nearest millionth
+        assert_eq!(
+            business.average_rating(&RatingScale::Stars)?.to_string(),
+            "4.666667"
+        );
+
+        // rounding doesn't overflow with denominators close to the limit
+        assert_eq!(
+            Rating::from_ratio(u128::MAX / 1_000_000, u128::MAX)?.to_string(),
+            "0.000001"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn business_aggregates_are_rebuilt() -> StdResult<()> {
+        let sscrt = HumanAddr(SSCRT_ADDRESS.to_string());
+        let mut deps = mock_dependencies_with_transfers(vec![
+            (sscrt.clone(), transfer(1, "first", "mock-address", 10)),
+            (sscrt.clone(), transfer(2, "second", "mock-address", 20)),
+            (sscrt.clone(), transfer(3, "third", "mock-address", 30)),
+        ]);
+
+        let env = mock_env("creator", &[]);
+        init(&mut deps, env, InitMsg::default())?;
+
+        let env = mock_env("anyone", &[]);
+        let msg = HandleMsg::RegisterBusiness {
+            name: "Starbucks".to_string(),
+            description: "a place to eat".to_string(),
+            address: HumanAddr("mock-address".to_string()),
+            category: None,
+        };
+        handle(&mut deps, env, msg)?;
+
+        for (reviewer, tx_id, rating) in &[("first", 1, 5), ("second", 2, 4), ("third", 3, 1)] {
+            let env = mock_env(*reviewer, &[]);
+            let msg = HandleMsg::ReviewBusiness {
+                address: HumanAddr("mock-address".to_string()),
+                content: "content".to_string(),
+                rating: *rating,
+                title: "title".to_string(),
+                receipts: vec![Receipt::Snip20 {
+                    token: None,
+                    tx_id: *tx_id,
+                    tx_page: 0,
+                    viewing_key: "vk".to_string(),
+                }],
+                dimension_ratings: vec![],
+                item: None,
+            };
+            handle(&mut deps, env, msg)?;
+        }
+
+        assert!(check_aggregates(&deps, "mock-address")?.consistent);
+
+        let mut business =
+            get_business_by_address(&deps.storage, &HumanAddr("mock-address".to_string()))?
+                .unwrap();
+        let expected = business.aggregates();
+        business.rating_sum = Uint128(7);
+        business.reviews_count = 4;
+        update_business(&mut deps.storage, business)?;
+
+        let drift = check_aggregates(&deps, "mock-address")?;
+        assert!(!drift.consistent);
+        assert_eq!(drift.stored.rating_sum, Uint128(7));
+        assert_eq!(drift.rebuilt, expected);
+
+        let msg = HandleMsg::RecomputeBusiness {
+            business_address: HumanAddr("mock-address".to_string()),
+            page_size: 2,
+        };
+        let env = mock_env("anyone", &[]);
+        assert_eq!(
+            handle(&mut deps, env, msg.clone()),
+            Err(StdError::unauthorized())
+        );
+
+        let env = mock_env("creator", &[]);
+        let res = handle(&mut deps, env, msg.clone())?;
+        match from_binary(&res.data.unwrap())? {
+            HandleAnswer::RecomputeBusiness { status, drift } => {
+                assert_eq!(
+                    status,
+                    "Rebuilt page 0 of the business' aggregates, call again to continue"
+                );
+                assert_eq!(drift, None);
+            }
+            _ => panic!("got wrong answer variant"),
+        }
+
+        // the reviews can't change while the rebuild is in progress
+        let env = mock_env("third", &[]);
+        let msg_edit = HandleMsg::ReviewBusiness {
+            address: HumanAddr("mock-address".to_string()),
+            content: "content".to_string(),
+            rating: 2,
+            title: "title".to_string(),
+            receipts: vec![],
+            dimension_ratings: vec![],
+            item: None,
+        };
+        match handle(&mut deps, env, msg_edit) {
+            Err(StdError::GenericErr { msg, .. }) => assert_eq!(
+                msg,
+                "The aggregates of the business are being rebuilt, its reviews can change again once the rebuild is done"
+            ),
+            _ => panic!("a review changed while its business was rebuilt"),
+        }
+
+        let env = mock_env("creator", &[]);
+        let res = handle(&mut deps, env, msg)?;
+        match from_binary(&res.data.unwrap())? {
+            HandleAnswer::RecomputeBusiness { status, drift } => {
+                assert_eq!(status, "Rebuilt the business' aggregates from 3 reviews");
+                let drift = drift.unwrap();
+                assert!(!drift.consistent);
+                assert_eq!(drift.stored.reviews_count, 4);
+                assert_eq!(drift.rebuilt, expected);
+            }
+            _ => panic!("got wrong answer variant"),
+        }
+
+        assert!(check_aggregates(&deps, "mock-address")?.consistent);
+
+        Ok(())
+    }
+
+    #[test]
+    fn categories_choose_rating_scales() -> StdResult<()> {
+        let sscrt = HumanAddr(SSCRT_ADDRESS.to_string());
+        let mut deps = mock_dependencies_with_transfers(vec![(
+            sscrt.clone(),
+            transfer(1, "customer", "mock-address", 100),
+        )]);
+
+        let env = mock_env("creator", &[]);
+        init(&mut deps, env, InitMsg::default())?;
+
+        let msg = HandleMsg::SetCategory {
+            name: "bicycles".to_string(),
+            rating_scale: Some(RatingScale::HalfStars),
+            dimensions: vec![],
+        };
+        let env = mock_env("anyone", &[]);
+        assert_eq!(
+            handle(&mut deps, env, msg.clone()),
+            Err(StdError::unauthorized())
+        );
+        let env = mock_env("creator", &[]);
+        handle(&mut deps, env, msg)?;
+
+        for name in &["", "a category that is too long"] {
+            let msg = HandleMsg::SetCategory {
+                name: name.to_string(),
+                rating_scale: None,
+                dimensions: vec![],
+            };
+            let env = mock_env("creator", &[]);
+            match handle(&mut deps, env, msg) {
+                Err(StdError::GenericErr { msg, .. }) => {
+                    assert_eq!(msg, "Name length must be between 1 and 20")
+                }
+                _ => panic!("a category was named {:?}", name),
+            }
+        }
+
+        let env = mock_env("anyone", &[]);
+        let msg = HandleMsg::RegisterBusiness {
+            name: "Bikes".to_string(),
+            description: "a place to ride".to_string(),
+            address: HumanAddr("mock-address".to_string()),
+            category: Some("cars".to_string()),
+        };
+        match handle(&mut deps, env, msg) {
+            Err(StdError::GenericErr { msg, .. }) => {
+                assert_eq!(msg, "There is no category named cars")
+            }
+            _ => panic!("registered a business in a category that doesn't exist"),
+        }
+
+        let env = mock_env("anyone", &[]);
+        let msg = HandleMsg::RegisterBusiness {
+            name: "Bikes".to_string(),
+            description: "a place to ride".to_string(),
+            address: HumanAddr("mock-address".to_string()),
+            category: Some("bicycles".to_string()),
+        };
+        handle(&mut deps, env, msg)?;
+
+        let review = |rating: u8| HandleMsg::ReviewBusiness {
+            address: HumanAddr("mock-address".to_string()),
+            content: "content".to_string(),
+            rating,
+            title: "title".to_string(),
+            receipts: vec![Receipt::Snip20 {
+                token: None,
+                tx_id: 1,
+                tx_page: 0,
+                viewing_key: "vk".to_string(),
+            }],
+            dimension_ratings: vec![],
+            item: None,
+        };
+        let env = mock_env("customer", &[]);
+        match handle(&mut deps, env, review(11)) {
+            Err(StdError::GenericErr { msg, .. }) => {
+                assert_eq!(msg, "ratings must be between 0 and 10 half stars")
+            }
+            _ => panic!("accepted a rating that is out of the scale"),
+        }
+
+        // 9 half stars
+        let env = mock_env("customer", &[]);
+        handle(&mut deps, env, review(9))?;
+
+        let msg = QueryMsg::GetSingleBusiness {
+            address: HumanAddr("mock-address".to_string()),
+        };
+        match from_binary(&query(&deps, msg.clone())?)? {
+            QueryAnswer::SingleBusiness { business, .. } => {
+                let business = business.unwrap();
+                assert_eq!(business.rating_scale, RatingScale::HalfStars);
+                assert_eq!(business.average_rating, "4.5".parse::<Rating>()?);
+            }
+            _ => panic!("got wrong answer variant"),
+        }
+
+        let reviews = QueryMsg::GetReviewsOnBusiness {
+            business_address: HumanAddr("mock-address".to_string()),
+            page: None,
+            page_size: 10,
+        };
+        match from_binary(&query(&deps, reviews)?)? {
+            QueryAnswer::Reviews {
+                reviews,
+                rating_scale,
+                ..
+            } => {
+                assert_eq!(rating_scale, RatingScale::HalfStars);
+                assert_eq!(reviews[0].rating, "4.5".parse::<Rating>()?);
+            }
+            _ => panic!("got wrong answer variant"),
+        }
+
+        // the same normalized ratings are published on the new scale
+        let env = mock_env("creator", &[]);
+        let msg_set = HandleMsg::SetCategory {
+            name: "bicycles".to_string(),
+            rating_scale: Some(RatingScale::TenPoints),
+            dimensions: vec![],
+        };
+        handle(&mut deps, env, msg_set)?;
+
+        match from_binary(&query(&deps, msg)?)? {
+            QueryAnswer::SingleBusiness { business, .. } => {
+                let business = business.unwrap();
+                assert_eq!(business.rating_scale, RatingScale::TenPoints);
+                assert_eq!(business.average_rating, "9".parse::<Rating>()?);
+            }
+            _ => panic!("got wrong answer variant"),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn dimensions_are_rated_separately() -> StdResult<()> {
+        let sscrt = HumanAddr(SSCRT_ADDRESS.to_string());
+        let mut deps = mock_dependencies_with_transfers(vec![
+            (sscrt.clone(), transfer(1, "first", "mock-address", 100)),
+            (sscrt.clone(), transfer(2, "second", "mock-address", 300)),
+        ]);
+
+        let env = mock_env("creator", &[]);
+        init(&mut deps, env, InitMsg::default())?;
+
+        let env = mock_env("creator", &[]);
+        let msg = HandleMsg::SetCategory {
+            name: "restaurants".to_string(),
+            rating_scale: None,
+            dimensions: vec!["service".to_string(), "price".to_string()],
+        };
+        handle(&mut deps, env, msg)?;
+
+        let env = mock_env("anyone", &[]);
+        let msg = HandleMsg::RegisterBusiness {
+            name: "Starbucks".to_string(),
+            description: "a place to eat".to_string(),
+            address: HumanAddr("mock-address".to_string()),
+            category: Some("restaurants".to_string()),
+        };
+        handle(&mut deps, env, msg)?;
+
+        let review =
+            |tx_id: u64, rating: u8, dimension_ratings: &[(&str, u8)]| HandleMsg::ReviewBusiness {
+                address: HumanAddr("mock-address".to_string()),
+                content: "content".to_string(),
+                rating,
+                title: "title".to_string(),
+                receipts: vec![Receipt::Snip20 {
+                    token: None,
+                    tx_id,
+                    tx_page: 0,
+                    viewing_key: "vk".to_string(),
+                }],
+                dimension_ratings: dimension_ratings
+                    .iter()
+                    .map(|(dimension, rating)| DimensionRating {
+                        dimension: dimension.to_string(),
+                        rating: *rating,
+                    })
+                    .collect(),
+                item: None,
+            };
+
+        for dimension_ratings in &[
+            vec![("price", 5)],
+            vec![("price", 5), ("price", 4)],
+            vec![("price", 5), ("service", 4), ("ambience", 3)],
+        ] {
+            let env = mock_env("first", &[]);
+            match handle(&mut deps, env, review(1, 5, dimension_ratings)) {
+                Err(StdError::GenericErr { msg, .. }) => assert_eq!(
+                    msg,
+                    "A rating must be given for each of the dimensions: service, price"
+                ),
+                _ => panic!("accepted a review without a rating for each dimension"),
+            }
+        }
+
+        let env = mock_env("first", &[]);
+        handle(
+            &mut deps,
+            env,
+            review(1, 5, &[("price", 5), ("service", 4)]),
+        )?;
+        let env = mock_env("second", &[]);
+        handle(
+            &mut deps,
+            env,
+            review(2, 3, &[("service", 4), ("price", 1)]),
+        )?;
+
+        let msg = QueryMsg::GetSingleBusiness {
+            address: HumanAddr("mock-address".to_string()),
+        };
+        match from_binary(&query(&deps, msg)?)? {
+            QueryAnswer::SingleBusiness { business, .. } => {
+                let business = business.unwrap();
+                assert_eq!(business.average_rating, "3.5".parse::<Rating>()?);
+                assert_eq!(
+                    business.dimension_ratings,
+                    vec![
+                        DisplayedDimensionRating {
+                            dimension: "price".to_string(),
+                            rating: "2".parse::<Rating>()?,
+                        },
+                        DisplayedDimensionRating {
+                            dimension: "service".to_string(),
+                            rating: "4".parse::<Rating>()?,
+                        },
+                    ]
+                );
+            }
+            _ => panic!("got wrong answer variant"),
+        }
+
+        assert!(check_aggregates(&deps, "mock-address")?.consistent);
+
+        Ok(())
+    }
+
+    #[test]
+    fn items_are_reviewed_separately() -> StdResult<()> {
+        let sscrt = HumanAddr(SSCRT_ADDRESS.to_string());
+        let mut deps = mock_dependencies_with_transfers(vec![
+            (sscrt.clone(), transfer(1, "first", "mock-address", 100)),
+            (sscrt.clone(), transfer(2, "second", "mock-address", 300)),
+        ]);
+
+        let env = mock_env("creator", &[]);
+        init(&mut deps, env, InitMsg::default())?;
+
+        let env = mock_env("anyone", &[]);
+        let msg = HandleMsg::RegisterBusiness {
+            name: "Starbucks".to_string(),
+            description: "a place to eat".to_string(),
+            address: HumanAddr("mock-address".to_string()),
+            category: None,
+        };
+        handle(&mut deps, env, msg)?;
+
+        let register_item = HandleMsg::RegisterItem {
+            business_address: HumanAddr("mock-address".to_string()),
+            sku: "latte".to_string(),
+            name: "Caffe Latte".to_string(),
+        };
+        let env = mock_env("mock-address", &[]);
+        match handle(&mut deps, env, register_item.clone()) {
+            Err(StdError::GenericErr { msg, .. }) => assert_eq!(
+                msg,
+                "Only the owner of a claimed business can manage its items"
+            ),
+            _ => panic!("registered an item on an unclaimed business"),
+        }
+
+        let claim = HandleMsg::ClaimBusiness {
+            address: HumanAddr("mock-address".to_string()),
+            owner: Some(HumanAddr("owner".to_string())),
+        };
+        let env = mock_env("anyone", &[]);
+        match handle(&mut deps, env, claim.clone()) {
+            Err(StdError::GenericErr { msg, .. }) => {
+                assert_eq!(msg, "A business can only be claimed from its own address")
+            }
+            _ => panic!("a business was claimed by another address"),
+        }
+        let env = mock_env("mock-address", &[]);
+        handle(&mut deps, env, claim)?;
+
+        let env = mock_env("owner", &[]);
+        handle(&mut deps, env, register_item)?;
+
+        let review = |tx_id: u64, rating: u8, item: Option<&str>| HandleMsg::ReviewBusiness {
+            address: HumanAddr("mock-address".to_string()),
+            content: "content".to_string(),
+            rating,
+            title: "title".to_string(),
+            receipts: vec![Receipt::Snip20 {
+                token: None,
+                tx_id,
+                tx_page: 0,
+                viewing_key: "vk".to_string(),
+            }],
+            dimension_ratings: vec![],
+            item: item.map(String::from),
+        };
+
+        let env = mock_env("first", &[]);
+        match handle(&mut deps, env, review(1, 5, Some("espresso"))) {
+            Err(StdError::GenericErr { msg, .. }) => assert_eq!(
+                msg,
+                "There is no item with sku espresso in the business' catalog"
+            ),
+            _ => panic!("reviewed an item that isn't in the catalog"),
+        }
+
+        let env = mock_env("first", &[]);
+        handle(&mut deps, env, review(1, 5, Some("latte")))?;
+        let env = mock_env("second", &[]);
+        handle(&mut deps, env, review(2, 1, None))?;
+
+        let items = QueryMsg::GetItems {
+            business_address: HumanAddr("mock-address".to_string()),
+            page: None,
+            page_size: 10,
+        };
+        match from_binary(&query(&deps, items.clone())?)? {
+            QueryAnswer::Items { items, total, .. } => {
+                assert_eq!(total, 1);
+                assert_eq!(items[0].name, "Caffe Latte");
+                assert_eq!(items[0].reviews_count, 1);
+                assert_eq!(items[0].average_rating, "5".parse::<Rating>()?);
+            }
+            _ => panic!("got wrong answer variant"),
+        }
+
+        // item reviews still count towards the business
+        let msg = QueryMsg::GetSingleBusiness {
+            address: HumanAddr("mock-address".to_string()),
+        };
+        match from_binary(&query(&deps, msg)?)? {
+            QueryAnswer::SingleBusiness { business, .. } => {
+                let business = business.unwrap();
+                assert!(business.claimed);
+                assert_eq!(business.reviews_count, 2);
+                assert_eq!(business.average_rating, "2".parse::<Rating>()?);
+            }
+            _ => panic!("got wrong answer variant"),
+        }
+
+        // moving a review to another item takes it off the previous one
+        let env = mock_env("first", &[]);
+        handle(&mut deps, env, review(1, 4, None))?;
+        match from_binary(&query(&deps, items)?)? {
+            QueryAnswer::Items { items, .. } => {
+                assert_eq!(items[0].reviews_count, 0);
+                assert_eq!(items[0].average_rating, Rating::zero());
+            }
+            _ => panic!("got wrong answer variant"),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn brands_aggregate_their_branches() -> StdResult<()> {
+        let sscrt = HumanAddr(SSCRT_ADDRESS.to_string());
+        let mut deps = mock_dependencies_with_transfers(vec![
+            (sscrt.clone(), transfer(1, "first", "downtown", 100)),
+            (sscrt.clone(), transfer(2, "second", "uptown", 300)),
+            (sscrt.clone(), transfer(3, "third", "uptown", 100)),
+        ]);
+
+        let env = mock_env("creator", &[]);
+        init(&mut deps, env, InitMsg::default())?;
+
+        // a name whose length doesn't fit in a byte is still too long
+        let env = mock_env("creator", &[]);
+        let msg = HandleMsg::RegisterBrand {
+            name: "a".repeat(260),
+            owner: HumanAddr("chain".to_string()),
+            rating_scale: None,
+        };
+        match handle(&mut deps, env, msg) {
+            Err(StdError::GenericErr { msg, .. }) => {
+                assert_eq!(msg, "Name length must be between 1 and 20")
+            }
+            _ => panic!("a brand was registered with a name that is too long"),
+        }
+
+        for branch in &["downtown", "uptown"] {
+            let env = mock_env("anyone", &[]);
+            let msg = HandleMsg::RegisterBusiness {
+                name: "Starbucks".to_string(),
+                description: "a place to eat".to_string(),
+                address: HumanAddr(branch.to_string()),
+                category: None,
+            };
+            handle(&mut deps, env, msg)?;
+
+            let env = mock_env(*branch, &[]);
+            let msg = HandleMsg::ClaimBusiness {
+                address: HumanAddr(branch.to_string()),
+                owner: Some(HumanAddr("chain".to_string())),
+            };
+            handle(&mut deps, env, msg)?;
+        }
+
+        let review = |address: &str, tx_id: u64, rating: u8| HandleMsg::ReviewBusiness {
+            address: HumanAddr(address.to_string()),
+            content: "content".to_string(),
+            rating,
+            title: "title".to_string(),
+            receipts: vec![Receipt::Snip20 {
+                token: None,
+                tx_id,
+                tx_page: 0,
+                viewing_key: "vk".to_string(),
+            }],
+            dimension_ratings: vec![],
+            item: None,
+        };
+        // the downtown branch is reviewed before it joins the brand
+        let env = mock_env("first", &[]);
+        handle(&mut deps, env, review("downtown", 1, 5))?;
+
+        // brand names are registered by the registrars, for their owners
+        let msg = HandleMsg::RegisterBrand {
+            name: "Starbucks".to_string(),
+            owner: HumanAddr("chain".to_string()),
+            rating_scale: None,
+        };
+        let env = mock_env("chain", &[]);
+        match handle(&mut deps, env, msg.clone()) {
+            Err(StdError::GenericErr { msg, .. }) => {
+                assert_eq!(msg, "Only the registrars can register brands")
+            }
+            _ => panic!("a brand was registered by someone who isn't a registrar"),
+        }
+        let env = mock_env("creator", &[]);
+        handle(&mut deps, env, msg)?;
+
+        let add_branch = |branch: &str| HandleMsg::AddBranch {
+            brand: "Starbucks".to_string(),
+            business_address: HumanAddr(branch.to_string()),
+        };
+        let env = mock_env("someone-else", &[]);
+        match handle(&mut deps, env, add_branch("downtown")) {
+            Err(StdError::GenericErr { msg, .. }) => assert_eq!(
+                msg,
+                "Only the owner of both the brand and the claimed business can add it as a branch"
+            ),
+            _ => panic!("a branch was added by someone else"),
+        }
+        for branch in &["downtown", "uptown"] {
+            let env = mock_env("chain", &[]);
+            handle(&mut deps, env, add_branch(branch))?;
+        }
+
+        let env = mock_env("second", &[]);
+        handle(&mut deps, env, review("uptown", 2, 1))?;
+        // receipts are still verified per branch
+        let env = mock_env("third", &[]);
+        assert!(handle(&mut deps, env, review("downtown", 3, 5)).is_err());
+
+        let brand = QueryMsg::GetBrand {
+            name: "Starbucks".to_string(),
+        };
+        match from_binary(&query(&deps, brand.clone())?)? {
+            QueryAnswer::Brand { brand } => {
+                let brand = brand.unwrap();
+                assert_eq!(brand.branches_count, 2);
+                assert_eq!(brand.reviews_count, 2);
+                assert_eq!(brand.average_rating, "2".parse::<Rating>()?);
+            }
+            _ => panic!("got wrong answer variant"),
+        }
+
+        let msg = QueryMsg::GetBranches {
+            brand: "Starbucks".to_string(),
+            page: None,
+            page_size: 10,
+        };
+        match from_binary(&query(&deps, msg)?)? {
+            QueryAnswer::Branches { branches, total } => {
+                assert_eq!(total, 2);
+                assert_eq!(branches[0].average_rating, "5".parse::<Rating>()?);
+                assert_eq!(branches[1].average_rating, "1".parse::<Rating>()?);
+                assert_eq!(branches[1].brand, Some("Starbucks".to_string()));
+            }
+            _ => panic!("got wrong answer variant"),
+        }
+
+        let env = mock_env("chain", &[]);
+        let msg = HandleMsg::RemoveBranch {
+            business_address: HumanAddr("uptown".to_string()),
+        };
+        handle(&mut deps, env, msg)?;
+        match from_binary(&query(&deps, brand)?)? {
+            QueryAnswer::Brand { brand } => {
+                let brand = brand.unwrap();
+                assert_eq!(brand.branches_count, 1);
+                assert_eq!(brand.average_rating, "5".parse::<Rating>()?);
+            }
+            _ => panic!("got wrong answer variant"),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn closed_businesses_are_hidden_and_read_only() -> StdResult<()> {
+        let sscrt = HumanAddr(SSCRT_ADDRESS.to_string());
+        let mut deps = mock_dependencies_with_transfers(vec![
+            (sscrt.clone(), transfer(1, "first", "closing", 100)),
+            (sscrt.clone(), transfer(2, "second", "closing", 100)),
+        ]);
+
+        let env = mock_env("creator", &[]);
+        init(&mut deps, env, InitMsg::default())?;
+
+        for address in &["open", "closing"] {
+            let env = mock_env("anyone", &[]);
+            let msg = HandleMsg::RegisterBusiness {
+                name: address.to_string(),
+                description: "a place to eat".to_string(),
+                address: HumanAddr(address.to_string()),
+                category: None,
+            };
+            handle(&mut deps, env, msg)?;
+        }
+
+        let review = |tx_id: u64| HandleMsg::ReviewBusiness {
+            address: HumanAddr("closing".to_string()),
+            content: "content".to_string(),
+            rating: 4,
+            title: "title".to_string(),
+            receipts: vec![Receipt::Snip20 {
+                token: None,
+                tx_id,
+                tx_page: 0,
+                viewing_key: "vk".to_string(),
+            }],
+            dimension_ratings: vec![],
+            item: None,
+        };
+        let env = mock_env("first", &[]);
+        handle(&mut deps, env, review(1))?;
+
+        let deactivate = HandleMsg::DeactivateBusiness {
+            address: HumanAddr("closing".to_string()),
+        };
+        let env = mock_env("anyone", &[]);
+        let error = handle(&mut deps, env, deactivate.clone()).unwrap_err();
+        assert_eq!(error, StdError::unauthorized());
+        let env = mock_env("creator", &[]);
+        handle(&mut deps, env, deactivate)?;
+
+        // neither new reviews nor edits are accepted
+        for (reviewer, tx_id) in &[("second", 2), ("first", 1)] {
+            let env = mock_env(*reviewer, &[]);
+            match handle(&mut deps, env, review(*tx_id)) {
+                Err(StdError::GenericErr { msg, .. }) => {
+                    assert_eq!(msg, "The business is closed and can no longer be reviewed")
+                }
+                _ => panic!("a closed business was reviewed"),
+            }
+        }
+
+        let listing = |include_closed: bool| QueryMsg::GetBusinesses {
+            page: None,
+            page_size: 10,
+            include_closed,
+        };
+        match from_binary(&query(&deps, listing(false))?)? {
+            QueryAnswer::Businesses { businesses, total } => {
+                assert_eq!(total, 1);
+                assert_eq!(businesses[0].address.as_str(), "open");
+            }
+            _ => panic!("got wrong answer variant"),
+        }
+        match from_binary(&query(&deps, listing(true))?)? {
+            QueryAnswer::Businesses { businesses, total } => {
+                assert_eq!(total, 2);
+                assert_eq!(businesses[1].status, BusinessStatus::Closed);
+                assert_eq!(businesses[1].reviews_count, 1);
+            }
+            _ => panic!("got wrong answer variant"),
+        }
+
+        let env = mock_env("creator", &[]);
+        let msg = HandleMsg::ReactivateBusiness {
+            address: HumanAddr("closing".to_string()),
+        };
+        handle(&mut deps, env, msg)?;
+        let env = mock_env("second", &[]);
+        handle(&mut deps, env, review(2))?;
+        match from_binary(&query(&deps, listing(false))?)? {
+            QueryAnswer::Businesses { total, .. } => assert_eq!(total, 2),
+            _ => panic!("got wrong answer variant"),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn migrate_businesses_from_legacy_layout() -> StdResult<()> {
+        let mut deps = mock_dependencies(20, &[]);
+
+        let env = mock_env("creator", &[]);
+        init(&mut deps, env, InitMsg::default())?;
+
+        let mut legacy_businesses = CashMap::init(b"businesses", &mut deps.storage);
+        for (address, average_rating) in &[("first", 4500), ("second", 2000), ("third", 0)] {
+            legacy_businesses.insert(
+                address.as_bytes(),
+                LegacyBusiness {
+                    name: address.to_string(),
+                    description: "a place to eat".to_string(),
+                    address: HumanAddr(address.to_string()),
+                    average_rating: Uint128(*average_rating),
+                    reviews_count: 2,
+                    total_weight: Uint128(40),
+                },
+            )?;
+        }
+
+        // the address of a business that wasn't migrated yet can't be registered over it
+        let env = mock_env("anyone", &[]);
+        let msg = HandleMsg::RegisterBusiness {
+            name: "Impostor".to_string(),
+            description: "a place to eat".to_string(),
+            address: HumanAddr("second".to_string()),
+            category: None,
+        };
+        match handle(&mut deps, env, msg) {
+            Err(StdError::GenericErr { msg, .. }) => {
+                assert_eq!(msg, "A business is already registered on that address")
+            }
+            _ => panic!("a legacy business was registered over"),
+        }
+
+        let msg = HandleMsg::MigrateBusinesses { limit: 2 };
+        let env = mock_env("anyone", &[]);
+        let error = handle(&mut deps, env, msg.clone()).unwrap_err();
+        assert_eq!(error, StdError::unauthorized());
+
+        for expected in &[
+            "Migrated 2 businesses, 1 are left",
+            "Migrated 1 businesses, 0 are left",
+            "Migrated 0 businesses, 0 are left",
+        ] {
+            let env = mock_env("creator", &[]);
+            match from_binary(&handle(&mut deps, env, msg.clone())?.data.unwrap())? {
+                HandleAnswer::MigrateBusinesses { status } => assert_eq!(status, *expected),
+                _ => panic!("got wrong answer variant"),
+            }
+        }
+
+        let business =
+            get_business_by_address(&deps.storage, &HumanAddr("first".to_string()))?.unwrap();
+        assert_eq!(business.status, BusinessStatus::Active);
+        assert_eq!(business.rating_sum, Uint128(40 * 90));
+        assert_eq!(business.vouchers_weight, Uint128::zero());
+        assert_eq!(business.attestation_key, None);
+        assert_eq!(
+            business.average_rating(&RatingScale::Stars)?,
+            "4.5".parse::<Rating>()?
+        );
+
+        let msg = QueryMsg::GetBusinesses {
+            page: None,
+            page_size: 10,
+            include_closed: false,
+        };
+        match from_binary(&query(&deps, msg)?)? {
+            QueryAnswer::Businesses { total, .. } => assert_eq!(total, 3),
+            _ => panic!("got wrong answer variant"),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn moderators_hide_reviews_and_businesses() -> StdResult<()> {
+        let sscrt = HumanAddr(SSCRT_ADDRESS.to_string());
+        let mut deps = mock_dependencies_with_transfers(vec![
+            (sscrt.clone(), transfer(1, "first", "mock-address", 100)),
+            (sscrt.clone(), transfer(2, "second", "mock-address", 300)),
+        ]);
+
+        let env = mock_env("creator", &[]);
+        let msg = InitMsg {
+            moderators: Some(vec![HumanAddr("moderator".to_string())]),
+            count_hidden_reviews: Some(false),
+            ..InitMsg::default()
+        };
+        init(&mut deps, env, msg)?;
+
+        let env = mock_env("anyone", &[]);
+        let msg = HandleMsg::RegisterBusiness {
+            name: "Starbucks".to_string(),
+            description: "a place to eat".to_string(),
+            address: HumanAddr("mock-address".to_string()),
+            category: None,
+        };
+        handle(&mut deps, env, msg)?;
+
+        for (reviewer, tx_id, rating) in &[("first", 1, 5), ("second", 2, 1)] {
+            let env = mock_env(*reviewer, &[]);
+            let msg = HandleMsg::ReviewBusiness {
+                address: HumanAddr("mock-address".to_string()),
+                content: "content".to_string(),
+                rating: *rating,
+                title: "title".to_string(),
+                receipts: vec![Receipt::Snip20 {
+                    token: None,
+                    tx_id: *tx_id,
+                    tx_page: 0,
+                    viewing_key: "vk".to_string(),
+                }],
+                dimension_ratings: vec![],
+                item: None,
+            };
+            handle(&mut deps, env, msg)?;
+        }
+
+        let hide = HandleMsg::HideReview {
+            business_address: HumanAddr("mock-address".to_string()),
+            reviewer: HumanAddr("second".to_string()),
+            reason: ModerationReason::Harassment,
+        };
+        let env = mock_env("anyone", &[]);
+        let error = handle(&mut deps, env, hide.clone()).unwrap_err();
+        assert_eq!(error, StdError::unauthorized());
+        let env = mock_env("moderator", &[]);
+        handle(&mut deps, env, hide)?;
+
+        let reviews = QueryMsg::GetReviewsOnBusiness {
+            business_address: HumanAddr("mock-address".to_string()),
+            page: None,
+            page_size: 10,
+        };
+        match from_binary(&query(&deps, reviews.clone())?)? {
+            QueryAnswer::Reviews { reviews, total, .. } => {
+                assert_eq!(total, 1);
+                assert_eq!(reviews.len(), 1);
+                assert_eq!(reviews[0].rating, "5".parse::<Rating>()?);
+            }
+            _ => panic!("got wrong answer variant"),
+        }
+
+        // the hidden review is kept, but doesn't count
+        let review = may_load_review(
+            &deps.storage,
+            &HumanAddr("mock-address".to_string()),
+            &HumanAddr("second".to_string()),
+        )
+        .unwrap();
+        assert_eq!(review.hidden.unwrap().reason, ModerationReason::Harassment);
+        let msg = QueryMsg::GetSingleBusiness {
+            address: HumanAddr("mock-address".to_string()),
+        };
+        match from_binary(&query(&deps, msg.clone())?)? {
+            QueryAnswer::SingleBusiness { business, .. } => {
+                let business = business.unwrap();
+                assert_eq!(business.reviews_count, 1);
+                assert_eq!(business.average_rating, "5".parse::<Rating>()?);
+            }
+            _ => panic!("got wrong answer variant"),
+        }
+        assert!(check_aggregates(&deps, "mock-address")?.consistent);
+
+        // counting hidden reviews from now on doesn't change what the hidden review contributed
+        let env = mock_env("creator", &[]);
+        let msg_moderation = HandleMsg::SetModeration {
+            moderators: None,
+            count_hidden_reviews: Some(true),
+            flag_threshold: None,
+        };
+        handle(&mut deps, env, msg_moderation)?;
+        let drift = check_aggregates(&deps, "mock-address")?;
+        assert!(drift.consistent);
+        assert_eq!(drift.stored.reviews_count, 1);
+
+        let env = mock_env("creator", &[]);
+        let unhide = HandleMsg::UnhideReview {
+            business_address: HumanAddr("mock-address".to_string()),
+            reviewer: HumanAddr("second".to_string()),
+        };
+        handle(&mut deps, env, unhide)?;
+        match from_binary(&query(&deps, msg.clone())?)? {
+            QueryAnswer::SingleBusiness { business, .. } => {
+                let business = business.unwrap();
+                assert_eq!(business.reviews_count, 2);
+                assert_eq!(business.average_rating, "2".parse::<Rating>()?);
+            }
+            _ => panic!("got wrong answer variant"),
+        }
+
+        let env = mock_env("moderator", &[]);
+        let hide = HandleMsg::HideBusiness {
+            address: HumanAddr("mock-address".to_string()),
+            reason: ModerationReason::Illegal,
+        };
+        handle(&mut deps, env, hide)?;
+        match from_binary(&query(&deps, msg)?)? {
+            QueryAnswer::SingleBusiness { business, status } => {
+                assert_eq!(business, None);
+                assert_eq!(status, "The business was hidden by a moderator");
+            }
+            _ => panic!("got wrong answer variant"),
+        }
+        for include_closed in &[false, true] {
+            let msg = QueryMsg::GetBusinesses {
+                page: None,
+                page_size: 10,
+                include_closed: *include_closed,
+            };
+            match from_binary(&query(&deps, msg)?)? {
+                QueryAnswer::Businesses { businesses, total } => {
+                    assert_eq!(total, 0);
+                    assert!(businesses.is_empty());
+                }
+                _ => panic!("got wrong answer variant"),
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn flagged_reviews_are_hidden_until_moderated() -> StdResult<()> {
+        let sscrt = HumanAddr(SSCRT_ADDRESS.to_string());
+        let mut deps = mock_dependencies_with_transfers(vec![
+            (sscrt.clone(), transfer(1, "troll", "mock-address", 100)),
+            (sscrt.clone(), transfer(2, "first", "mock-address", 30)),
+            (sscrt.clone(), transfer(3, "second", "mock-address", 40)),
+        ]);
+
+        let env = mock_env("creator", &[]);
+        let msg = InitMsg {
+            count_hidden_reviews: Some(false),
+            flag_threshold: Some(Uint128(50)),
+            ..InitMsg::default()
+        };
+        init(&mut deps, env, msg)?;
+
+        let env = mock_env("anyone", &[]);
+        let msg = HandleMsg::RegisterBusiness {
+            name: "Starbucks".to_string(),
+            description: "a place to eat".to_string(),
+            address: HumanAddr("mock-address".to_string()),
+            category: None,
+        };
+        handle(&mut deps, env, msg)?;
+
+        for (reviewer, tx_id, rating) in &[("troll", 1, 0), ("first", 2, 5), ("second", 3, 4)] {
+            let env = mock_env(*reviewer, &[]);
+            let msg = HandleMsg::ReviewBusiness {
+                address: HumanAddr("mock-address".to_string()),
+                content: "content".to_string(),
+                rating: *rating,
+                title: "title".to_string(),
+                receipts: vec![Receipt::Snip20 {
+                    token: None,
+                    tx_id: *tx_id,
+                    tx_page: 0,
+                    viewing_key: "vk".to_string(),
+                }],
+                dimension_ratings: vec![],
+                item: None,
+            };
+            handle(&mut deps, env, msg)?;
+        }
+
+        let flag = |reason: ModerationReason| HandleMsg::FlagReview {
+            business_address: HumanAddr("mock-address".to_string()),
+            reviewer: HumanAddr("troll".to_string()),
+            reason,
+        };
+        let env = mock_env("stranger", &[]);
+        match handle(&mut deps, env, flag(ModerationReason::Spam)) {
+            Err(StdError::GenericErr { msg, .. }) => assert_eq!(
+                msg,
+                "Only customers who reviewed the business can flag its reviews"
+            ),
+            _ => panic!("a review was flagged by someone who isn't a customer"),
+        }
+
+        let env = mock_env("first", &[]);
+        handle(&mut deps, env, flag(ModerationReason::Spam))?;
+        let env = mock_env("first", &[]);
+        match handle(&mut deps, env, flag(ModerationReason::Harassment)) {
+            Err(StdError::GenericErr { msg, .. }) => {
+                assert_eq!(msg, "You already flagged this review")
+            }
+            _ => panic!("a review was flagged twice by the same address"),
+        }
+
+        let reviews = QueryMsg::GetReviewsOnBusiness {
+            business_address: HumanAddr("mock-address".to_string()),
+            page: None,
+            page_size: 10,
+        };
+        match from_binary(&query(&deps, reviews.clone())?)? {
+            QueryAnswer::Reviews { total, .. } => assert_eq!(total, 3),
+            _ => panic!("got wrong answer variant"),
+        }
+
+        // 30 + 40 passes the threshold of 50
+        let env = mock_env("second", &[]);
+        handle(&mut deps, env, flag(ModerationReason::Harassment))?;
+        match from_binary(&query(&deps, reviews)?)? {
+            QueryAnswer::Reviews { total, reviews, .. } => {
+                assert_eq!(total, 2);
+                assert_eq!(reviews.len(), 2);
+            }
+            _ => panic!("got wrong answer variant"),
+        }
+        let review = may_load_review(
+            &deps.storage,
+            &HumanAddr("mock-address".to_string()),
+            &HumanAddr("troll".to_string()),
+        )
+        .unwrap();
+        assert_eq!(
+            review.hidden,
+            Some(Hidden {
+                moderator: None,
+                reason: ModerationReason::Harassment,
+                timestamp: mock_env("second", &[]).block.time,
+            })
+        );
+
+        let queue = QueryMsg::GetFlaggedReviews {
+            page: None,
+            page_size: 10,
+        };
+        match from_binary(&query(&deps, queue.clone())?)? {
+            QueryAnswer::FlaggedReviews { reviews, total } => {
+                assert_eq!(total, 1);
+                assert_eq!(reviews[0].reviewer, HumanAddr("troll".to_string()));
+                assert_eq!(reviews[0].flags_count, 2);
+                assert_eq!(reviews[0].flagged_weight, Uint128(70));
+                assert!(reviews[0].hidden);
+            }
+            _ => panic!("got wrong answer variant"),
+        }
+
+        // a moderator dismisses the flags, which shows the review again
+        let env = mock_env("creator", &[]);
+        let msg = HandleMsg::DismissFlags {
+            business_address: HumanAddr("mock-address".to_string()),
+            reviewer: HumanAddr("troll".to_string()),
+        };
+        handle(&mut deps, env, msg)?;
+        match from_binary(&query(&deps, queue)?)? {
+            QueryAnswer::FlaggedReviews { total, .. } => assert_eq!(total, 0),
+            _ => panic!("got wrong answer variant"),
+        }
+        let msg = QueryMsg::GetSingleBusiness {
+            address: HumanAddr("mock-address".to_string()),
+        };
+        match from_binary(&query(&deps, msg)?)? {
+            QueryAnswer::SingleBusiness { business, .. } => {
+                assert_eq!(business.unwrap().reviews_count, 3)
+            }
+            _ => panic!("got wrong answer variant"),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn disputes_are_ruled_on_by_the_arbiter() -> StdResult<()> {
+        let sscrt = HumanAddr(SSCRT_ADDRESS.to_string());
+        let mut deps = mock_dependencies_with_transfers(vec![
+            (sscrt.clone(), transfer(1, "first", "mock-address", 30)),
+            (sscrt.clone(), transfer(2, "second", "mock-address", 10)),
+        ]);
+
+        let env = mock_env("creator", &[]);
+        let msg = InitMsg {
+            arbiter: Some(HumanAddr("arbiter".to_string())),
+            dispute_bond: Some(Uint128(10)),
+            ..InitMsg::default()
+        };
+        init(&mut deps, env, msg)?;
+
+        let env = mock_env("anyone", &[]);
+        let msg = HandleMsg::RegisterBusiness {
+            name: "Starbucks".to_string(),
+            description: "a place to eat".to_string(),
+            address: HumanAddr("mock-address".to_string()),
+            category: None,
+        };
+        handle(&mut deps, env, msg)?;
+        let env = mock_env("mock-address", &[]);
+        let msg = HandleMsg::ClaimBusiness {
+            address: HumanAddr("mock-address".to_string()),
+            owner: None,
+        };
+        handle(&mut deps, env, msg)?;
+
+        for (reviewer, tx_id, rating) in &[("first", 1, 5), ("second", 2, 1)] {
+            let env = mock_env(*reviewer, &[]);
+            let msg = HandleMsg::ReviewBusiness {
+                address: HumanAddr("mock-address".to_string()),
+                content: "content".to_string(),
+                rating: *rating,
+                title: "title".to_string(),
+                receipts: vec![Receipt::Snip20 {
+                    token: None,
+                    tx_id: *tx_id,
+                    tx_page: 0,
+                    viewing_key: "vk".to_string(),
+                }],
+                dimension_ratings: vec![],
+                item: None,
+            };
+            handle(&mut deps, env, msg)?;
+        }
+
+        let open = |reviewer: &str| HandleMsg::OpenDispute {
+            business_address: HumanAddr("mock-address".to_string()),
+            reviewer: HumanAddr(reviewer.to_string()),
+            reason: "never visited us".to_string(),
+        };
+        let resolve = |reviewer: &str, outcome: DisputeOutcome| HandleMsg::ResolveDispute {
+            business_address: HumanAddr("mock-address".to_string()),
+            reviewer: HumanAddr(reviewer.to_string()),
+            outcome,
+        };
+        let bond = coins(10, NATIVE_DENOM);
+
+        let env = mock_env("mock-address", &coins(5, NATIVE_DENOM));
+        match handle(&mut deps, env, open("second")) {
+            Err(StdError::GenericErr { msg, .. }) => {
+                assert_eq!(msg, "Opening a dispute requires a bond of exactly 10 uscrt")
+            }
+            _ => panic!("a dispute was opened without the bond"),
+        }
+        let env = mock_env("first", &bond);
+        match handle(&mut deps, env, open("second")) {
+            Err(StdError::GenericErr { msg, .. }) => assert_eq!(
+                msg,
+                "Only the owner of the business can dispute its reviews"
+            ),
+            _ => panic!("a review was disputed by someone who doesn't own the business"),
+        }
+
+        let env = mock_env("mock-address", &bond);
+        handle(&mut deps, env, open("second"))?;
+        let env = mock_env("mock-address", &bond);
+        match handle(&mut deps, env, open("second")) {
+            Err(StdError::GenericErr { msg, .. }) => {
+                assert_eq!(msg, "The review is already disputed")
+            }
+            _ => panic!("a review was disputed twice at once"),
+        }
+
+        let env = mock_env("second", &[]);
+        let msg = HandleMsg::RespondToDispute {
+            business_address: HumanAddr("mock-address".to_string()),
+            response: "I did, twice".to_string(),
+        };
+        handle(&mut deps, env, msg)?;
+
+        let env = mock_env("creator", &[]);
+        match handle(
+            &mut deps,
+            env,
+            resolve("second", DisputeOutcome::ExcludeRating),
+        ) {
+            Err(StdError::Unauthorized { .. }) => {}
+            _ => panic!("a dispute was resolved by someone who isn't the arbiter"),
+        }
+
+        // the bond is returned, and only the first review counts from now on
+        let env = mock_env("arbiter", &[]);
+        let result = handle(
+            &mut deps,
+            env,
+            resolve("second", DisputeOutcome::ExcludeRating),
+        )?;
+        assert_eq!(
+            result.messages,
+            vec![CosmosMsg::Bank(BankMsg::Send {
+                from_address: HumanAddr(MOCK_CONTRACT_ADDR.to_string()),
+                to_address: HumanAddr("mock-address".to_string()),
+                amount: bond.clone(),
+            })]
+        );
+        let business = QueryMsg::GetSingleBusiness {
+            address: HumanAddr("mock-address".to_string()),
+        };
+        match from_binary(&query(&deps, business.clone())?)? {
+            QueryAnswer::SingleBusiness { business, .. } => {
+                let business = business.unwrap();
+                assert_eq!(business.average_rating, "5".parse::<Rating>()?);
+                assert_eq!(business.reviews_count, 1);
+            }
+            _ => panic!("got wrong answer variant"),
+        }
+        let msg = QueryMsg::GetDispute {
+            business_address: HumanAddr("mock-address".to_string()),
+            reviewer: HumanAddr("second".to_string()),
+        };
+        match from_binary(&query(&deps, msg)?)? {
+            QueryAnswer::Dispute { dispute } => {
+                let dispute = dispute.unwrap();
+                assert_eq!(dispute.response, Some("I did, twice".to_string()));
+                assert_eq!(
+                    dispute.state,
+                    DisputeState::Resolved {
+                        outcome: DisputeOutcome::ExcludeRating,
+                        timestamp: mock_env("arbiter", &[]).block.time,
+                    }
+                );
+            }
+            _ => panic!("got wrong answer variant"),
+        }
+
+        let env = mock_env("mock-address", &bond);
+        match handle(&mut deps, env, open("second")) {
+            Err(StdError::GenericErr { msg, .. }) => {
+                assert_eq!(msg, "An arbiter already ruled against this review")
+            }
+            _ => panic!("a review was disputed after a ruling against it"),
+        }
+
+        // an upheld review stays as it is, and the bond goes to the admin
+        let env = mock_env("mock-address", &bond);
+        handle(&mut deps, env, open("first"))?;
+        let env = mock_env("arbiter", &[]);
+        let result = handle(&mut deps, env, resolve("first", DisputeOutcome::Upheld))?;
+        assert_eq!(
+            result.messages,
+            vec![CosmosMsg::Bank(BankMsg::Send {
+                from_address: HumanAddr(MOCK_CONTRACT_ADDR.to_string()),
+                to_address: HumanAddr("creator".to_string()),
+                amount: bond.clone(),
+            })]
+        );
+
+        // hidden content leaves the rating in the aggregates
+        let env = mock_env("mock-address", &bond);
+        handle(&mut deps, env, open("first"))?;
+        let env = mock_env("arbiter", &[]);
+        handle(
+            &mut deps,
+            env,
+            resolve("first", DisputeOutcome::HideContent),
+        )?;
+        let reviews = QueryMsg::GetReviewsOnBusiness {
+            business_address: HumanAddr("mock-address".to_string()),
+            page: None,
+            page_size: 10,
+        };
+        match from_binary(&query(&deps, reviews)?)? {
+            QueryAnswer::Reviews { reviews, .. } => {
+                let first = reviews
+                    .iter()
+                    .find(|r| r.dispute_outcome == Some(DisputeOutcome::HideContent))
+                    .unwrap();
+                assert_eq!(first.title, "");
+                assert_eq!(first.content, "");
+                assert_eq!(first.rating, "5".parse::<Rating>()?);
+            }
+            _ => panic!("got wrong answer variant"),
+        }
+        match from_binary(&query(&deps, business)?)? {
+            QueryAnswer::SingleBusiness { business, .. } => {
+                assert_eq!(business.unwrap().reviews_count, 1)
+            }
+            _ => panic!("got wrong answer variant"),
+        }
+
+        // the admin can turn disputes off by unsetting the arbiter
+        let unset = HandleMsg::SetArbitration {
+            arbiter: None,
+            dispute_bond: None,
+        };
+        let env = mock_env("arbiter", &[]);
+        let error = handle(&mut deps, env, unset.clone()).unwrap_err();
+        assert_eq!(error, StdError::unauthorized());
+        let env = mock_env("creator", &[]);
+        handle(&mut deps, env, unset)?;
+        assert_eq!(load_config(&deps.storage)?.arbiter, None);
+        assert_eq!(load_config(&deps.storage)?.dispute_bond, Uint128(10));
+        let env = mock_env("mock-address", &bond);
+        match handle(&mut deps, env, open("second")) {
+            Err(StdError::GenericErr { msg, .. }) => {
+                assert_eq!(msg, "Disputes are disabled until the admin sets an arbiter")
+            }
+            _ => panic!("a dispute was opened without an arbiter"),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn associated_addresses_cannot_review() -> StdResult<()> {
+        let sscrt = HumanAddr(SSCRT_ADDRESS.to_string());
+        let mut deps = mock_dependencies_with_transfers(vec![
+            (sscrt.clone(), transfer(1, "wallet", "mock-address", 100)),
+            (sscrt.clone(), transfer(2, "customer", "mock-address", 10)),
+            (sscrt.clone(), transfer(3, "wallet", "mock-address", 100)),
+        ]);
+
+        let env = mock_env("creator", &[]);
+        init(&mut deps, env, InitMsg::default())?;
+
+        let env = mock_env("anyone", &[]);
+        let msg = HandleMsg::RegisterBusiness {
+            name: "Starbucks".to_string(),
+            description: "a place to eat".to_string(),
+            address: HumanAddr("mock-address".to_string()),
+            category: None,
+        };
+        handle(&mut deps, env, msg)?;
+        let env = mock_env("mock-address", &[]);
+        let msg = HandleMsg::ClaimBusiness {
+            address: HumanAddr("mock-address".to_string()),
+            owner: Some(HumanAddr("owner".to_string())),
+        };
+        handle(&mut deps, env, msg)?;
+
+        let review = |tx_id: u64, rating: u8| HandleMsg::ReviewBusiness {
+            address: HumanAddr("mock-address".to_string()),
+            content: "content".to_string(),
+            rating,
+            title: "title".to_string(),
+            receipts: vec![Receipt::Snip20 {
+                token: None,
+                tx_id,
+                tx_page: 0,
+                viewing_key: "vk".to_string(),
+            }],
+            dimension_ratings: vec![],
+            item: None,
+        };
+        let env = mock_env("mock-address", &[]);
+        match handle(&mut deps, env, review(1, 5)) {
+            Err(StdError::GenericErr { msg, .. }) => {
+                assert_eq!(msg, "A business can't review itself")
+            }
+            _ => panic!("a business reviewed itself"),
+        }
+        // nor can its owner
+        let env = mock_env("owner", &[]);
+        match handle(&mut deps, env, review(1, 5)) {
+            Err(StdError::GenericErr { msg, .. }) => {
+                assert_eq!(msg, "The owner of a business can't review it")
+            }
+            _ => panic!("the owner of a business reviewed it"),
+        }
+        let env = mock_env("wallet", &[]);
+        handle(&mut deps, env, review(1, 5))?;
+        let env = mock_env("customer", &[]);
+        handle(&mut deps, env, review(2, 2))?;
+
+        let associate = |addresses: &[&str]| HandleMsg::AssociateAddresses {
+            business_address: HumanAddr("mock-address".to_string()),
+            addresses: addresses
+                .iter()
+                .map(|address| HumanAddr(address.to_string()))
+                .collect(),
+        };
+        let dissociate = |addresses: &[&str]| HandleMsg::DissociateAddresses {
+            business_address: HumanAddr("mock-address".to_string()),
+            addresses: addresses
+                .iter()
+                .map(|address| HumanAddr(address.to_string()))
+                .collect(),
+        };
+        let confirm = HandleMsg::ConfirmAssociation {
+            business_address: HumanAddr("mock-address".to_string()),
+        };
+        let business_stats = |deps: &Extern<MockStorage, MockApi, Snip20Querier>| {
+            let msg = QueryMsg::GetSingleBusiness {
+                address: HumanAddr("mock-address".to_string()),
+            };
+            match from_binary(&query(deps, msg).unwrap()).unwrap() {
+                QueryAnswer::SingleBusiness { business, .. } => {
+                    let business = business.unwrap();
+                    (business.average_rating, business.reviews_count)
+                }
+                _ => panic!("got wrong answer variant"),
+            }
+        };
+
+        let env = mock_env("customer", &[]);
+        match handle(&mut deps, env, associate(&["wallet"])) {
+            Err(StdError::Unauthorized { .. }) => {}
+            _ => panic!("addresses were associated by someone who doesn't own the business"),
+        }
+
+        // the owner can keep addresses from reviewing, but can't take their reviews out
+        let env = mock_env("owner", &[]);
+        let result = handle(&mut deps, env, associate(&["wallet", "customer"]))?;
+        match from_binary(&result.data.unwrap())? {
+            HandleAnswer::AssociateAddresses { status } => assert_eq!(
+                status,
+                "Associated 2 addresses with the business, 0 of their reviews no longer count"
+            ),
+            _ => panic!("got wrong answer variant"),
+        }
+        assert_eq!(business_stats(&deps).1, 2);
+
+        let env = mock_env("owner", &[]);
+        let result = handle(&mut deps, env, dissociate(&["customer"]))?;
+        match from_binary(&result.data.unwrap())? {
+            HandleAnswer::DissociateAddresses { status } => assert_eq!(
+                status,
+                "Dissociated 1 addresses from the business, 0 of their reviews count again"
+            ),
+            _ => panic!("got wrong answer variant"),
+        }
+        let env = mock_env("customer", &[]);
+        match handle(&mut deps, env, confirm.clone()) {
+            Err(StdError::GenericErr { msg, .. }) => {
+                assert_eq!(msg, "The address is not associated with that business")
+            }
+            _ => panic!("an address that isn't associated confirmed an association"),
+        }
+
+        // the associated address takes its own review out
+        let env = mock_env("wallet", &[]);
+        let result = handle(&mut deps, env, confirm)?;
+        match from_binary(&result.data.unwrap())? {
+            HandleAnswer::ConfirmAssociation { status } => assert_eq!(
+                status,
+                "Confirmed the association, the review no longer counts"
+            ),
+            _ => panic!("got wrong answer variant"),
+        }
+        assert_eq!(business_stats(&deps), ("2".parse::<Rating>()?, 1));
+        assert!(check_aggregates(&deps, "mock-address")?.consistent);
+
+        let env = mock_env("wallet", &[]);
+        match handle(&mut deps, env, review(3, 5)) {
+            Err(StdError::GenericErr { msg, .. }) => assert_eq!(
+                msg,
+                "Addresses that are associated with the business can't review it"
+            ),
+            _ => panic!("an associated address reviewed the business"),
+        }
+
+        // only the admin or the arbiter can count the review again
+        let env = mock_env("owner", &[]);
+        match handle(&mut deps, env, dissociate(&["wallet"])) {
+            Err(StdError::GenericErr { msg, .. }) => assert_eq!(
+                msg,
+                "The review of wallet no longer counts, only the admin or the arbiter can dissociate it"
+            ),
+            _ => panic!("the owner counted an excluded review again"),
+        }
+        let env = mock_env("creator", &[]);
+        let result = handle(&mut deps, env, dissociate(&["wallet"]))?;
+        match from_binary(&result.data.unwrap())? {
+            HandleAnswer::DissociateAddresses { status } => assert_eq!(
+                status,
+                "Dissociated 1 addresses from the business, 1 of their reviews count again"
+            ),
+            _ => panic!("got wrong answer variant"),
+        }
+        assert_eq!(business_stats(&deps).1, 2);
+
+        let env = mock_env("creator", &[]);
+        let result = handle(&mut deps, env, associate(&["wallet"]))?;
+        match from_binary(&result.data.unwrap())? {
+            HandleAnswer::AssociateAddresses { status } => assert_eq!(
+                status,
+                "Associated 1 addresses with the business, 1 of their reviews no longer count"
+            ),
+            _ => panic!("got wrong answer variant"),
+        }
+        assert_eq!(business_stats(&deps), ("2".parse::<Rating>()?, 1));
+        assert!(check_aggregates(&deps, "mock-address")?.consistent);
 
-    use super::*;
-    use crate::state::get_business_by_address;
+        Ok(())
+    }
 
     #[test]
-    fn register_business() -> StdResult<()> {
-        let mut deps = mock_dependencies(20, &coins(2, "token"));
+    fn review_edits_are_kept_in_history() -> StdResult<()> {
+        let sscrt = HumanAddr(SSCRT_ADDRESS.to_string());
+        let mut deps = mock_dependencies_with_transfers(vec![(
+            sscrt.clone(),
+            transfer(1, "reviewer", "mock-address", 100),
+        )]);
 
-        let msg = InitMsg {};
-        let env = mock_env("creator", &coins(2, "token"));
-        let _res = init(&mut deps, env, msg).unwrap();
+        let env = mock_env("creator", &[]);
+        init(&mut deps, env, InitMsg::default())?;
 
-        let env = mock_env("anyone", &coins(2, "token"));
+        let env = mock_env("anyone", &[]);
         let msg = HandleMsg::RegisterBusiness {
             name: "Starbucks".to_string(),
             description: "a place to eat".to_string(),
             address: HumanAddr("mock-address".to_string()),
+            category: None,
         };
-        let res = handle(&mut deps, env, msg);
-        println!("res: {:?}", res);
-        let res2 = res.unwrap();
-        println!("res2: {:?}", res2);
-        let res3 = res2.data;
-        println!("res3: {:?}", res3);
-        let res4 = res3.unwrap();
-        println!("res4: {:?}", res4);
-        let res5: StdResult<HandleAnswer> = from_binary(&res4);
-        println!("res5: {:?}", res5);
-        let res6: HandleAnswer = res5.unwrap();
-        println!("res6: {:?}", res6);
-        match res6 {
-            HandleAnswer::RegisterBusiness { status } => {
-                assert_eq!("successfully called register business", status);
-                println!("success")
+        handle(&mut deps, env, msg)?;
+
+        let review = |title: &str, rating: u8| HandleMsg::ReviewBusiness {
+            address: HumanAddr("mock-address".to_string()),
+            content: "content".to_string(),
+            rating,
+            title: title.to_string(),
+            receipts: vec![Receipt::Snip20 {
+                token: None,
+                tx_id: 1,
+                tx_page: 0,
+                viewing_key: "vk".to_string(),
+            }],
+            dimension_ratings: vec![],
+            item: None,
+        };
+        let mut env = mock_env("reviewer", &[]);
+        let start = env.block.time;
+        handle(&mut deps, env.clone(), review("awful", 1))?;
+        env.block.time = start + 100;
+        handle(&mut deps, env, review("refunded, great", 5))?;
+
+        let reviews = QueryMsg::GetReviewsOnBusiness {
+            business_address: HumanAddr("mock-address".to_string()),
+            page: None,
+            page_size: 10,
+        };
+        match from_binary(&query(&deps, reviews)?)? {
+            QueryAnswer::Reviews { reviews, .. } => {
+                assert_eq!(reviews[0].title, "refunded, great");
+                assert!(reviews[0].edited);
+                assert_eq!(reviews[0].revisions_count, 2);
             }
             _ => panic!("got wrong answer variant"),
         }
 
-        // check that the business was indeed saved
-        let saved = get_business_by_address(&deps.storage, &HumanAddr("mock-address".to_string()));
+        let history = QueryMsg::GetReviewHistory {
+            business_address: HumanAddr("mock-address".to_string()),
+            reviewer: HumanAddr("reviewer".to_string()),
+            page: None,
+            page_size: 10,
+        };
+        match from_binary(&query(&deps, history.clone())?)? {
+            QueryAnswer::ReviewHistory {
+                revisions, total, ..
+            } => {
+                assert_eq!(total, 2);
+                assert_eq!(
+                    revisions
+                        .iter()
+                        .map(|r| (r.title.as_str(), r.rating, r.timestamp))
+                        .collect::<Vec<_>>(),
+                    vec![
+                        ("awful", "1".parse::<Rating>()?, start),
+                        ("refunded, great", "5".parse::<Rating>()?, start + 100),
+                    ]
+                );
+            }
+            _ => panic!("got wrong answer variant"),
+        }
 
-        assert_eq!(
-            saved.unwrap().unwrap(),
-            Business {
-                address: HumanAddr("mock-address".to_string()),
-                name: "Starbucks".to_string(),
-                description: "a place to eat".to_string(),
-                average_rating: Uint128::from(0u128),
-                reviews_count: 0,
-                total_weight: Uint128(0)
-            }
-        );
-
-        let mut all_businesses = CashMap::init(b"businesses", &mut deps.storage);
-        all_businesses.insert(
-            b"second",
-            Business {
-                address: HumanAddr("second".to_string()),
-                name: "second".to_string(),
-                description: "second".to_string(),
-                average_rating: Uint128::from(0u128),
-                reviews_count: 0,
-                total_weight: Default::default(),
-            },
-        )?;
+        // the history of a hidden review is left out with it
+        let env = mock_env("creator", &[]);
+        let msg = HandleMsg::HideReview {
+            business_address: HumanAddr("mock-address".to_string()),
+            reviewer: HumanAddr("reviewer".to_string()),
+            reason: ModerationReason::Spam,
+        };
+        handle(&mut deps, env, msg)?;
+        match from_binary(&query(&deps, history)?)? {
+            QueryAnswer::ReviewHistory {
+                revisions, total, ..
+            } => {
+                assert_eq!(total, 0);
+                assert!(revisions.is_empty());
+            }
+            _ => panic!("got wrong answer variant"),
+        }
 
-        all_businesses.insert(
-            b"third",
-            Business {
-                address: HumanAddr("third".to_string()),
-                name: "third".to_string(),
-                description: "third".to_string(),
-                average_rating: Uint128::from(0u128),
-                reviews_count: 0,
-                total_weight: Default::default(),
-            },
-        )?;
+        Ok(())
+    }
 
-        all_businesses.insert(
-            b"arthur",
-            Business {
-                address: HumanAddr("arthur".to_string()),
-                name: "arthur".to_string(),
-                description: "arthur the third".to_string(),
-                average_rating: Uint128::from(0u128),
-                reviews_count: 0,
-                total_weight: Default::default(),
-            },
-        )?;
+    #[test]
+    fn review_edits_are_limited() -> StdResult<()> {
+        let sscrt = HumanAddr(SSCRT_ADDRESS.to_string());
+        let mut deps = mock_dependencies_with_transfers(vec![(
+            sscrt.clone(),
+            transfer(1, "reviewer", "mock-address", 100),
+        )]);
 
-        // QUERY
-        let msg = QueryMsg::GetBusinesses {
-            page: Some(1),
-            page_size: 2,
+        let env = mock_env("creator", &[]);
+        let msg = InitMsg {
+            min_edit_interval_seconds: Some(60),
+            review_lock_seconds: Some(1000),
+            ..InitMsg::default()
         };
+        init(&mut deps, env, msg)?;
 
-        let res = query(&deps, msg);
-        let res_unpacked: QueryAnswer = from_binary(&res.unwrap()).unwrap();
-        match res_unpacked {
-            QueryAnswer::Businesses { businesses, total } => {
-                assert_eq!(total, 4);
-                // println!("{:?}", businesses);
-                assert_eq!(businesses[0].address.as_str(), "third");
-                println!("success")
+        let env = mock_env("anyone", &[]);
+        let msg = HandleMsg::RegisterBusiness {
+            name: "Starbucks".to_string(),
+            description: "a place to eat".to_string(),
+            address: HumanAddr("mock-address".to_string()),
+            category: None,
+        };
+        handle(&mut deps, env, msg)?;
+
+        let review = |rating: u8| HandleMsg::ReviewBusiness {
+            address: HumanAddr("mock-address".to_string()),
+            content: "content".to_string(),
+            rating,
+            title: "title".to_string(),
+            receipts: vec![Receipt::Snip20 {
+                token: None,
+                tx_id: 1,
+                tx_page: 0,
+                viewing_key: "vk".to_string(),
+            }],
+            dimension_ratings: vec![],
+            item: None,
+        };
+        let mut env = mock_env("reviewer", &[]);
+        let start = env.block.time;
+        handle(&mut deps, env.clone(), review(1))?;
+
+        env.block.time = start + 10;
+        match handle(&mut deps, env.clone(), review(5)) {
+            Err(StdError::GenericErr { msg, .. }) => assert_eq!(
+                msg,
+                "A review can only be edited once every 60 seconds, the next edit is possible in 50 seconds"
+            ),
+            _ => panic!("a review was edited before the minimum interval passed"),
+        }
+        env.block.time = start + 60;
+        handle(&mut deps, env.clone(), review(5))?;
+
+        // the lock counts from the creation of the review, not from its last update
+        env.block.time = start + 1000;
+        match handle(&mut deps, env, review(1)) {
+            Err(StdError::GenericErr { msg, .. }) => {
+                assert_eq!(msg, "The review is locked and can no longer be edited")
             }
-            _ => panic!("wrong query variant"),
+            _ => panic!("a locked review was edited"),
         }
 
+        let env = mock_env("creator", &[]);
+        let msg = HandleMsg::SetEditLimits {
+            min_edit_interval_seconds: None,
+            review_lock_seconds: Some(0),
+        };
+        handle(&mut deps, env, msg)?;
+        let mut env = mock_env("reviewer", &[]);
+        env.block.time = start + 1000;
+        handle(&mut deps, env, review(1))?;
+
         Ok(())
     }
 
     #[test]
-    fn register_existing_business() {
-        let mut deps = mock_dependencies(20, &coins(2, "token"));
-
-        let msg = InitMsg {};
-        let env = mock_env("creator", &coins(2, "token"));
-        let _res = init(&mut deps, env, msg).unwrap();
+    fn registration_deposits_are_returned_on_claim() -> StdResult<()> {
+        let mut deps = mock_dependencies(20, &[]);
+        let env = mock_env("creator", &[]);
+        let msg = InitMsg {
+            registration_deposit: Some(RegistrationDeposit {
+                amount: Uint128(100),
+                token: None,
+                refundable: true,
+            }),
+            ..InitMsg::default()
+        };
+        init(&mut deps, env, msg)?;
 
-        let env = mock_env("anyone", &coins(2, "token"));
-        let msg = HandleMsg::RegisterBusiness {
+        let register = HandleMsg::RegisterBusiness {
             name: "Starbucks".to_string(),
             description: "a place to eat".to_string(),
             address: HumanAddr("mock-address".to_string()),
+            category: None,
         };
-        let res = handle(&mut deps, env, msg);
-        let res_unpacked = from_binary::<HandleAnswer>(&res.unwrap().data.unwrap()).unwrap();
-        match res_unpacked {
-            HandleAnswer::RegisterBusiness { status } => {
-                assert_eq!("successfully called register business", status);
-                println!("success")
-            }
+        let env = mock_env("anyone", &coins(50, NATIVE_DENOM));
+        match handle(&mut deps, env, register.clone()) {
+            Err(StdError::GenericErr { msg, .. }) => assert_eq!(
+                msg,
+                "Registering a business requires a deposit of exactly 100 uscrt"
+            ),
+            _ => panic!("a business was registered without the deposit"),
+        }
+        let env = mock_env("anyone", &coins(100, NATIVE_DENOM));
+        handle(&mut deps, env, register)?;
+
+        let deposit = QueryMsg::GetBusinessDeposit {
+            business_address: HumanAddr("mock-address".to_string()),
+        };
+        match from_binary(&query(&deps, deposit.clone())?)? {
+            QueryAnswer::BusinessDeposit { deposit } => assert_eq!(
+                deposit,
+                Some(DisplayedDeposit {
+                    amount: Uint128(100),
+                    token: None,
+                    refundable: true,
+                })
+            ),
             _ => panic!("got wrong answer variant"),
         }
 
-        // another business, should succeed
-        let env = mock_env("anyone", &coins(2, "token"));
-        let msg = HandleMsg::RegisterBusiness {
+        let env = mock_env("mock-address", &[]);
+        let msg = HandleMsg::ClaimBusiness {
+            address: HumanAddr("mock-address".to_string()),
+            owner: None,
+        };
+        let result = handle(&mut deps, env, msg)?;
+        assert_eq!(
+            result.messages,
+            vec![CosmosMsg::Bank(BankMsg::Send {
+                from_address: HumanAddr(MOCK_CONTRACT_ADDR.to_string()),
+                to_address: HumanAddr("anyone".to_string()),
+                amount: coins(100, NATIVE_DENOM),
+            })]
+        );
+        match from_binary(&query(&deps, deposit)?)? {
+            QueryAnswer::BusinessDeposit { deposit } => assert_eq!(deposit, None),
+            _ => panic!("got wrong answer variant"),
+        }
+
+        // without a deposit, funds that are sent along aren't kept
+        let env = mock_env("creator", &[]);
+        let msg = HandleMsg::SetRegistrationDeposit { deposit: None };
+        handle(&mut deps, env, msg)?;
+        let register = HandleMsg::RegisterBusiness {
             name: "Starbucks".to_string(),
             description: "a place to eat".to_string(),
-            address: HumanAddr("another-address".to_string()),
+            address: HumanAddr("other-address".to_string()),
+            category: None,
         };
-        let res = handle(&mut deps, env, msg);
-        let res_unpacked = from_binary::<HandleAnswer>(&res.unwrap().data.unwrap()).unwrap();
-        match res_unpacked {
-            HandleAnswer::RegisterBusiness { status } => {
-                assert_eq!("successfully called register business", status);
-                println!("success")
+        let env = mock_env("anyone", &coins(100, NATIVE_DENOM));
+        match handle(&mut deps, env, register.clone()) {
+            Err(StdError::GenericErr { msg, .. }) => {
+                assert_eq!(msg, "Registering a business doesn't require any funds")
             }
-            _ => panic!("got wrong answer variant"),
+            _ => panic!("funds were taken without a deposit"),
         }
+        let env = mock_env("anyone", &[]);
+        handle(&mut deps, env, register)?;
 
-        // again, should fail
-        let env = mock_env("anyone", &coins(2, "token"));
+        Ok(())
+    }
+
+    #[test]
+    fn registration_fees_in_snip20_are_swept() -> StdResult<()> {
+        let token = DepositToken {
+            address: HumanAddr("fee-token".to_string()),
+            code_hash: "fee-token-hash".to_string(),
+        };
+        let mut deps = mock_dependencies(20, &[]);
+        let env = mock_env("creator", &[]);
+        let msg = InitMsg {
+            registration_deposit: Some(RegistrationDeposit {
+                amount: Uint128(100),
+                token: Some(token.clone()),
+                refundable: false,
+            }),
+            ..InitMsg::default()
+        };
+        let result = init(&mut deps, env.clone(), msg)?;
+        assert_eq!(
+            result.messages,
+            vec![register_receive_msg(
+                env.contract_code_hash,
+                None,
+                BLOCK_SIZE,
+                token.code_hash.clone(),
+                token.address.clone(),
+            )?]
+        );
+
+        let env = mock_env("anyone", &[]);
         let msg = HandleMsg::RegisterBusiness {
             name: "Starbucks".to_string(),
             description: "a place to eat".to_string(),
             address: HumanAddr("mock-address".to_string()),
+            category: None,
         };
-        let res = handle(&mut deps, env, msg).unwrap_err();
+        match handle(&mut deps, env, msg) {
+            Err(StdError::GenericErr { msg, .. }) => assert_eq!(
+                msg,
+                "Registering a business requires a deposit of exactly 100 of the token fee-token, sent through its Send"
+            ),
+            _ => panic!("a business was registered without the deposit"),
+        }
+
+        let receive = HandleMsg::Receive {
+            sender: HumanAddr("anyone".to_string()),
+            from: HumanAddr("anyone".to_string()),
+            amount: Uint128(100),
+            msg: Some(to_binary(&ReceiveMsg::RegisterBusiness {
+                name: "Starbucks".to_string(),
+                description: "a place to eat".to_string(),
+                address: HumanAddr("mock-address".to_string()),
+                category: None,
+            })?),
+        };
+        let env = mock_env("other-token", &[]);
+        match handle(&mut deps, env, receive.clone()) {
+            Err(StdError::GenericErr { msg, .. }) => {
+                assert_eq!(msg, "The contract doesn't accept this token")
+            }
+            _ => panic!("a business was registered with another token"),
+        }
+        let env = mock_env("fee-token", &[]);
+        handle(&mut deps, env, receive)?;
+
+        // a fee isn't returned when the business is claimed
+        let env = mock_env("mock-address", &[]);
+        let msg = HandleMsg::ClaimBusiness {
+            address: HumanAddr("mock-address".to_string()),
+            owner: None,
+        };
+        assert!(handle(&mut deps, env, msg)?.messages.is_empty());
+
+        let msg = HandleMsg::SweepDeposits {
+            business_addresses: vec![
+                HumanAddr("mock-address".to_string()),
+                HumanAddr("unknown".to_string()),
+            ],
+        };
+        let env = mock_env("anyone", &[]);
+        match handle(&mut deps, env, msg.clone()) {
+            Err(StdError::Unauthorized { .. }) => {}
+            _ => panic!("deposits were swept by someone who isn't the admin"),
+        }
+        let env = mock_env("creator", &[]);
+        let result = handle(&mut deps, env, msg)?;
         assert_eq!(
-            res,
-            StdError::generic_err("A business is already registered on that address")
+            result.messages,
+            vec![transfer_msg(
+                HumanAddr("creator".to_string()),
+                Uint128(100),
+                None,
+                None,
+                BLOCK_SIZE,
+                token.code_hash,
+                token.address,
+            )?]
         );
+
+        Ok(())
     }
 
     #[test]
-    fn review_unregistered_business() -> StdResult<()> {
-        let mut deps = mock_dependencies(20, &coins(2, "token"));
-
-        let msg = InitMsg {};
-        let env = mock_env("creator", &coins(2, "token"));
-        let _res = init(&mut deps, env, msg).unwrap();
+    fn registration_modes_restrict_registrars() -> StdResult<()> {
+        let mut deps = mock_dependencies(20, &[]);
+        let env = mock_env("creator", &[]);
+        let msg = InitMsg {
+            registration_mode: Some(RegistrationMode::OwnerOnly),
+            ..InitMsg::default()
+        };
+        init(&mut deps, env, msg)?;
 
-        let env = mock_env("anyone", &coins(2, "token"));
-        let msg = HandleMsg::ReviewBusiness {
-            address: HumanAddr("mock-address".to_string()),
-            content: "very enjoyable time at this place".to_string(),
-            rating: 5,
-            title: "Fantastic!".to_string(),
-            tx_id: 0,
-            tx_page: 0,
-            viewing_key: "vk".to_string(),
+        let register = |address: &str| HandleMsg::RegisterBusiness {
+            name: "Starbucks".to_string(),
+            description: "a place to eat".to_string(),
+            address: HumanAddr(address.to_string()),
+            category: None,
         };
+        let env = mock_env("anyone", &[]);
+        match handle(&mut deps, env, register("mock-address")) {
+            Err(StdError::GenericErr { msg, .. }) => assert_eq!(
+                msg,
+                "Businesses can only be registered from their own address"
+            ),
+            _ => panic!("a business was registered by someone else in owner-only mode"),
+        }
+        let env = mock_env("mock-address", &[]);
+        handle(&mut deps, env, register("mock-address"))?;
 
-        let res = handle(&mut deps, env, msg);
-        let error = res.unwrap_err();
+        let env = mock_env("creator", &[]);
+        let msg = HandleMsg::SetRegistrationMode {
+            mode: RegistrationMode::Allowlisted,
+        };
+        handle(&mut deps, env, msg)?;
+        let env = mock_env("creator", &[]);
+        let msg = HandleMsg::AddRegistrars {
+            registrars: vec![HumanAddr("registrar".to_string())],
+        };
+        handle(&mut deps, env, msg)?;
 
-        if let StdError::GenericErr { msg, .. } = error {
-            assert_eq!(
-                "There is no business registered on that address. You can register it instead.",
-                msg
-            )
-        } else {
-            panic!("there should be a generic error here")
+        let env = mock_env("second-address", &[]);
+        match handle(&mut deps, env, register("second-address")) {
+            Err(StdError::GenericErr { msg, .. }) => {
+                assert_eq!(msg, "Only the registrars can register businesses")
+            }
+            _ => panic!("a business was registered by someone who isn't a registrar"),
         }
+        let env = mock_env("registrar", &[]);
+        handle(&mut deps, env, register("second-address"))?;
+
+        let env = mock_env("creator", &[]);
+        let msg = HandleMsg::RemoveRegistrars {
+            registrars: vec![HumanAddr("registrar".to_string())],
+        };
+        handle(&mut deps, env, msg)?;
+        let env = mock_env("registrar", &[]);
+        assert!(handle(&mut deps, env, register("third-address")).is_err());
+        let env = mock_env("creator", &[]);
+        handle(&mut deps, env, register("third-address"))?;
 
         Ok(())
     }
 
     #[test]
-    fn review_rating_out_of_bounds() -> StdResult<()> {
-        let mut deps = mock_dependencies(20, &coins(2, "token"));
-
-        let msg = InitMsg {};
-        let env = mock_env("creator", &coins(2, "token"));
-        init(&mut deps, env, msg).unwrap();
+    fn vouchers_are_redeemed_once_within_the_budget() -> StdResult<()> {
+        let mut deps = mock_dependencies(20, &[]);
+        let env = mock_env("creator", &[]);
+        let msg = InitMsg {
+            voucher_weight_budget: Some(Uint128(100)),
+            ..InitMsg::default()
+        };
+        init(&mut deps, env, msg)?;
 
-        let env = mock_env("anyone", &coins(2, "token"));
+        let env = mock_env("anyone", &[]);
         let msg = HandleMsg::RegisterBusiness {
             name: "Starbucks".to_string(),
             description: "a place to eat".to_string(),
             address: HumanAddr("mock-address".to_string()),
+            category: None,
         };
         handle(&mut deps, env, msg)?;
-
-        // 1st review
-        let env = mock_env("anyone", &coins(2, "token"));
-        let msg = HandleMsg::ReviewBusiness {
+        let env = mock_env("mock-address", &[]);
+        let msg = HandleMsg::ClaimBusiness {
             address: HumanAddr("mock-address".to_string()),
-            content: "very enjoyable time at this place".to_string(),
-            rating: 6,
-            title: "Fantastic!".to_string(),
-            tx_id: 0,
-            tx_page: 0,
-            viewing_key: "vk".to_string(),
+            owner: None,
+        };
+        handle(&mut deps, env, msg)?;
+
+        let env = mock_env("mock-address", &[]);
+        let start = env.block.time;
+        let mint = |vouchers: &[(&str, u128, Option<u64>)]| HandleMsg::MintVouchers {
+            business_address: HumanAddr("mock-address".to_string()),
+            vouchers: vouchers
+                .iter()
+                .map(|(secret, weight, expires_at)| NewVoucher {
+                    secret_hash: voucher_hash(secret),
+                    weight: Uint128(*weight),
+                    expires_at: *expires_at,
+                })
+                .collect(),
         };
+        let result = handle(
+            &mut deps,
+            env.clone(),
+            mint(&[("first", 60, None), ("second", 30, Some(start + 100))]),
+        )?;
+        match from_binary(&result.data.unwrap())? {
+            HandleAnswer::MintVouchers { status } => assert_eq!(
+                status,
+                "Minted 2 vouchers, 10 of the voucher weight budget is left"
+            ),
+            _ => panic!("got wrong answer variant"),
+        }
+        match handle(&mut deps, env.clone(), mint(&[("third", 20, None)])) {
+            Err(StdError::GenericErr { msg, .. }) => assert_eq!(
+                msg,
+                "The vouchers exceed the voucher weight budget of 100, of which 90 was already minted"
+            ),
+            _ => panic!("vouchers were minted beyond the budget"),
+        }
 
-        let res = handle(&mut deps, env, msg);
-        let error = res.unwrap_err();
+        // lowering the budget below what was minted leaves none of it
+        let env = mock_env("creator", &[]);
+        let msg = HandleMsg::SetVoucherBudget {
+            voucher_weight_budget: Uint128(50),
+            max_attested_amount: None,
+        };
+        handle(&mut deps, env, msg)?;
+        let env = mock_env("mock-address", &[]);
+        let result = handle(&mut deps, env, mint(&[]))?;
+        match from_binary(&result.data.unwrap())? {
+            HandleAnswer::MintVouchers { status } => assert_eq!(
+                status,
+                "Minted 0 vouchers, 0 of the voucher weight budget is left"
+            ),
+            _ => panic!("got wrong answer variant"),
+        }
 
-        if let StdError::GenericErr { msg, .. } = error {
-            assert_eq!("ratings must be between 0 and 5 stars", msg);
-        } else {
-            panic!("there should be a generic error here");
+        let review = |secret: &str| HandleMsg::ReviewBusiness {
+            address: HumanAddr("mock-address".to_string()),
+            content: "content".to_string(),
+            rating: 4,
+            title: "title".to_string(),
+            receipts: vec![Receipt::Voucher {
+                secret: secret.to_string(),
+            }],
+            dimension_ratings: vec![],
+            item: None,
+        };
+        let env = mock_env("customer", &[]);
+        handle(&mut deps, env, review("first"))?;
+        let saved = may_load_review(
+            &deps.storage,
+            &HumanAddr("mock-address".to_string()),
+            &HumanAddr("customer".to_string()),
+        )
+        .unwrap();
+        assert_eq!(saved.weight, Uint128(60));
+        assert_eq!(
+            saved.tx_ids,
+            vec![ReceiptId::Voucher {
+                secret_hash: voucher_hash("first")
+            }]
+        );
+
+        let env = mock_env("someone-else", &[]);
+        match handle(&mut deps, env, review("first")) {
+            Err(StdError::GenericErr { msg, .. }) => {
+                assert_eq!(msg, "The voucher was already redeemed")
+            }
+            _ => panic!("a voucher was redeemed twice"),
+        }
+        let mut env = mock_env("someone-else", &[]);
+        env.block.time = start + 100;
+        match handle(&mut deps, env, review("second")) {
+            Err(StdError::GenericErr { msg, .. }) => assert_eq!(msg, "The voucher expired"),
+            _ => panic!("an expired voucher was redeemed"),
         }
 
         Ok(())
     }
 
     #[test]
-    fn register_business_long_name() {
-        let mut deps = mock_dependencies(20, &coins(2, "token"));
+    fn attestations_are_verified_and_their_nonces_used_once() -> StdResult<()> {
+        use k256::ecdsa::signature::Signer;
+        use k256::ecdsa::SigningKey;
 
-        let msg = InitMsg {};
-        let env = mock_env("creator", &coins(2, "token"));
-        let _res = init(&mut deps, env, msg).unwrap();
+        let mut deps = mock_dependencies(20, &[]);
+        let env = mock_env("creator", &[]);
+        let msg = InitMsg {
+            voucher_weight_budget: Some(Uint128(300)),
+            max_attested_amount: Some(Uint128(250)),
+            ..InitMsg::default()
+        };
+        init(&mut deps, env, msg)?;
 
-        let env = mock_env("anyone", &coins(2, "token"));
+        let env = mock_env("anyone", &[]);
         let msg = HandleMsg::RegisterBusiness {
-            name: "NameIs21Characters...".to_string(),
+            name: "Starbucks".to_string(),
             description: "a place to eat".to_string(),
             address: HumanAddr("mock-address".to_string()),
+            category: None,
         };
+        handle(&mut deps, env, msg)?;
+        let env = mock_env("mock-address", &[]);
+        let msg = HandleMsg::ClaimBusiness {
+            address: HumanAddr("mock-address".to_string()),
+            owner: None,
+        };
+        handle(&mut deps, env, msg)?;
 
-        let res = handle(&mut deps, env, msg);
-        let error = res.unwrap_err();
-
-        if let StdError::GenericErr { msg, .. } = error {
-            assert_eq!("Name length can't be bigger than 20", msg)
-        } else {
-            panic!("there should be a generic error here")
+        let signing_key = SigningKey::from_bytes(&[7; 32]).unwrap();
+        let set_key = HandleMsg::SetAttestationKey {
+            business_address: HumanAddr("mock-address".to_string()),
+            pubkey: Some(Binary(signing_key.verifying_key().to_bytes().to_vec())),
+        };
+        let env = mock_env("anyone", &[]);
+        match handle(&mut deps, env, set_key.clone()) {
+            Err(StdError::GenericErr { msg, .. }) => assert_eq!(
+                msg,
+                "Only the owner of the business can set its attestation key"
+            ),
+            _ => panic!("the attestation key was set by someone other than the owner"),
         }
-    }
+        let env = mock_env("mock-address", &[]);
+        handle(&mut deps, env, set_key)?;
 
-    #[test]
-    fn register_business_long_description() {
-        let mut deps = mock_dependencies(20, &coins(2, "token"));
+        let attest = |reviewer: &str, amount: u128, nonce: u64| {
+            let message = attestation_message(
+                &HumanAddr(MOCK_CONTRACT_ADDR.to_string()),
+                &HumanAddr("mock-address".to_string()),
+                &HumanAddr(reviewer.to_string()),
+                Uint128(amount),
+                nonce,
+            );
+            let signature: Signature = signing_key.sign(message.as_bytes());
+            Receipt::Attestation {
+                amount: Uint128(amount),
+                nonce,
+                signature: Binary(signature.as_ref().to_vec()),
+            }
+        };
+        let review = |receipt: Receipt| HandleMsg::ReviewBusiness {
+            address: HumanAddr("mock-address".to_string()),
+            content: "content".to_string(),
+            rating: 4,
+            title: "title".to_string(),
+            receipts: vec![receipt],
+            dimension_ratings: vec![],
+            item: None,
+        };
 
-        let msg = InitMsg {};
-        let env = mock_env("creator", &coins(2, "token"));
-        let _res = init(&mut deps, env, msg).unwrap();
+        let env = mock_env("customer", &[]);
+        handle(&mut deps, env, review(attest("customer", 250, 1)))?;
+        let saved = may_load_review(
+            &deps.storage,
+            &HumanAddr("mock-address".to_string()),
+            &HumanAddr("customer".to_string()),
+        )
+        .unwrap();
+        assert_eq!(saved.weight, Uint128(250));
+        assert_eq!(saved.tx_ids, vec![ReceiptId::Attestation { nonce: 1 }]);
 
-        let env = mock_env("anyone", &coins(2, "token"));
-        let msg = HandleMsg::RegisterBusiness {
-            name: "Scrt Labs".to_string(),
-            description: "DescriptionIs43CharactersLongWhichIsTooMuch".to_string(),
-            address: HumanAddr("mock-address".to_string()),
+        // an attestation that was signed for someone else, or for another amount
+        let env = mock_env("someone-else", &[]);
+        match handle(&mut deps, env, review(attest("customer", 250, 2))) {
+            Err(StdError::GenericErr { msg, .. }) => {
+                assert_eq!(msg, "The attestation signature is invalid")
+            }
+            _ => panic!("an attestation was accepted from another reviewer"),
+        }
+        let forged = match attest("someone-else", 100, 2) {
+            Receipt::Attestation {
+                nonce, signature, ..
+            } => Receipt::Attestation {
+                amount: Uint128(200),
+                nonce,
+                signature,
+            },
+            _ => unreachable!(),
         };
+        let env = mock_env("someone-else", &[]);
+        match handle(&mut deps, env, review(forged)) {
+            Err(StdError::GenericErr { msg, .. }) => {
+                assert_eq!(msg, "The attestation signature is invalid")
+            }
+            _ => panic!("an attestation was accepted for another amount"),
+        }
 
-        let res = handle(&mut deps, env, msg);
-        let error = res.unwrap_err();
+        let env = mock_env("someone-else", &[]);
+        match handle(&mut deps, env, review(attest("someone-else", 100, 1))) {
+            Err(StdError::GenericErr { msg, .. }) => {
+                assert_eq!(msg, "The attestation nonce was already used")
+            }
+            _ => panic!("an attestation nonce was used twice"),
+        }
 
-        if let StdError::GenericErr { msg, .. } = error {
-            assert_eq!("Description length can't be bigger than 40", msg)
-        } else {
-            panic!("there should be a generic error here")
+        // attested amounts are capped, and count against the voucher weight budget
+        let env = mock_env("someone-else", &[]);
+        match handle(&mut deps, env, review(attest("someone-else", 251, 2))) {
+            Err(StdError::GenericErr { msg, .. }) => {
+                assert_eq!(msg, "Attested amounts must be between 1 and 250")
+            }
+            _ => panic!("an attestation was accepted beyond the largest amount"),
         }
+        let env = mock_env("someone-else", &[]);
+        match handle(&mut deps, env, review(attest("someone-else", 100, 2))) {
+            Err(StdError::GenericErr { msg, .. }) => assert_eq!(
+                msg,
+                "The attestation exceeds the voucher weight budget of 300, of which 250 was already used"
+            ),
+            _ => panic!("an attestation was accepted beyond the budget"),
+        }
+        let env = mock_env("someone-else", &[]);
+        handle(&mut deps, env, review(attest("someone-else", 50, 2)))?;
+        let business =
+            get_business_by_address(&deps.storage, &HumanAddr("mock-address".to_string()))?
+                .unwrap();
+        assert_eq!(business.vouchers_weight, Uint128(300));
+
+        let env = mock_env("mock-address", &[]);
+        let msg = HandleMsg::SetAttestationKey {
+            business_address: HumanAddr("mock-address".to_string()),
+            pubkey: None,
+        };
+        handle(&mut deps, env, msg)?;
+        let env = mock_env("someone-else", &[]);
+        match handle(&mut deps, env, review(attest("someone-else", 100, 3))) {
+            Err(StdError::GenericErr { msg, .. }) => {
+                assert_eq!(msg, "The business doesn't accept purchase attestations")
+            }
+            _ => panic!("an attestation was accepted without an attestation key"),
+        }
+
+        Ok(())
     }
 }
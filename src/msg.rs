@@ -1,36 +1,490 @@
-use cosmwasm_std::{HumanAddr, Uint128};
+use cosmwasm_std::{Binary, HumanAddr, Uint128};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-#[derive(Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+use crate::rating::{Rating, RatingScale};
+use crate::state::{
+    AggregateRebuild, BusinessAggregates, BusinessStatus, Category, DepositToken, DimensionRating,
+    DisputeOutcome, DisputeState, FlagWeight, ModerationReason, ReceiptAge, RegistrationDeposit,
+    RegistrationMode, Snip20Token, TimeDecay, WeightStrategy,
+};
+
+#[derive(Deserialize, Clone, Debug, Default, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
-pub struct InitMsg {}
+pub struct InitMsg {
+    /// SNIP-20 tokens whose transfers are accepted as receipts. Defaults to sSCRT only.
+    pub accepted_tokens: Option<Vec<Snip20Token>>,
+    /// Native payments of a smaller amount can't be cited as receipts. Defaults to 0.
+    pub min_native_receipt_amount: Option<Uint128>,
+    /// Enables netting refunds from the weight of reviews. Defaults to false.
+    pub refund_netting: Option<bool>,
+    /// Receipts older than this can't be cited. Defaults to no limit.
+    pub max_receipt_age: Option<ReceiptAge>,
+    /// Whether reviews can be edited when all of their receipts are older than max_receipt_age.
+    /// Defaults to true.
+    pub allow_stale_edits: Option<bool>,
+    /// How the amounts of a review's receipts are turned into its weight. Defaults to linear.
+    pub weight_strategy: Option<WeightStrategy>,
+    /// Enables an exponentially time-decayed average next to the plain one. Defaults to none.
+    pub time_decay: Option<TimeDecay>,
+    /// The scale of businesses whose category doesn't choose one. Defaults to whole stars.
+    pub rating_scale: Option<RatingScale>,
+    /// defaults to none, the admin can always moderate
+    pub moderators: Option<Vec<HumanAddr>>,
+    /// defaults to true
+    pub count_hidden_reviews: Option<bool>,
+    /// defaults to zero, which never hides reviews because of their flags
+    pub flag_threshold: Option<Uint128>,
+    /// defaults to none, which disables disputes
+    pub arbiter: Option<HumanAddr>,
+    /// the uscrt that an owner must send to open a dispute, defaults to zero
+    pub dispute_bond: Option<Uint128>,
+    /// The least number of seconds between two updates of a review. Defaults to 0.
+    pub min_edit_interval_seconds: Option<u64>,
+    /// Reviews can't be updated once they are this many seconds old. Defaults to 0, which never
+    /// locks them.
+    pub review_lock_seconds: Option<u64>,
+    /// What registering a business costs. Defaults to nothing.
+    pub registration_deposit: Option<RegistrationDeposit>,
+    /// The total weight of the vouchers that every business can mint, and of the purchases that
+    /// it can attest to. Defaults to 0, which disables both.
+    pub voucher_weight_budget: Option<Uint128>,
+    /// The largest amount that a single purchase attestation can be of. Defaults to 0, which
+    /// disables attestations.
+    pub max_attested_amount: Option<Uint128>,
+    /// Who can register businesses. Defaults to anyone.
+    pub registration_mode: Option<RegistrationMode>,
+    /// Can register businesses when the mode is allowlisted. Defaults to none, the admin can
+    /// always register businesses in that mode.
+    pub registrars: Option<Vec<HumanAddr>>,
+}
 
 #[derive(Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum HandleMsg {
+    /// Must be sent with the registration deposit when it is in uscrt
     RegisterBusiness {
         name: String,
         address: HumanAddr,
         description: String,
+        /// must be one of the categories that the admin set
+        category: Option<String>,
+    },
+    /// Called by a SNIP-20 when tokens are sent to the contract, with a `ReceiveMsg`
+    Receive {
+        sender: HumanAddr,
+        from: HumanAddr,
+        amount: Uint128,
+        msg: Option<Binary>,
     },
     ReviewBusiness {
         address: HumanAddr,
         content: String,
         rating: u8,
         title: String,
+        receipts: Vec<Receipt>,
+        /// a rating for every dimension of the business' category, on the business' scale
+        #[serde(default)]
+        dimension_ratings: Vec<DimensionRating>,
+        /// the sku of an item in the business' catalog that the review is about
+        item: Option<String>,
+    },
+    PayBusiness {
+        address: HumanAddr,
+    },
+    MigrateReviews {
+        business_address: HumanAddr,
+        reviewers: Vec<HumanAddr>,
+    },
+    /// Moves up to `limit` businesses that were saved before businesses had a status into the
+    /// current layout
+    MigrateBusinesses {
+        limit: u32,
+    },
+    /// Nets the refunds on a page of transfer history from the weight of a review. The reviewer
+    /// scans their own history, while the owner of the business or the admin name the `reviewer`
+    /// and scan the history of the business.
+    ReverifyRefunds {
+        business_address: HumanAddr,
+        reviewer: Option<HumanAddr>,
+        token: Option<HumanAddr>,
+        tx_page: u32,
+        viewing_key: String,
+    },
+    SetWeightStrategy {
+        strategy: WeightStrategy,
+    },
+    RecomputeWeights {
+        business_address: HumanAddr,
+        page: u32,
+        page_size: u32,
+    },
+    /// Processes the next page of a rebuild of the business' aggregates from its reviews. The
+    /// page size is only taken from the call that starts the rebuild.
+    RecomputeBusiness {
+        business_address: HumanAddr,
+        page_size: u32,
+    },
+    /// Must be sent from the business' address. The owner, who defaults to the sender, can then
+    /// manage the business.
+    ClaimBusiness {
+        address: HumanAddr,
+        owner: Option<HumanAddr>,
+    },
+    /// Adds an item to the catalog of a claimed business, or renames an existing one
+    RegisterItem {
+        business_address: HumanAddr,
+        sku: String,
+        name: String,
+    },
+    /// Prevents the given addresses from reviewing the business. Can be sent by the owner of the
+    /// business, the admin or the arbiter. When it is sent by the admin or the arbiter, the
+    /// existing reviews of the addresses are also left out of the business' aggregates.
+    AssociateAddresses {
+        business_address: HumanAddr,
+        addresses: Vec<HumanAddr>,
+    },
+    /// Sent by an associated address to confirm its association, which leaves its existing review
+    /// out of the business' aggregates.
+    ConfirmAssociation {
+        business_address: HumanAddr,
+    },
+    /// Lets the given addresses review the business again, and counts their reviews again. The
+    /// owner of the business can only dissociate addresses whose reviews weren't left out.
+    DissociateAddresses {
+        business_address: HumanAddr,
+        addresses: Vec<HumanAddr>,
+    },
+    /// Must be sent by the owner of the claimed business. The weight of the vouchers counts
+    /// against the business' voucher weight budget as soon as they are minted.
+    MintVouchers {
+        business_address: HumanAddr,
+        vouchers: Vec<NewVoucher>,
+    },
+    /// Must be sent by the owner of the claimed business. Sets the SEC1 encoded secp256k1 public
+    /// key that the business signs purchase attestations with, or stops accepting them when unset.
+    SetAttestationKey {
+        business_address: HumanAddr,
+        pubkey: Option<Binary>,
+    },
+    /// Closes a business, so that it can't be reviewed and isn't listed by default. Can be sent by
+    /// the owner of the business or by the admin.
+    DeactivateBusiness {
+        address: HumanAddr,
+    },
+    ReactivateBusiness {
+        address: HumanAddr,
+    },
+    /// Replaces the moderators or the counting of hidden reviews. Businesses with hidden reviews
+    /// should be rebuilt with `RecomputeBusiness` after the latter changes.
+    SetModeration {
+        moderators: Option<Vec<HumanAddr>>,
+        count_hidden_reviews: Option<bool>,
+        flag_threshold: Option<Uint128>,
+    },
+    /// Can be sent by the admin or a moderator, and so can the other moderation messages
+    HideReview {
+        business_address: HumanAddr,
+        reviewer: HumanAddr,
+        reason: ModerationReason,
+    },
+    UnhideReview {
+        business_address: HumanAddr,
+        reviewer: HumanAddr,
+    },
+    HideBusiness {
+        address: HumanAddr,
+        reason: ModerationReason,
+    },
+    UnhideBusiness {
+        address: HumanAddr,
+    },
+    /// Can be sent once per review by every customer who reviewed the business
+    FlagReview {
+        business_address: HumanAddr,
+        reviewer: HumanAddr,
+        reason: ModerationReason,
+    },
+    /// Takes a review off the moderators' queue, and shows it again if its flags hid it
+    DismissFlags {
+        business_address: HumanAddr,
+        reviewer: HumanAddr,
+    },
+    /// Replaces the arbiter of disputes, or unsets it when it is omitted, which disables disputes.
+    /// The bond that opening one requires is kept when it is omitted. Disputes that are already
+    /// open keep their bond, and wait for an arbiter to be set again.
+    SetArbitration {
+        arbiter: Option<HumanAddr>,
+        dispute_bond: Option<Uint128>,
+    },
+    /// Must be sent by the owner of the claimed business, with the dispute bond in uscrt
+    OpenDispute {
+        business_address: HumanAddr,
+        reviewer: HumanAddr,
+        reason: String,
+    },
+    /// Must be sent by the reviewer while the dispute is open. A later response replaces the
+    /// previous one.
+    RespondToDispute {
+        business_address: HumanAddr,
+        response: String,
+    },
+    /// Must be sent by the arbiter. The bond is slashed if the review is upheld, and returned to
+    /// the owner otherwise.
+    ResolveDispute {
+        business_address: HumanAddr,
+        reviewer: HumanAddr,
+        outcome: DisputeOutcome,
+    },
+    /// Registers a brand for its owner, with its average published on the given scale (defaults
+    /// to the contract's). Must be sent by a registrar or the admin, so that brand names can't be
+    /// taken by whoever registers them first.
+    RegisterBrand {
+        name: String,
+        owner: HumanAddr,
+        rating_scale: Option<RatingScale>,
+    },
+    /// Must be sent by the owner of both the brand and the claimed business
+    AddBranch {
+        brand: String,
+        business_address: HumanAddr,
+    },
+    /// Can be sent by the owner of either the brand or the business
+    RemoveBranch {
+        business_address: HumanAddr,
+    },
+    /// Replaces the terms of the registration deposit, or removes it. Deposits that were already
+    /// paid keep their terms.
+    SetRegistrationDeposit {
+        deposit: Option<RegistrationDeposit>,
+    },
+    /// Sends the deposits of the given businesses to the admin, e.g. when they are spam or the
+    /// deposits are fees. Businesses without a deposit are skipped.
+    SweepDeposits {
+        business_addresses: Vec<HumanAddr>,
+    },
+    SetRegistrationMode {
+        mode: RegistrationMode,
+    },
+    /// The largest attested amount is kept as it was when it isn't given
+    SetVoucherBudget {
+        voucher_weight_budget: Uint128,
+        max_attested_amount: Option<Uint128>,
+    },
+    AddRegistrars {
+        registrars: Vec<HumanAddr>,
+    },
+    RemoveRegistrars {
+        registrars: Vec<HumanAddr>,
+    },
+    /// Sets the interval between updates of a review, or its lock period. Reviews that were
+    /// written before their creation time was kept are locked by the time of their last update.
+    SetEditLimits {
+        min_edit_interval_seconds: Option<u64>,
+        review_lock_seconds: Option<u64>,
+    },
+    /// Adds a category, or updates an existing one
+    SetCategory {
+        name: String,
+        rating_scale: Option<RatingScale>,
+        #[serde(default)]
+        dimensions: Vec<String>,
+    },
+}
+
+/// The messages that can be sent to the contract along with tokens, through a SNIP-20's Send
+#[derive(Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[cfg_attr(test, derive(Serialize))]
+#[serde(rename_all = "snake_case")]
+pub enum ReceiveMsg {
+    /// Must be sent with the registration deposit, when it is in this token
+    RegisterBusiness {
+        name: String,
+        address: HumanAddr,
+        description: String,
+        category: Option<String>,
+    },
+}
+
+/// A proof of payment to a business, cited when reviewing it
+#[derive(Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Receipt {
+    Snip20 {
+        /// the token that was transferred, defaults to sSCRT
+        token: Option<HumanAddr>,
         tx_id: u64,
         tx_page: u32,
         viewing_key: String,
     },
+    Native {
+        receipt_id: u64,
+    },
+    /// A voucher that the business minted, which is redeemed by revealing its secret
+    Voucher {
+        secret: String,
+    },
+    /// A purchase that the business attested to off-chain, by signing the reviewer's address,
+    /// the amount and a nonce with its attestation key
+    Attestation {
+        amount: Uint128,
+        nonce: u64,
+        /// the 64 byte compact secp256k1 signature
+        signature: Binary,
+    },
+}
+
+#[derive(Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[cfg_attr(test, derive(Serialize))]
+#[serde(rename_all = "snake_case")]
+pub struct NewVoucher {
+    /// the sha256 hash of the voucher's secret
+    pub secret_hash: Binary,
+    /// the weight that the voucher gives to the review that redeems it
+    pub weight: Uint128,
+    /// the block time from which the voucher can no longer be redeemed
+    pub expires_at: Option<u64>,
 }
 
 #[derive(Serialize, Clone, Debug, PartialEq, JsonSchema)]
 #[cfg_attr(test, derive(Deserialize))]
 #[serde(rename_all = "snake_case")]
 pub enum HandleAnswer {
-    RegisterBusiness { status: String },
-    ReviewBusiness { status: String },
+    RegisterBusiness {
+        status: String,
+    },
+    ReviewBusiness {
+        status: String,
+    },
+    PayBusiness {
+        status: String,
+        receipt_id: u64,
+    },
+    MigrateReviews {
+        status: String,
+    },
+    MigrateBusinesses {
+        status: String,
+    },
+    ReverifyRefunds {
+        status: String,
+    },
+    SetWeightStrategy {
+        status: String,
+    },
+    RecomputeWeights {
+        status: String,
+    },
+    RecomputeBusiness {
+        status: String,
+        /// only present once the rebuild is done
+        drift: Option<Box<AggregatesDrift>>,
+    },
+    SetCategory {
+        status: String,
+    },
+    ClaimBusiness {
+        status: String,
+    },
+    RegisterItem {
+        status: String,
+    },
+    AssociateAddresses {
+        status: String,
+    },
+    ConfirmAssociation {
+        status: String,
+    },
+    DissociateAddresses {
+        status: String,
+    },
+    MintVouchers {
+        status: String,
+    },
+    SetAttestationKey {
+        status: String,
+    },
+    DeactivateBusiness {
+        status: String,
+    },
+    ReactivateBusiness {
+        status: String,
+    },
+    SetModeration {
+        status: String,
+    },
+    HideReview {
+        status: String,
+    },
+    UnhideReview {
+        status: String,
+    },
+    HideBusiness {
+        status: String,
+    },
+    UnhideBusiness {
+        status: String,
+    },
+    FlagReview {
+        status: String,
+    },
+    DismissFlags {
+        status: String,
+    },
+    RegisterBrand {
+        status: String,
+    },
+    AddBranch {
+        status: String,
+    },
+    RemoveBranch {
+        status: String,
+    },
+    SetArbitration {
+        status: String,
+    },
+    SetEditLimits {
+        status: String,
+    },
+    SetRegistrationDeposit {
+        status: String,
+    },
+    SweepDeposits {
+        status: String,
+    },
+    SetRegistrationMode {
+        status: String,
+    },
+    SetVoucherBudget {
+        status: String,
+    },
+    AddRegistrars {
+        status: String,
+    },
+    RemoveRegistrars {
+        status: String,
+    },
+    OpenDispute {
+        status: String,
+    },
+    RespondToDispute {
+        status: String,
+    },
+    ResolveDispute {
+        status: String,
+    },
+}
+
+/// The aggregates that a business had, next to the ones that were rebuilt from its reviews.
+/// The decayed sums are rounded down at every update, so small differences in them are expected
+/// and don't make the aggregates inconsistent.
+#[derive(Serialize, Clone, Debug, PartialEq, JsonSchema)]
+#[cfg_attr(test, derive(Deserialize))]
+#[serde(rename_all = "snake_case")]
+pub struct AggregatesDrift {
+    pub stored: BusinessAggregates,
+    pub rebuilt: BusinessAggregates,
+    pub consistent: bool,
 }
 
 #[derive(Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -40,6 +494,9 @@ pub enum QueryMsg {
     GetBusinesses {
         page: Option<u32>,
         page_size: u32,
+        /// closed businesses are only listed when this is set
+        #[serde(default)]
+        include_closed: bool,
     },
     GetSingleBusiness {
         address: HumanAddr,
@@ -49,6 +506,51 @@ pub enum QueryMsg {
         page: Option<u32>,
         page_size: u32,
     },
+    /// Rebuilds the aggregates of a business from a page of its reviews without saving them. The
+    /// rebuild that is answered is passed to the next call, until the drift is answered instead.
+    /// The page size is taken from the first call.
+    CheckBusinessAggregates {
+        business_address: HumanAddr,
+        page_size: u32,
+        rebuild: Option<AggregateRebuild>,
+    },
+    GetCategories {},
+    GetItems {
+        business_address: HumanAddr,
+        page: Option<u32>,
+        page_size: u32,
+    },
+    GetBrand {
+        name: String,
+    },
+    /// The moderators' queue
+    GetFlaggedReviews {
+        page: Option<u32>,
+        page_size: u32,
+    },
+    GetBranches {
+        brand: String,
+        page: Option<u32>,
+        page_size: u32,
+    },
+    /// The versions of a review, from the first one. Hidden reviews have no history.
+    GetReviewHistory {
+        business_address: HumanAddr,
+        reviewer: HumanAddr,
+        page: Option<u32>,
+        page_size: u32,
+    },
+    /// The terms of the registration deposit
+    GetRegistrationDeposit {},
+    /// The deposit that is held for a business, if any
+    GetBusinessDeposit {
+        business_address: HumanAddr,
+    },
+    /// The latest dispute on a review
+    GetDispute {
+        business_address: HumanAddr,
+        reviewer: HumanAddr,
+    },
 }
 
 #[derive(Serialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -57,8 +559,33 @@ pub enum QueryMsg {
 pub struct DisplayedReview {
     pub title: String,
     pub content: String,
-    pub rating: u8, // 0 to 5
+    pub rating: Rating, // on the business' rating scale
+    pub dimension_ratings: Vec<DisplayedDimensionRating>,
+    pub item: Option<String>,
     pub last_update_timestamp: u64,
+    pub edited: bool,
+    pub revisions_count: u32,
+    // the title and content are empty when an arbiter hid them
+    pub dispute_outcome: Option<DisputeOutcome>,
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq, JsonSchema)]
+#[cfg_attr(test, derive(Deserialize))]
+#[serde(rename_all = "snake_case")]
+pub struct DisplayedRevision {
+    pub title: String,
+    pub content: String,
+    pub rating: Rating,
+    pub item: Option<String>,
+    pub timestamp: u64,
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq, JsonSchema)]
+#[cfg_attr(test, derive(Deserialize))]
+#[serde(rename_all = "snake_case")]
+pub struct DisplayedDimensionRating {
+    pub dimension: String,
+    pub rating: Rating,
 }
 
 #[derive(Serialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -68,10 +595,78 @@ pub struct DisplayedBusiness {
     pub name: String,
     pub description: String,
     pub address: HumanAddr,
-    pub average_rating: Uint128, // max - 5000, min - 0
+    pub category: Option<String>,
+    pub claimed: bool,
+    pub brand: Option<String>,
+    pub status: BusinessStatus,
+    pub rating_scale: RatingScale,
+    pub average_rating: Rating,
+    // only present when time decay is enabled and the business has recent enough reviews
+    pub decayed_average_rating: Option<Rating>,
+    // the average of every dimension that the business was rated on
+    pub dimension_ratings: Vec<DisplayedDimensionRating>,
+    pub reviews_count: u32,
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq, JsonSchema)]
+#[cfg_attr(test, derive(Deserialize))]
+#[serde(rename_all = "snake_case")]
+pub struct DisplayedItem {
+    pub sku: String,
+    pub name: String,
+    pub average_rating: Rating,
     pub reviews_count: u32,
 }
 
+#[derive(Serialize, Clone, Debug, PartialEq, JsonSchema)]
+#[cfg_attr(test, derive(Deserialize))]
+#[serde(rename_all = "snake_case")]
+pub struct DisplayedBrand {
+    pub name: String,
+    pub owner: HumanAddr,
+    pub rating_scale: RatingScale,
+    // weighted by the reviews of all the branches, as if they were a single business
+    pub average_rating: Rating,
+    pub reviews_count: u32,
+    pub branches_count: u32,
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq, JsonSchema)]
+#[cfg_attr(test, derive(Deserialize))]
+#[serde(rename_all = "snake_case")]
+pub struct DisplayedFlaggedReview {
+    pub business_address: HumanAddr,
+    pub reviewer: HumanAddr,
+    pub flags_count: u32,
+    pub flagged_weight: Uint128,
+    pub reasons: Vec<FlagWeight>,
+    // whether the flags hid the review
+    pub hidden: bool,
+    pub review: DisplayedReview,
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq, JsonSchema)]
+#[cfg_attr(test, derive(Deserialize))]
+#[serde(rename_all = "snake_case")]
+pub struct DisplayedDispute {
+    pub business_address: HumanAddr,
+    pub reviewer: HumanAddr,
+    pub bond: Uint128,
+    pub reason: String,
+    pub response: Option<String>,
+    pub state: DisputeState,
+    pub opened_timestamp: u64,
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq, JsonSchema)]
+#[cfg_attr(test, derive(Deserialize))]
+#[serde(rename_all = "snake_case")]
+pub struct DisplayedDeposit {
+    pub amount: Uint128,
+    pub token: Option<DepositToken>,
+    pub refundable: bool,
+}
+
 #[derive(Serialize, Clone, Debug, PartialEq, JsonSchema)]
 #[cfg_attr(test, derive(Deserialize))]
 #[serde(rename_all = "snake_case")]
@@ -87,5 +682,43 @@ pub enum QueryAnswer {
     Reviews {
         reviews: Vec<DisplayedReview>,
         total: u32,
+        rating_scale: RatingScale,
+    },
+    Categories {
+        categories: Vec<Category>,
+    },
+    Items {
+        items: Vec<DisplayedItem>,
+        total: u32,
+        rating_scale: RatingScale,
+    },
+    BusinessAggregates {
+        rebuild: Option<AggregateRebuild>,
+        drift: Option<AggregatesDrift>,
+    },
+    Brand {
+        brand: Option<DisplayedBrand>,
+    },
+    FlaggedReviews {
+        reviews: Vec<DisplayedFlaggedReview>,
+        total: u32,
+    },
+    Branches {
+        branches: Vec<DisplayedBusiness>,
+        total: u32,
+    },
+    Dispute {
+        dispute: Option<DisplayedDispute>,
+    },
+    ReviewHistory {
+        revisions: Vec<DisplayedRevision>,
+        total: u32,
+        rating_scale: RatingScale,
+    },
+    RegistrationDeposit {
+        deposit: Option<RegistrationDeposit>,
+    },
+    BusinessDeposit {
+        deposit: Option<DisplayedDeposit>,
     },
 }
@@ -0,0 +1,203 @@
+use std::fmt;
+use std::str::FromStr;
+
+use cosmwasm_std::{StdError, StdResult};
+use schemars::gen::SchemaGenerator;
+use schemars::schema::Schema;
+use schemars::JsonSchema;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+/// Ratings are kept internally as a whole number of hundredths of the highest rating, so that
+/// every scale maps into the same range exactly
+pub const MAX_NORMALIZED_RATING: u8 = 100;
+
+/// The scale that reviewers rate a business on
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum RatingScale {
+    /// 0 to 5 whole stars
+    Stars,
+    /// 0 to 5 stars in steps of half a star, rated as a number of half stars (0 to 10)
+    HalfStars,
+    /// 0 to 10 points
+    TenPoints,
+    /// 0 - don't recommend, 1 - recommend
+    Binary,
+}
+
+impl Default for RatingScale {
+    fn default() -> Self {
+        RatingScale::Stars
+    }
+}
+
+impl RatingScale {
+    /// The number of steps between the lowest and the highest rating
+    fn steps(&self) -> u8 {
+        match self {
+            RatingScale::Stars => 5,
+            RatingScale::HalfStars => 10,
+            RatingScale::TenPoints => 10,
+            RatingScale::Binary => 1,
+        }
+    }
+
+    /// The highest rating, in the units that averages are published in
+    fn max_value(&self) -> u128 {
+        match self {
+            RatingScale::Stars | RatingScale::HalfStars => 5,
+            RatingScale::TenPoints => 10,
+            RatingScale::Binary => 1,
+        }
+    }
+
+    fn describe_max(&self) -> &'static str {
+        match self {
+            RatingScale::Stars => "5 stars",
+            RatingScale::HalfStars => "10 half stars",
+            RatingScale::TenPoints => "10 points",
+            RatingScale::Binary => "1 (0 - don't recommend, 1 - recommend)",
+        }
+    }
+
+    /// Turns a rating that was given on this scale into the internal range
+    pub fn normalize(&self, rating: u8) -> StdResult<u8> {
+        if rating > self.steps() {
+            return Err(StdError::generic_err(format!(
+                "ratings must be between 0 and {}",
+                self.describe_max()
+            )));
+        }
+
+        Ok(rating * (MAX_NORMALIZED_RATING / self.steps()))
+    }
+
+    /// Publishes a sum of weighted normalized ratings as an average on this scale, e.g. in stars
+    /// for both whole and half stars
+    pub fn average(&self, normalized_rating_sum: u128, total_weight: u128) -> StdResult<Rating> {
+        let overflow = || StdError::generic_err("rating is out of the representable range");
+        Rating::from_ratio(
+            normalized_rating_sum
+                .checked_mul(self.max_value())
+                .ok_or_else(overflow)?,
+            total_weight
+                .checked_mul(MAX_NORMALIZED_RATING as u128)
+                .ok_or_else(overflow)?,
+        )
+    }
+}
+
+/// A non-negative fixed-point rating with 6 decimal places, e.g. 4.428571 stars.
+///
+/// Averages are kept by the contract as exact integer sums of weighted ratings, and only turned
+/// into a `Rating` when they are published. That division is the only place where precision is
+/// lost: the quotient is rounded to the nearest millionth, and exact halves are rounded up.
+///
+/// In JSON a rating is a decimal string without trailing zeros, e.g. `"4.5"` or `"5"`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Rating(u128);
+
+impl Rating {
+    pub const DECIMAL_PLACES: u32 = 6;
+    const ONE: u128 = 1_000_000;
+
+    pub fn zero() -> Self {
+        Rating(0)
+    }
+
+    /// Returns numerator / denominator, rounded to the nearest millionth (halves up). A zero
+    /// denominator gives a zero rating, as an average over no weight at all.
+    pub fn from_ratio(numerator: u128, denominator: u128) -> StdResult<Self> {
+        if denominator == 0 {
+            return Ok(Rating::zero());
+        }
+
+        let overflow = || StdError::generic_err("rating is out of the representable range");
+        let whole = (numerator / denominator)
+            .checked_mul(Self::ONE)
+            .ok_or_else(overflow)?;
+        let remainder = (numerator % denominator)
+            .checked_mul(Self::ONE)
+            .ok_or_else(overflow)?;
+        // the remainder of the fraction is compared with what is left of the denominator rather
+        // than adding half a denominator to it, which could overflow for large denominators
+        let mut fraction = remainder / denominator;
+        let rest = remainder % denominator;
+        if rest >= denominator - rest {
+            fraction += 1;
+        }
+
+        whole.checked_add(fraction).map(Rating).ok_or_else(overflow)
+    }
+
+    /// The rating in millionths
+    pub fn millionths(&self) -> u128 {
+        self.0
+    }
+}
+
+impl fmt::Display for Rating {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let whole = self.0 / Self::ONE;
+        let fraction = self.0 % Self::ONE;
+        if fraction == 0 {
+            return write!(f, "{}", whole);
+        }
+
+        let fraction = format!("{:06}", fraction);
+        write!(f, "{}.{}", whole, fraction.trim_end_matches('0'))
+    }
+}
+
+impl FromStr for Rating {
+    type Err = StdError;
+
+    fn from_str(input: &str) -> StdResult<Self> {
+        let invalid = || StdError::generic_err(format!("invalid rating: {}", input));
+
+        let mut parts = input.splitn(2, '.');
+        let whole: u128 = parts
+            .next()
+            .unwrap_or_default()
+            .parse()
+            .map_err(|_| invalid())?;
+
+        let fraction = parts.next().unwrap_or("0");
+        if fraction.is_empty()
+            || fraction.len() > Self::DECIMAL_PLACES as usize
+            || !fraction.chars().all(|c| c.is_ascii_digit())
+        {
+            return Err(invalid());
+        }
+        let fraction: u128 = format!("{:0<6}", fraction).parse().map_err(|_| invalid())?;
+
+        whole
+            .checked_mul(Self::ONE)
+            .and_then(|whole| whole.checked_add(fraction))
+            .map(Rating)
+            .ok_or_else(invalid)
+    }
+}
+
+impl Serialize for Rating {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for Rating {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let input = String::deserialize(deserializer)?;
+        input.parse().map_err(de::Error::custom)
+    }
+}
+
+impl JsonSchema for Rating {
+    fn schema_name() -> String {
+        "Rating".to_string()
+    }
+
+    fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+        String::json_schema(gen)
+    }
+}
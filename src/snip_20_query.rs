@@ -1,17 +1,29 @@
 use cosmwasm_std::{HumanAddr, Querier, StdError, StdResult};
 use secret_toolkit::snip20::{transfer_history_query, TransferHistory, Tx};
 
+use crate::state::Snip20Token;
+
 const QUERY_PAGE_SIZE: u32 = 10;
-const SSCRT_HASH: &str = "E47144CD74E2E3E24275962CAA7719F081CCFA81A46532812596CA3D5BA6ECEB";
-const SSCRT_ADDRESS: &str = "secret18vd8fpwxzck93qlwghaj6arh4p7c5n8978vsyg";
+pub const BLOCK_SIZE: usize = 256;
+pub const SSCRT_HASH: &str = "E47144CD74E2E3E24275962CAA7719F081CCFA81A46532812596CA3D5BA6ECEB";
+pub const SSCRT_ADDRESS: &str = "secret18vd8fpwxzck93qlwghaj6arh4p7c5n8978vsyg";
 
-pub fn query_snip20_tx<Q: Querier>(
+pub fn find_snip20_tx(txs: &[Tx], tx_id: u64) -> StdResult<&Tx> {
+    txs.iter().find(|&x| x.id == tx_id).ok_or_else(|| {
+        StdError::generic_err(format!(
+            "there was no transaction with id {} in the specified page",
+            tx_id
+        ))
+    })
+}
+
+pub fn query_snip20_history_page<Q: Querier>(
     querier: &Q,
-    tx_id: u64,
+    token: &Snip20Token,
     viewing_key: String,
     tx_page: u32,
     requester_address: &HumanAddr,
-) -> StdResult<Tx> {
+) -> StdResult<Vec<Tx>> {
     // address whose balance is being requested:
 
     let tx_history: TransferHistory = transfer_history_query(
@@ -20,17 +32,10 @@ pub fn query_snip20_tx<Q: Querier>(
         viewing_key,
         Some(tx_page),
         QUERY_PAGE_SIZE,
-        256,
-        SSCRT_HASH.to_string(),
-        HumanAddr(SSCRT_ADDRESS.to_string()),
+        BLOCK_SIZE,
+        token.code_hash.clone(),
+        token.address.clone(),
     )?;
 
-    let specific_tx = tx_history.txs.iter().find(|&x| x.id == tx_id);
-    match specific_tx {
-        Some(tx) => Ok(tx.clone()),
-        None => Err(StdError::generic_err(format!(
-            "there was no transaction with id {} in the specified page",
-            tx_id
-        ))),
-    }
+    Ok(tx_history.txs)
 }
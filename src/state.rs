@@ -1,16 +1,301 @@
-use cosmwasm_std::{HumanAddr, ReadonlyStorage, StdError, StdResult, Storage, Uint128};
+use cosmwasm_std::{Binary, HumanAddr, ReadonlyStorage, StdError, StdResult, Storage, Uint128};
+use cosmwasm_storage::{singleton, singleton_read};
 use schemars::JsonSchema;
 use secret_toolkit::incubator::{CashMap, ReadOnlyCashMap};
 use serde::{Deserialize, Serialize};
 
-use crate::msg::DisplayedReview;
+use crate::msg::{DisplayedDimensionRating, DisplayedReview};
+use crate::rating::{Rating, RatingScale, MAX_NORMALIZED_RATING};
+use crate::utils::{averageable_decayed_sums, result_add, result_mul, result_sub};
 
-pub static KEY_BUSINESSES: &[u8] = b"businesses";
-pub static PREFIX_REVIEWS: &str = "reviews";
+pub static KEY_CONFIG: &[u8] = b"config";
+pub static KEY_BUSINESSES: &[u8] = b"businesses_v2";
+pub static KEY_LEGACY_BUSINESSES: &[u8] = b"businesses";
+pub static KEY_LISTED_BUSINESSES: &[u8] = b"listed_businesses";
+pub static KEY_HIDDEN_BUSINESSES: &[u8] = b"hidden_businesses";
+pub static PREFIX_REVIEWS: &str = "reviews_v2";
+pub static PREFIX_LEGACY_REVIEWS: &str = "reviews";
+pub static PREFIX_ITEMS: &str = "items";
+pub static PREFIX_ASSOCIATED_ADDRESSES: &str = "associated_addresses";
+pub static PREFIX_REVISIONS: &str = "revisions";
+pub static PREFIX_VOUCHERS: &str = "vouchers";
+pub static PREFIX_ATTESTATION_NONCES: &str = "attestationnonces";
+pub static KEY_NATIVE_RECEIPTS: &[u8] = b"native_receipts";
+pub static KEY_AGGREGATE_REBUILDS: &[u8] = b"aggregate_rebuilds";
+pub static KEY_CATEGORIES: &[u8] = b"categories";
+pub static KEY_BRANDS: &[u8] = b"brands";
+pub static PREFIX_BRAND_BRANCHES: &str = "brand_branches";
+pub static PREFIX_FLAGS: &str = "flags";
+pub static KEY_FLAGGED_REVIEWS: &[u8] = b"flagged_reviews";
+pub static KEY_DISPUTES: &[u8] = b"disputes";
+pub static KEY_DEPOSITS: &[u8] = b"deposits";
 
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct Snip20Token {
+    pub address: HumanAddr,
+    pub code_hash: String,
+    /// transfers of a smaller amount can't be cited as receipts
+    #[serde(default)]
+    pub min_receipt_amount: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct Config {
+    pub admin: HumanAddr,
+    pub accepted_tokens: Vec<Snip20Token>,
+    /// native payments of a smaller amount can't be cited as receipts
+    pub min_native_receipt_amount: Uint128,
+    /// whether reviewers' weights can be re-verified against refunds from the business
+    pub refund_netting: bool,
+    pub max_receipt_age: Option<ReceiptAge>,
+    /// whether reviews whose receipts are all older than max_receipt_age can still be edited
+    pub allow_stale_edits: bool,
+    pub weight_strategy: WeightStrategy,
+    pub time_decay: Option<TimeDecay>,
+    /// the scale of businesses whose category doesn't choose one
+    pub rating_scale: RatingScale,
+    /// can hide and unhide reviews and businesses, besides the admin
+    pub moderators: Vec<HumanAddr>,
+    /// whether hidden reviews still count towards the aggregates of their business
+    pub count_hidden_reviews: bool,
+    /// reviews are hidden once the weight of their flags reaches this, unless it is zero
+    pub flag_threshold: Uint128,
+    /// rules on the disputes that owners open on reviews, which are disabled while this is none
+    pub arbiter: Option<HumanAddr>,
+    /// the uscrt that an owner must send to open a dispute
+    pub dispute_bond: Uint128,
+    /// the least number of seconds between two updates of a review
+    pub min_edit_interval_seconds: u64,
+    /// reviews can't be updated once they are this many seconds old, unless it is zero
+    pub review_lock_seconds: u64,
+    /// what registering a business costs, if anything
+    pub registration_deposit: Option<RegistrationDeposit>,
+    pub registration_mode: RegistrationMode,
+    /// can register businesses when the mode is allowlisted, besides the admin
+    pub registrars: Vec<HumanAddr>,
+    /// the total weight of the vouchers that every business can mint, and of the purchases that it
+    /// can attest to
+    pub voucher_weight_budget: Uint128,
+    /// purchase attestations of a larger amount are rejected
+    pub max_attested_amount: Uint128,
+}
+
+impl Config {
+    pub fn is_moderator(&self, address: &HumanAddr) -> bool {
+        *address == self.admin || self.moderators.contains(address)
+    }
+
+    pub fn is_registrar(&self, address: &HumanAddr) -> bool {
+        *address == self.admin || self.registrars.contains(address)
+    }
+}
+
+/// Who can register businesses
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum RegistrationMode {
+    /// anyone can register any address
+    Open,
+    /// a business can only be registered from its own address
+    OwnerOnly,
+    /// only the registrars and the admin can register businesses
+    Allowlisted,
+}
+
+impl Default for RegistrationMode {
+    fn default() -> Self {
+        RegistrationMode::Open
+    }
+}
+
+/// The terms of the deposit that registering a business requires
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct RegistrationDeposit {
+    pub amount: Uint128,
+    /// the SNIP-20 that the deposit is sent in through its Send, or none for uscrt
+    pub token: Option<DepositToken>,
+    /// whether the deposit is returned when the business is claimed, or kept as a fee
+    pub refundable: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct DepositToken {
+    pub address: HumanAddr,
+    pub code_hash: String,
+}
+
+/// Why a review or a business was hidden
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ModerationReason {
+    Illegal,
+    Spam,
+    Harassment,
+    PersonalInformation,
+    Other,
+}
+
+/// Hidden content is kept in storage, so that it can be restored on appeal
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct Hidden {
+    // none when the review was hidden because of its flags, until a moderator looks at it
+    pub moderator: Option<HumanAddr>,
+    pub reason: ModerationReason,
+    pub timestamp: u64,
+}
+
+/// An exponential decay of the weight of reviews, by the time since they were last updated
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct TimeDecay {
+    /// the number of seconds after which a review counts half as much
+    pub half_life_seconds: u64,
+}
+
+/// How the accumulated amount of a review's receipts is turned into the weight of its rating
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum WeightStrategy {
+    /// the weight is the amount itself
+    Linear,
+    /// the weight is the square root of the amount
+    SquareRoot,
+    /// the weight is log2(1 + amount), in units of 1/65536
+    Logarithmic,
+    /// the weight is the amount, but no more than the given percentage of the business' total
+    /// amount
+    Capped { max_share_percent: u8 },
+}
+
+/// How old a receipt can be when it is cited, measured against the block time or height in
+/// which the payment happened.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ReceiptAge {
+    Seconds(u64),
+    Blocks(u64),
+}
+
+pub fn save_config<S: Storage>(store: &mut S, config: &Config) -> StdResult<()> {
+    singleton(store, KEY_CONFIG).save(config)
+}
+
+pub fn load_config<S: ReadonlyStorage>(store: &S) -> StdResult<Config> {
+    singleton_read(store, KEY_CONFIG).load()
+}
+
+/// Businesses are saved with bincode, which doesn't fill in missing fields, so businesses in the
+/// legacy layout are moved into this one by `LegacyBusiness::migrate`, which sets every field
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub struct Business {
+    pub name: String,
+    pub description: String,
+    pub address: HumanAddr,
+    pub category: Option<String>,
+    // set when the business' address claims it
+    pub owner: Option<HumanAddr>,
+    // the brand that the business is a branch of
+    pub brand: Option<String>,
+    pub status: BusinessStatus,
+    // set while a moderator hides the business
+    pub hidden: Option<Hidden>,
+    // the number of reviews that are hidden, whether they count in the aggregates or not
+    pub hidden_reviews_count: u32,
+    // the total weight of the vouchers that the business minted, redeemed or not, and of the
+    // purchases that it attested to
+    pub vouchers_weight: Uint128,
+    // the SEC1 encoded secp256k1 public key that the business signs purchase attestations with
+    pub attestation_key: Option<Binary>,
+    pub reviews_count: u32,
+
+    // the sum of the reviews' applied weights, which the average is weighted by
+    pub total_weight: Uint128,
+    // the sum of the reviews' ratings multiplied by their applied weights. It is kept exactly,
+    // and divided by total_weight only when the average is published.
+    pub rating_sum: Uint128,
+    // the same sums for every rating dimension, sorted by dimension
+    pub dimensions: Vec<DimensionAggregate>,
+    // the sum of the reviews' raw weights, before the weight strategy was applied
+    pub total_raw_weight: Uint128,
+
+    // the sums of the reviews' decayed weights and decayed weighted ratings, as of decay_timestamp.
+    // Decaying both sums by the same factor doesn't change their ratio, so they only need to be
+    // brought up to date when a review changes.
+    pub decayed_weight: Uint128,
+    pub decayed_rating_sum: Uint128,
+    pub decay_timestamp: u64,
+}
+
+impl Business {
+    pub fn aggregates(&self) -> BusinessAggregates {
+        BusinessAggregates {
+            reviews_count: self.reviews_count,
+            total_weight: self.total_weight,
+            total_raw_weight: self.total_raw_weight,
+            rating_sum: self.rating_sum,
+            dimensions: self.dimensions.clone(),
+            decayed_weight: self.decayed_weight,
+            decayed_rating_sum: self.decayed_rating_sum,
+        }
+    }
+
+    pub fn set_aggregates(&mut self, aggregates: &BusinessAggregates) {
+        self.reviews_count = aggregates.reviews_count;
+        self.total_weight = aggregates.total_weight;
+        self.total_raw_weight = aggregates.total_raw_weight;
+        self.rating_sum = aggregates.rating_sum;
+        self.dimensions = aggregates.dimensions.clone();
+        self.decayed_weight = aggregates.decayed_weight;
+        self.decayed_rating_sum = aggregates.decayed_rating_sum;
+    }
+
+    /// Whether the business is in the default listing: active and not hidden
+    pub fn is_listed(&self) -> bool {
+        self.status == BusinessStatus::Active && self.hidden.is_none()
+    }
+
+    pub fn average_rating(&self, scale: &RatingScale) -> StdResult<Rating> {
+        scale.average(self.rating_sum.u128(), self.total_weight.u128())
+    }
+
+    /// Only present when time decay is enabled and some of the reviews still count.
+    /// Exponential decay scales all the contributions by the same factor as time passes, so the
+    /// decayed average as of the last update is also the current one.
+    pub fn decayed_average_rating(&self, scale: &RatingScale) -> StdResult<Option<Rating>> {
+        if self.decayed_weight.is_zero() {
+            return Ok(None);
+        }
+
+        let (rating_sum, weight) =
+            averageable_decayed_sums(self.decayed_rating_sum.u128(), self.decayed_weight.u128());
+        scale.average(rating_sum, weight).map(Some)
+    }
+}
+
+/// Closed businesses can't be reviewed, and are only listed when asked for
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum BusinessStatus {
+    Active,
+    Closed,
+}
+
+impl Default for BusinessStatus {
+    fn default() -> Self {
+        BusinessStatus::Active
+    }
+}
+
+/// The layout that businesses were saved in before they had a status, when they kept a truncated
+/// average instead of the sum of the weighted ratings
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct LegacyBusiness {
     pub name: String,
     pub description: String,
     pub address: HumanAddr,
@@ -20,7 +305,161 @@ pub struct Business {
     pub total_weight: Uint128,
 }
 
+impl LegacyBusiness {
+    /// The weighted rating sum is restored from the truncated average, so it can be off by a
+    /// little until the business is rebuilt from its reviews
+    pub fn migrate(self) -> StdResult<Business> {
+        let legacy_max_rating = 5000 / MAX_NORMALIZED_RATING as u128;
+        let rating_sum = self
+            .total_weight
+            .u128()
+            .checked_mul(self.average_rating.u128())
+            .ok_or_else(|| StdError::generic_err("Critical failure migrating a business"))?
+            / legacy_max_rating;
+
+        Ok(Business {
+            name: self.name,
+            description: self.description,
+            address: self.address,
+            category: None,
+            owner: None,
+            brand: None,
+            status: BusinessStatus::Active,
+            hidden: None,
+            hidden_reviews_count: 0,
+            vouchers_weight: Uint128::zero(),
+            attestation_key: None,
+            reviews_count: self.reviews_count,
+            total_weight: self.total_weight,
+            rating_sum: Uint128(rating_sum),
+            dimensions: vec![],
+            total_raw_weight: self.total_weight,
+            decayed_weight: Uint128::zero(),
+            decayed_rating_sum: Uint128::zero(),
+            decay_timestamp: 0,
+        })
+    }
+}
+
+/// A group of businesses that are rated alike
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct Category {
+    pub name: String,
+    /// defaults to the deployment's scale
+    pub rating_scale: Option<RatingScale>,
+    /// the aspects of a business, e.g. price or service, that reviewers rate separately
+    pub dimensions: Vec<String>,
+}
+
+pub fn save_category<S: Storage>(store: &mut S, category: Category) -> StdResult<()> {
+    let mut all_categories = CashMap::init(KEY_CATEGORIES, store);
+    all_categories.insert(category.name.as_bytes(), category.clone())
+}
+
+pub fn may_load_category<S: ReadonlyStorage>(store: &S, name: &str) -> Option<Category> {
+    let all_categories: ReadOnlyCashMap<Category, S> = ReadOnlyCashMap::init(KEY_CATEGORIES, store);
+    all_categories.get(name.as_bytes())
+}
+
+pub fn get_all_categories<S: ReadonlyStorage>(store: &S) -> Vec<Category> {
+    let all_categories: ReadOnlyCashMap<Category, S> = ReadOnlyCashMap::init(KEY_CATEGORIES, store);
+    all_categories.iter().collect()
+}
+
+/// The scale of a business is chosen by its category, or else by the deployment
+pub fn rating_scale_of<S: ReadonlyStorage>(
+    store: &S,
+    config: &Config,
+    business: &Business,
+) -> RatingScale {
+    business
+        .category
+        .as_ref()
+        .and_then(|name| may_load_category(store, name))
+        .and_then(|category| category.rating_scale)
+        .unwrap_or(config.rating_scale)
+}
+
+/// The fields of a business that are derived from its reviews
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, JsonSchema)]
+pub struct BusinessAggregates {
+    pub reviews_count: u32,
+    pub total_weight: Uint128,
+    pub total_raw_weight: Uint128,
+    pub rating_sum: Uint128,
+    pub dimensions: Vec<DimensionAggregate>,
+    pub decayed_weight: Uint128,
+    pub decayed_rating_sum: Uint128,
+}
+
+/// The weighted sums of the ratings that a business got on one dimension. Reviews that didn't rate
+/// the dimension don't count in its total weight.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct DimensionAggregate {
+    pub dimension: String,
+    pub total_weight: Uint128,
+    pub rating_sum: Uint128,
+}
+
+/// A rating of one of the dimensions of a business' category
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct DimensionRating {
+    pub dimension: String,
+    pub rating: u8,
+}
+
+/// The progress of a paged rebuild of a business' aggregates
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct AggregateRebuild {
+    pub next_page: u32,
+    pub page_size: u32,
+    // the time that the decayed sums are rebuilt as of
+    pub decay_timestamp: u64,
+    pub aggregates: BusinessAggregates,
+}
+
+pub fn may_load_aggregate_rebuild<S: ReadonlyStorage>(
+    store: &S,
+    business_address: &HumanAddr,
+) -> Option<AggregateRebuild> {
+    let all_rebuilds: ReadOnlyCashMap<AggregateRebuild, S> =
+        ReadOnlyCashMap::init(KEY_AGGREGATE_REBUILDS, store);
+    all_rebuilds.get(business_address.as_str().as_bytes())
+}
+
+pub fn save_aggregate_rebuild<S: Storage>(
+    store: &mut S,
+    business_address: &HumanAddr,
+    rebuild: AggregateRebuild,
+) -> StdResult<()> {
+    let mut all_rebuilds = CashMap::init(KEY_AGGREGATE_REBUILDS, store);
+    all_rebuilds.insert(business_address.as_str().as_bytes(), rebuild)
+}
+
+pub fn remove_aggregate_rebuild<S: Storage>(
+    store: &mut S,
+    business_address: &HumanAddr,
+) -> StdResult<()> {
+    let mut all_rebuilds: CashMap<AggregateRebuild, S> =
+        CashMap::init(KEY_AGGREGATE_REBUILDS, store);
+    all_rebuilds.remove(business_address.as_str().as_bytes())
+}
+
 pub fn create_business<S: Storage>(store: &mut S, business: Business) -> StdResult<()> {
+    // a business that is still in the legacy layout keeps its address until it is migrated
+    let legacy_businesses: ReadOnlyCashMap<LegacyBusiness, S> =
+        ReadOnlyCashMap::init(KEY_LEGACY_BUSINESSES, store);
+    if legacy_businesses
+        .get(business.address.as_str().as_bytes())
+        .is_some()
+    {
+        return Err(StdError::generic_err(
+            "A business is already registered on that address".to_string(),
+        ));
+    }
+
     let mut all_businesses = CashMap::init(KEY_BUSINESSES, store);
     let existing_business: Option<Business> =
         all_businesses.get(business.address.as_str().as_bytes());
@@ -31,27 +470,69 @@ pub fn create_business<S: Storage>(store: &mut S, business: Business) -> StdResu
         )),
         None => {
             all_businesses.insert(business.address.as_str().as_bytes(), business.clone())?;
+            if business.is_listed() {
+                let mut listed_businesses = CashMap::init(KEY_LISTED_BUSINESSES, store);
+                listed_businesses.insert(
+                    business.address.as_str().as_bytes(),
+                    business.address.clone(),
+                )?;
+            }
             Ok(())
         }
     }
 }
 
-pub fn apply_review_on_business<S: Storage>(
-    store: &mut S,
-    business_address: HumanAddr,
-    new_total_weight: u128,
-    new_average_rating: u128,
-    is_new: u8,
-) -> StdResult<()> {
+/// Saves a business that already exists, and moves its aggregates between the brands it left or
+/// joined, so that brand aggregates always match the sum of their branches.
+pub fn update_business<S: Storage>(store: &mut S, business: Business) -> StdResult<()> {
     let mut all_businesses = CashMap::init(KEY_BUSINESSES, store);
-    let business: Option<Business> = all_businesses.get(business_address.as_str().as_bytes());
-
-    match business {
-        Some(mut b) => {
-            b.average_rating = Uint128::from(new_average_rating);
-            b.total_weight = Uint128::from(new_total_weight);
-            b.reviews_count += is_new as u32;
-            all_businesses.insert(business_address.as_str().as_bytes(), b)?;
+    let existing_business: Option<Business> =
+        all_businesses.get(business.address.as_str().as_bytes());
+
+    match existing_business {
+        Some(existing_business) => {
+            all_businesses.insert(business.address.as_str().as_bytes(), business.clone())?;
+            if let Some(brand) = &existing_business.brand {
+                apply_branch_on_brand(store, brand, &existing_business, true)?;
+            }
+            if let Some(brand) = &business.brand {
+                apply_branch_on_brand(store, brand, &business, false)?;
+            }
+
+            if existing_business.brand != business.brand {
+                if let Some(brand) = &existing_business.brand {
+                    branches_of_brand(store, brand).remove(business.address.as_str().as_bytes())?;
+                }
+                if let Some(brand) = &business.brand {
+                    branches_of_brand(store, brand).insert(
+                        business.address.as_str().as_bytes(),
+                        business.address.clone(),
+                    )?;
+                }
+            }
+
+            if existing_business.is_listed() != business.is_listed() {
+                let mut listed_businesses = CashMap::init(KEY_LISTED_BUSINESSES, store);
+                if business.is_listed() {
+                    listed_businesses.insert(
+                        business.address.as_str().as_bytes(),
+                        business.address.clone(),
+                    )?;
+                } else {
+                    listed_businesses.remove(business.address.as_str().as_bytes())?;
+                }
+            }
+            if existing_business.hidden.is_some() != business.hidden.is_some() {
+                let mut hidden_businesses = CashMap::init(KEY_HIDDEN_BUSINESSES, store);
+                if business.hidden.is_some() {
+                    hidden_businesses.insert(
+                        business.address.as_str().as_bytes(),
+                        business.address.clone(),
+                    )?;
+                } else {
+                    hidden_businesses.remove(business.address.as_str().as_bytes())?;
+                }
+            }
             Ok(())
         }
         None => Err(StdError::generic_err(
@@ -60,19 +541,82 @@ pub fn apply_review_on_business<S: Storage>(
     }
 }
 
+/// Pages through the listed businesses, or through all the businesses that aren't hidden when
+/// closed ones are included
 pub fn get_businesses_page<S: ReadonlyStorage>(
     store: &S,
     page: Option<u32>,
     page_size: u32,
+    include_closed: bool,
 ) -> StdResult<(Vec<Business>, u32)> {
+    let page = page.unwrap_or(0);
     let all_businesses = ReadOnlyCashMap::init(KEY_BUSINESSES, store);
 
-    let businesses_page: Vec<Business> = all_businesses.paging(page.unwrap_or(0), page_size)?;
-    let businesses_len: u32 = all_businesses.len();
+    if include_closed {
+        let hidden_businesses: ReadOnlyCashMap<HumanAddr, S> =
+            ReadOnlyCashMap::init(KEY_HIDDEN_BUSINESSES, store);
+        let businesses_len: u32 = all_businesses.len();
+        // a drifted index shouldn't keep the businesses from being listed
+        let total = businesses_len.saturating_sub(hidden_businesses.len());
+        if page_size == 0 || page.saturating_mul(page_size) >= businesses_len {
+            return Ok((vec![], total));
+        }
+        // hidden businesses are skipped, so a page can hold less than page_size of them
+        let businesses_page: Vec<Business> = all_businesses.paging(page, page_size)?;
+        let businesses_page = businesses_page
+            .into_iter()
+            .filter(|business| business.hidden.is_none())
+            .collect();
+        return Ok((businesses_page, total));
+    }
+
+    let listed_businesses: ReadOnlyCashMap<HumanAddr, S> =
+        ReadOnlyCashMap::init(KEY_LISTED_BUSINESSES, store);
+    let businesses_len = listed_businesses.len();
+    if page_size == 0 || page.saturating_mul(page_size) >= businesses_len {
+        return Ok((vec![], businesses_len));
+    }
+
+    let businesses_page = listed_businesses
+        .paging(page, page_size)?
+        .iter()
+        .map(|address| {
+            all_businesses
+                .get(address.as_str().as_bytes())
+                .ok_or_else(|| StdError::generic_err("Critical failure loading a listed business"))
+        })
+        .collect::<StdResult<_>>()?;
 
     Ok((businesses_page, businesses_len))
 }
 
+/// Moves up to `limit` businesses from the legacy namespace into the current one. Returns the
+/// number of businesses that were migrated and the number that are left.
+pub fn migrate_legacy_businesses<S: Storage>(store: &mut S, limit: u32) -> StdResult<(u32, u32)> {
+    let legacy_businesses: ReadOnlyCashMap<LegacyBusiness, S> =
+        ReadOnlyCashMap::init(KEY_LEGACY_BUSINESSES, store);
+    if limit == 0 || legacy_businesses.is_empty() {
+        return Ok((0, legacy_businesses.len()));
+    }
+    // migrated businesses are removed, so the next ones are always on the first page
+    let legacy_page = legacy_businesses.paging(0, limit)?;
+
+    let mut migrated = 0;
+    for legacy_business in legacy_page {
+        let address = legacy_business.address.clone();
+        let mut legacy_businesses: CashMap<LegacyBusiness, S> =
+            CashMap::init(KEY_LEGACY_BUSINESSES, store);
+        legacy_businesses.remove(address.as_str().as_bytes())?;
+
+        create_business(store, legacy_business.migrate()?)?;
+        migrated += 1;
+    }
+
+    let legacy_businesses: ReadOnlyCashMap<LegacyBusiness, S> =
+        ReadOnlyCashMap::init(KEY_LEGACY_BUSINESSES, store);
+    Ok((migrated, legacy_businesses.len()))
+}
+
 pub fn get_business_by_address<S: ReadonlyStorage>(
     store: &S,
     address: &HumanAddr,
@@ -84,19 +628,271 @@ pub fn get_business_by_address<S: ReadonlyStorage>(
     Ok(existing_business)
 }
 
+/// A chain that receives payments at a different address in each of its branches. Every branch
+/// is still a separate business, and the brand only keeps the sums of their aggregates.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct Brand {
+    pub name: String,
+    pub owner: HumanAddr,
+    pub rating_scale: RatingScale,
+    pub reviews_count: u32,
+    pub total_weight: Uint128,
+    pub rating_sum: Uint128,
+}
+
+pub fn create_brand<S: Storage>(store: &mut S, brand: Brand) -> StdResult<()> {
+    let mut all_brands: CashMap<Brand, S> = CashMap::init(KEY_BRANDS, store);
+    if all_brands.contains(brand.name.as_bytes()) {
+        return Err(StdError::generic_err(format!(
+            "There is already a brand named {}",
+            brand.name
+        )));
+    }
+
+    all_brands.insert(brand.name.clone().as_bytes(), brand)
+}
+
+pub fn may_load_brand<S: ReadonlyStorage>(store: &S, name: &str) -> Option<Brand> {
+    let all_brands: ReadOnlyCashMap<Brand, S> = ReadOnlyCashMap::init(KEY_BRANDS, store);
+    all_brands.get(name.as_bytes())
+}
+
+// adds the aggregates of a branch to the sums of its brand, or removes them
+fn apply_branch_on_brand<S: Storage>(
+    store: &mut S,
+    brand_name: &str,
+    branch: &Business,
+    remove: bool,
+) -> StdResult<()> {
+    let mut all_brands: CashMap<Brand, S> = CashMap::init(KEY_BRANDS, store);
+    let mut brand = all_brands
+        .get(brand_name.as_bytes())
+        .ok_or_else(|| StdError::generic_err(format!("There is no brand named {}", brand_name)))?;
+
+    if remove {
+        brand.reviews_count = brand
+            .reviews_count
+            .checked_sub(branch.reviews_count)
+            .ok_or_else(|| StdError::generic_err("underflow in subtraction"))?;
+        brand.total_weight = Uint128(result_sub(
+            brand.total_weight.u128(),
+            branch.total_weight.u128(),
+        )?);
+        brand.rating_sum = Uint128(result_sub(
+            brand.rating_sum.u128(),
+            branch.rating_sum.u128(),
+        )?);
+    } else {
+        brand.reviews_count = brand
+            .reviews_count
+            .checked_add(branch.reviews_count)
+            .ok_or_else(|| StdError::generic_err("overflow in addition"))?;
+        brand.total_weight = Uint128(result_add(
+            brand.total_weight.u128(),
+            branch.total_weight.u128(),
+        )?);
+        brand.rating_sum = Uint128(result_add(
+            brand.rating_sum.u128(),
+            branch.rating_sum.u128(),
+        )?);
+    }
+    all_brands.insert(brand_name.as_bytes(), brand)
+}
+
+fn branches_of_brand<'a, S: Storage>(
+    store: &'a mut S,
+    brand_name: &str,
+) -> CashMap<'a, HumanAddr, S> {
+    let mut namespace = String::from(PREFIX_BRAND_BRANCHES);
+    namespace.push_str(brand_name);
+    CashMap::init(namespace.as_bytes(), store)
+}
+
+pub fn get_branches_page<S: ReadonlyStorage>(
+    store: &S,
+    brand_name: &str,
+    page: u32,
+    page_size: u32,
+) -> StdResult<(Vec<HumanAddr>, u32)> {
+    let mut namespace = String::from(PREFIX_BRAND_BRANCHES);
+    namespace.push_str(brand_name);
+    let namespace: &[u8] = namespace.as_bytes();
+
+    let branches: ReadOnlyCashMap<HumanAddr, S> = ReadOnlyCashMap::init(namespace, store);
+    let total = branches.len();
+    if page_size == 0 || page.saturating_mul(page_size) >= total {
+        return Ok((vec![], total));
+    }
+
+    Ok((branches.paging(page, page_size)?, total))
+}
+
+/// Saved with bincode like businesses, so reviews in the legacy layout are moved into this one by
+/// `LegacyReview::migrate`, which sets every field
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub struct Review {
+    pub reviewer: HumanAddr,
     pub title: String,
     pub content: String,
-    pub rating: u8, // 0 to 5
+    // normalized, between 0 and MAX_NORMALIZED_RATING
+    pub rating: u8,
+    // normalized as well
+    pub dimension_ratings: Vec<DimensionRating>,
+    // the sku of the item of the business that the review is about, if any
+    pub item: Option<String>,
     pub last_update_timestamp: u64,
+    // none for reviews that were migrated from the legacy layout, which didn't keep it
+    pub created_timestamp: Option<u64>,
+    // set while a moderator hides the review
+    pub hidden: Option<Hidden>,
+    // set once an arbiter ruled against the review, and kept when it is edited
+    pub dispute_outcome: Option<DisputeOutcome>,
+    // set while the reviewer is associated with the business, once the admin, the arbiter or the
+    // reviewer confirmed it, which leaves the review out of the aggregates
+    pub associated: bool,
+    // the number of versions in the review's history, which is zero for reviews that were
+    // migrated from the legacy layout, until they are edited
+    pub revisions_count: u32,
 
     // kept private
     pub weight: Uint128,
+    // the weight that is counted in the business' average, after the weight strategy was applied
+    pub applied_weight: Uint128,
+    // whether the review is counted in the aggregates of its business and item, as it was decided
+    // when the review was last saved
+    pub counted: bool,
+    pub tx_ids: Vec<ReceiptId>,
+    // the amounts of the review's SNIP-20 receipts, which refunds are matched against
+    pub receipt_amounts: Vec<ReceiptAmount>,
+    pub refund_ids: Vec<ReceiptId>,
+    // the receipts that were refunded, each of which is netted once
+    pub refunded_receipts: Vec<ReceiptId>,
+    // the block time and height of the newest receipt, if known
+    pub latest_receipt_time: Option<u64>,
+    pub latest_receipt_height: Option<u64>,
+}
+
+impl Review {
+    pub fn displayed(&self, rating_scale: &RatingScale) -> StdResult<DisplayedReview> {
+        let (title, content) = match self.dispute_outcome {
+            Some(DisputeOutcome::HideContent) => (String::new(), String::new()),
+            _ => (self.title.clone(), self.content.clone()),
+        };
+
+        Ok(DisplayedReview {
+            title,
+            content,
+            rating: rating_scale.average(self.rating as u128, 1)?,
+            dimension_ratings: self
+                .dimension_ratings
+                .iter()
+                .map(|r| {
+                    Ok(DisplayedDimensionRating {
+                        dimension: r.dimension.clone(),
+                        rating: rating_scale.average(r.rating as u128, 1)?,
+                    })
+                })
+                .collect::<StdResult<_>>()?,
+            item: self.item.clone(),
+            last_update_timestamp: self.last_update_timestamp,
+            edited: self.revisions_count > 1,
+            revisions_count: self.revisions_count,
+            dispute_outcome: self.dispute_outcome,
+        })
+    }
+
+    /// The current version of the review, as it is kept in its history
+    pub fn revision(&self) -> Revision {
+        Revision {
+            title: self.title.clone(),
+            content: self.content.clone(),
+            rating: self.rating,
+            item: self.item.clone(),
+            timestamp: self.last_update_timestamp,
+        }
+    }
+
+    /// Whether the review should be counted in the aggregates of its business and item, the next
+    /// time that it is saved
+    pub fn counts_toward_aggregates(&self, config: &Config) -> bool {
+        (self.hidden.is_none() || config.count_hidden_reviews)
+            && self.dispute_outcome != Some(DisputeOutcome::ExcludeRating)
+            && !self.associated
+    }
+}
+
+/// Identifies a receipt that was already accounted for in a review. SNIP-20 tx ids are only
+/// unique within their token, so they are qualified by the token's contract address.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ReceiptId {
+    Snip20 { token: HumanAddr, tx_id: u64 },
+    Native { receipt_id: u64 },
+    Voucher { secret_hash: Binary },
+    Attestation { nonce: u64 },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct ReceiptAmount {
+    pub receipt: ReceiptId,
+    pub amount: Uint128,
+}
+
+/// The layout of a review before receipt ids were qualified by their token.
+/// Back then, sSCRT was the only accepted token.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct LegacyReview {
+    pub title: String,
+    pub content: String,
+    pub rating: u8, // 0 to 5
+    pub last_update_timestamp: u64,
+    pub weight: Uint128,
     pub tx_ids: Vec<u64>,
 }
 
+impl LegacyReview {
+    pub fn migrate(self, reviewer: &HumanAddr, sscrt_address: &HumanAddr) -> Review {
+        let tx_ids = self
+            .tx_ids
+            .into_iter()
+            .map(|tx_id| ReceiptId::Snip20 {
+                token: sscrt_address.clone(),
+                tx_id,
+            })
+            .collect();
+
+        Review {
+            reviewer: reviewer.clone(),
+            title: self.title,
+            content: self.content,
+            // legacy ratings were given in whole stars
+            rating: self.rating * (MAX_NORMALIZED_RATING / 5),
+            dimension_ratings: vec![],
+            item: None,
+            last_update_timestamp: self.last_update_timestamp,
+            created_timestamp: None,
+            hidden: None,
+            dispute_outcome: None,
+            associated: false,
+            revisions_count: 0,
+            weight: self.weight,
+            // legacy reviews were always weighted linearly
+            applied_weight: self.weight,
+            counted: true,
+            tx_ids,
+            // the amounts weren't kept, so refunds of legacy receipts are only matched by memo
+            receipt_amounts: vec![],
+            refund_ids: vec![],
+            refunded_receipts: vec![],
+            latest_receipt_time: None,
+            latest_receipt_height: None,
+        }
+    }
+}
+
 pub fn may_load_review<S: Storage>(
     store: &S,
     business_address: &HumanAddr,
@@ -128,9 +924,91 @@ pub fn create_review<S: Storage>(
         .map_err(|_| StdError::generic_err("couldn't save review for business"))
 }
 
+/// Moves the reviews of the given reviewers from the legacy namespace of a business into the
+/// current one. Returns the number of reviews that were migrated, and the number that were
+/// dropped because their reviewer already wrote a review in the current layout.
+pub fn migrate_legacy_reviews<S: Storage>(
+    store: &mut S,
+    business_address: &HumanAddr,
+    reviewers: &[HumanAddr],
+    sscrt_address: &HumanAddr,
+) -> StdResult<(u32, u32)> {
+    let mut legacy_namespace = String::from(PREFIX_LEGACY_REVIEWS);
+    legacy_namespace.push_str(business_address.as_str());
+    let legacy_namespace: &[u8] = legacy_namespace.as_bytes();
+
+    let mut migrated = 0;
+    let mut superseded = 0;
+    for reviewer in reviewers {
+        let legacy_reviews: ReadOnlyCashMap<LegacyReview, S> =
+            ReadOnlyCashMap::init(legacy_namespace, store);
+        let legacy_review = match legacy_reviews.get(reviewer.as_str().as_bytes()) {
+            Some(review) => review.migrate(reviewer, sscrt_address),
+            // get can't tell a missing review from one that can't be decoded, but load can
+            None => match legacy_reviews.load(reviewer.as_str().as_bytes()) {
+                Err(StdError::NotFound { .. }) | Ok(..) => continue,
+                Err(..) => {
+                    return Err(StdError::generic_err(format!(
+                        "The legacy review of {} can't be decoded",
+                        reviewer
+                    )))
+                }
+            },
+        };
+        let mut legacy_reviews: CashMap<LegacyReview, S> = CashMap::init(legacy_namespace, store);
+        legacy_reviews.remove(reviewer.as_str().as_bytes())?;
+
+        if may_load_review(store, business_address, reviewer).is_none() {
+            create_review(store, business_address, reviewer, legacy_review)?;
+            migrated += 1;
+            continue;
+        }
+
+        // the review that was already written in the current layout is more recent, and its
+        // contribution was added to the business' aggregates on top of the legacy one
+        let mut business = get_business_by_address(store, business_address)?
+            .ok_or_else(|| StdError::generic_err("Critical failure migrating a review"))?;
+        let weight = legacy_review.weight.u128();
+        business.reviews_count = business
+            .reviews_count
+            .checked_sub(1)
+            .ok_or_else(|| StdError::generic_err("Critical failure migrating a review"))?;
+        business.total_weight = Uint128(result_sub(business.total_weight.u128(), weight)?);
+        business.total_raw_weight = Uint128(result_sub(business.total_raw_weight.u128(), weight)?);
+        business.rating_sum = Uint128(result_sub(
+            business.rating_sum.u128(),
+            result_mul(weight, legacy_review.rating as u128)?,
+        )?);
+        update_business(store, business)?;
+        superseded += 1;
+    }
+
+    Ok((migrated, superseded))
+}
+
+pub fn get_reviews_page<S: ReadonlyStorage>(
+    store: &S,
+    business_address: &HumanAddr,
+    page: u32,
+    page_size: u32,
+) -> StdResult<(Vec<Review>, u32)> {
+    let mut namespace = String::from(PREFIX_REVIEWS);
+    namespace.push_str(business_address.as_str());
+    let namespace: &[u8] = namespace.as_bytes();
+
+    let reviews_on_business: ReadOnlyCashMap<Review, S> = ReadOnlyCashMap::init(namespace, store);
+    let total = reviews_on_business.len();
+    if page_size == 0 || page.saturating_mul(page_size) >= total {
+        return Ok((vec![], total));
+    }
+
+    Ok((reviews_on_business.paging(page, page_size)?, total))
+}
+
 pub fn get_reviews_on_business<S: Storage>(
     store: &S,
     business_address: &HumanAddr,
+    rating_scale: &RatingScale,
     page: Option<u32>,
     page_size: u32,
 ) -> StdResult<(Vec<DisplayedReview>, u32)> {
@@ -141,17 +1019,465 @@ pub fn get_reviews_on_business<S: Storage>(
     let reviews_on_business: ReadOnlyCashMap<Review, S> = ReadOnlyCashMap::init(namespace, store);
     let reviews_page: Vec<Review> = reviews_on_business.paging(page.unwrap_or(0), page_size)?;
 
+    // hidden reviews are skipped, so a page can hold less than page_size of them
     let displayed_page: Vec<DisplayedReview> = reviews_page
         .iter()
-        .map(|review: &Review| DisplayedReview {
-            title: review.title.clone(),
-            content: review.content.clone(),
-            rating: review.rating,
-            last_update_timestamp: review.last_update_timestamp,
-        })
-        .collect();
+        .filter(|review| review.hidden.is_none())
+        .map(|review| review.displayed(rating_scale))
+        .collect::<StdResult<_>>()?;
 
     let reviews_count = reviews_on_business.len();
 
     Ok((displayed_page, reviews_count))
 }
+
+/// A single-use voucher that a business minted for a customer who paid it off-chain. It is
+/// identified by the sha256 hash of its secret, which the customer reveals to redeem it.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct Voucher {
+    pub weight: Uint128,
+    // the block time from which the voucher can no longer be redeemed
+    pub expires_at: Option<u64>,
+    pub minted_timestamp: u64,
+    pub minted_height: u64,
+    pub redeemed: bool,
+}
+
+pub fn may_load_voucher<S: ReadonlyStorage>(
+    store: &S,
+    business_address: &HumanAddr,
+    secret_hash: &[u8],
+) -> Option<Voucher> {
+    let mut namespace = String::from(PREFIX_VOUCHERS);
+    namespace.push_str(business_address.as_str());
+    let namespace: &[u8] = namespace.as_bytes();
+
+    let vouchers: ReadOnlyCashMap<Voucher, S> = ReadOnlyCashMap::init(namespace, store);
+    vouchers.get(secret_hash)
+}
+
+pub fn save_voucher<S: Storage>(
+    store: &mut S,
+    business_address: &HumanAddr,
+    secret_hash: &[u8],
+    voucher: Voucher,
+) -> StdResult<()> {
+    let mut namespace = String::from(PREFIX_VOUCHERS);
+    namespace.push_str(business_address.as_str());
+    let namespace: &[u8] = namespace.as_bytes();
+
+    let mut vouchers: CashMap<Voucher, S> = CashMap::init(namespace, store);
+    vouchers.insert(secret_hash, voucher)
+}
+
+/// Returns the block time at which the business' attestation nonce was used, if it was
+pub fn may_load_attestation_nonce<S: ReadonlyStorage>(
+    store: &S,
+    business_address: &HumanAddr,
+    nonce: u64,
+) -> Option<u64> {
+    let mut namespace = String::from(PREFIX_ATTESTATION_NONCES);
+    namespace.push_str(business_address.as_str());
+    let namespace: &[u8] = namespace.as_bytes();
+
+    let nonces: ReadOnlyCashMap<u64, S> = ReadOnlyCashMap::init(namespace, store);
+    nonces.get(&nonce.to_be_bytes())
+}
+
+pub fn use_attestation_nonce<S: Storage>(
+    store: &mut S,
+    business_address: &HumanAddr,
+    nonce: u64,
+    timestamp: u64,
+) -> StdResult<()> {
+    let mut namespace = String::from(PREFIX_ATTESTATION_NONCES);
+    namespace.push_str(business_address.as_str());
+    let namespace: &[u8] = namespace.as_bytes();
+
+    let mut nonces: CashMap<u64, S> = CashMap::init(namespace, store);
+    nonces.insert(&nonce.to_be_bytes(), timestamp)
+}
+
+/// A version of a review, as it was written by the reviewer
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct Revision {
+    pub title: String,
+    pub content: String,
+    // normalized, like the rating of the review
+    pub rating: u8,
+    pub item: Option<String>,
+    pub timestamp: u64,
+}
+
+/// Appends a version to the history of a review, which is never changed otherwise. Returns the
+/// number of versions in the history.
+pub fn append_revision<S: Storage>(
+    store: &mut S,
+    business_address: &HumanAddr,
+    reviewer: &HumanAddr,
+    revision: Revision,
+) -> StdResult<u32> {
+    let mut namespace = String::from(PREFIX_REVISIONS);
+    namespace.push_str(&review_key(business_address, reviewer));
+    let namespace: &[u8] = namespace.as_bytes();
+
+    let mut revisions: CashMap<Revision, S> = CashMap::init(namespace, store);
+    let revisions_count = revisions.len();
+    revisions.insert(&revisions_count.to_be_bytes(), revision)?;
+    Ok(revisions_count + 1)
+}
+
+/// Pages through the history of a review, from its first version
+pub fn get_revisions_page<S: ReadonlyStorage>(
+    store: &S,
+    business_address: &HumanAddr,
+    reviewer: &HumanAddr,
+    page: u32,
+    page_size: u32,
+) -> StdResult<(Vec<Revision>, u32)> {
+    let mut namespace = String::from(PREFIX_REVISIONS);
+    namespace.push_str(&review_key(business_address, reviewer));
+    let namespace: &[u8] = namespace.as_bytes();
+
+    let revisions: ReadOnlyCashMap<Revision, S> = ReadOnlyCashMap::init(namespace, store);
+    let total = revisions.len();
+    if page_size == 0 || page.saturating_mul(page_size) >= total {
+        return Ok((vec![], total));
+    }
+
+    Ok((revisions.paging(page, page_size)?, total))
+}
+
+/// Whether the address was associated with the business, e.g. as another wallet of its owner, so
+/// that it can't review it
+pub fn is_associated_address<S: ReadonlyStorage>(
+    store: &S,
+    business_address: &HumanAddr,
+    address: &HumanAddr,
+) -> bool {
+    let mut namespace = String::from(PREFIX_ASSOCIATED_ADDRESSES);
+    namespace.push_str(business_address.as_str());
+    let namespace: &[u8] = namespace.as_bytes();
+
+    let associated_addresses: ReadOnlyCashMap<HumanAddr, S> =
+        ReadOnlyCashMap::init(namespace, store);
+    associated_addresses
+        .get(address.as_str().as_bytes())
+        .is_some()
+}
+
+pub fn add_associated_address<S: Storage>(
+    store: &mut S,
+    business_address: &HumanAddr,
+    address: &HumanAddr,
+) -> StdResult<()> {
+    let mut namespace = String::from(PREFIX_ASSOCIATED_ADDRESSES);
+    namespace.push_str(business_address.as_str());
+    let namespace: &[u8] = namespace.as_bytes();
+
+    let mut associated_addresses: CashMap<HumanAddr, S> = CashMap::init(namespace, store);
+    associated_addresses.insert(address.as_str().as_bytes(), address.clone())
+}
+
+pub fn remove_associated_address<S: Storage>(
+    store: &mut S,
+    business_address: &HumanAddr,
+    address: &HumanAddr,
+) -> StdResult<()> {
+    let mut namespace = String::from(PREFIX_ASSOCIATED_ADDRESSES);
+    namespace.push_str(business_address.as_str());
+    let namespace: &[u8] = namespace.as_bytes();
+
+    let mut associated_addresses: CashMap<HumanAddr, S> = CashMap::init(namespace, store);
+    associated_addresses.remove(address.as_str().as_bytes())
+}
+
+/// A product or service in the catalog of a business. Reviews of the item count both in its own
+/// aggregates and in the business'.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct Item {
+    pub sku: String,
+    pub name: String,
+    pub reviews_count: u32,
+    pub total_weight: Uint128,
+    pub rating_sum: Uint128,
+}
+
+pub fn may_load_item<S: ReadonlyStorage>(
+    store: &S,
+    business_address: &HumanAddr,
+    sku: &str,
+) -> Option<Item> {
+    let mut namespace = String::from(PREFIX_ITEMS);
+    namespace.push_str(business_address.as_str());
+    let namespace: &[u8] = namespace.as_bytes();
+
+    let items_of_business: ReadOnlyCashMap<Item, S> = ReadOnlyCashMap::init(namespace, store);
+    items_of_business.get(sku.as_bytes())
+}
+
+pub fn save_item<S: Storage>(
+    store: &mut S,
+    business_address: &HumanAddr,
+    item: Item,
+) -> StdResult<()> {
+    let mut namespace = String::from(PREFIX_ITEMS);
+    namespace.push_str(business_address.as_str());
+    let namespace: &[u8] = namespace.as_bytes();
+
+    let mut items_of_business: CashMap<Item, S> = CashMap::init(namespace, store);
+    items_of_business.insert(item.sku.clone().as_bytes(), item)
+}
+
+pub fn get_items_page<S: ReadonlyStorage>(
+    store: &S,
+    business_address: &HumanAddr,
+    page: u32,
+    page_size: u32,
+) -> StdResult<(Vec<Item>, u32)> {
+    let mut namespace = String::from(PREFIX_ITEMS);
+    namespace.push_str(business_address.as_str());
+    let namespace: &[u8] = namespace.as_bytes();
+
+    let items_of_business: ReadOnlyCashMap<Item, S> = ReadOnlyCashMap::init(namespace, store);
+    let total = items_of_business.len();
+    if page_size == 0 || page.saturating_mul(page_size) >= total {
+        return Ok((vec![], total));
+    }
+
+    Ok((items_of_business.paging(page, page_size)?, total))
+}
+
+/// A flag that a customer of a business raised on one of its reviews, weighted by the customer's
+/// own verified spend there
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct Flag {
+    pub reason: ModerationReason,
+    pub weight: Uint128,
+    pub timestamp: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct FlagWeight {
+    pub reason: ModerationReason,
+    pub weight: Uint128,
+}
+
+/// A review in the moderators' queue, with the flags that were raised on it since a moderator
+/// last looked at it
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct FlaggedReview {
+    pub business_address: HumanAddr,
+    pub reviewer: HumanAddr,
+    pub flags_count: u32,
+    pub flagged_weight: Uint128,
+    // the weight of the flags of every reason that was given
+    pub reasons: Vec<FlagWeight>,
+}
+
+fn review_key(business_address: &HumanAddr, reviewer: &HumanAddr) -> String {
+    let mut key = String::from(business_address.as_str());
+    key.push_str(reviewer.as_str());
+    key
+}
+
+/// Saves the flag of an address on a review. Every address can only flag a review once, even
+/// after a moderator looked at it.
+pub fn create_flag<S: Storage>(
+    store: &mut S,
+    business_address: &HumanAddr,
+    reviewer: &HumanAddr,
+    flagger: &HumanAddr,
+    flag: Flag,
+) -> StdResult<()> {
+    let mut namespace = String::from(PREFIX_FLAGS);
+    namespace.push_str(&review_key(business_address, reviewer));
+    let namespace: &[u8] = namespace.as_bytes();
+
+    let mut flags_on_review: CashMap<Flag, S> = CashMap::init(namespace, store);
+    if flags_on_review.contains(flagger.as_str().as_bytes()) {
+        return Err(StdError::generic_err("You already flagged this review"));
+    }
+    flags_on_review.insert(flagger.as_str().as_bytes(), flag)
+}
+
+pub fn may_load_flagged_review<S: ReadonlyStorage>(
+    store: &S,
+    business_address: &HumanAddr,
+    reviewer: &HumanAddr,
+) -> Option<FlaggedReview> {
+    let flagged_reviews: ReadOnlyCashMap<FlaggedReview, S> =
+        ReadOnlyCashMap::init(KEY_FLAGGED_REVIEWS, store);
+    flagged_reviews.get(review_key(business_address, reviewer).as_bytes())
+}
+
+pub fn save_flagged_review<S: Storage>(store: &mut S, flagged: FlaggedReview) -> StdResult<()> {
+    let key = review_key(&flagged.business_address, &flagged.reviewer);
+    let mut flagged_reviews: CashMap<FlaggedReview, S> = CashMap::init(KEY_FLAGGED_REVIEWS, store);
+    flagged_reviews.insert(key.as_bytes(), flagged)
+}
+
+/// Takes a review off the moderators' queue. Returns whether it was on it.
+pub fn remove_flagged_review<S: Storage>(
+    store: &mut S,
+    business_address: &HumanAddr,
+    reviewer: &HumanAddr,
+) -> StdResult<bool> {
+    let key = review_key(business_address, reviewer);
+    let mut flagged_reviews: CashMap<FlaggedReview, S> = CashMap::init(KEY_FLAGGED_REVIEWS, store);
+    if !flagged_reviews.contains(key.as_bytes()) {
+        return Ok(false);
+    }
+
+    flagged_reviews.remove(key.as_bytes())?;
+    Ok(true)
+}
+
+pub fn get_flagged_reviews_page<S: ReadonlyStorage>(
+    store: &S,
+    page: u32,
+    page_size: u32,
+) -> StdResult<(Vec<FlaggedReview>, u32)> {
+    let flagged_reviews: ReadOnlyCashMap<FlaggedReview, S> =
+        ReadOnlyCashMap::init(KEY_FLAGGED_REVIEWS, store);
+    let total = flagged_reviews.len();
+    if page_size == 0 || page.saturating_mul(page_size) >= total {
+        return Ok((vec![], total));
+    }
+
+    Ok((flagged_reviews.paging(page, page_size)?, total))
+}
+
+/// The deposit that was paid when a business was registered, held by the contract on the terms
+/// of that time
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct HeldDeposit {
+    // the deposit is returned to whoever paid it
+    pub registrant: HumanAddr,
+    pub amount: Uint128,
+    pub token: Option<DepositToken>,
+    pub refundable: bool,
+}
+
+pub fn may_load_deposit<S: ReadonlyStorage>(
+    store: &S,
+    business_address: &HumanAddr,
+) -> Option<HeldDeposit> {
+    let deposits: ReadOnlyCashMap<HeldDeposit, S> = ReadOnlyCashMap::init(KEY_DEPOSITS, store);
+    deposits.get(business_address.as_str().as_bytes())
+}
+
+pub fn save_deposit<S: Storage>(
+    store: &mut S,
+    business_address: &HumanAddr,
+    deposit: HeldDeposit,
+) -> StdResult<()> {
+    let mut deposits: CashMap<HeldDeposit, S> = CashMap::init(KEY_DEPOSITS, store);
+    deposits.insert(business_address.as_str().as_bytes(), deposit)
+}
+
+/// Removes the deposit of a business from storage, so that it can be paid out
+pub fn take_deposit<S: Storage>(
+    store: &mut S,
+    business_address: &HumanAddr,
+) -> StdResult<Option<HeldDeposit>> {
+    let mut deposits: CashMap<HeldDeposit, S> = CashMap::init(KEY_DEPOSITS, store);
+    let deposit = deposits.get(business_address.as_str().as_bytes());
+    if deposit.is_some() {
+        deposits.remove(business_address.as_str().as_bytes())?;
+    }
+    Ok(deposit)
+}
+
+/// How an arbiter ruled on a dispute
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum DisputeOutcome {
+    /// the review stands as it is, and the owner's bond is slashed
+    Upheld,
+    /// the title and content of the review are no longer shown, but its rating still counts
+    HideContent,
+    /// the review is still shown, but its rating no longer counts toward the aggregates
+    ExcludeRating,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum DisputeState {
+    Open,
+    Resolved {
+        outcome: DisputeOutcome,
+        timestamp: u64,
+    },
+}
+
+/// The latest dispute that the owner of a business opened on one of its reviews
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct Dispute {
+    pub business_address: HumanAddr,
+    pub reviewer: HumanAddr,
+    // the bond is returned to whoever opened the dispute, even if the business changed hands
+    pub owner: HumanAddr,
+    // in uscrt
+    pub bond: Uint128,
+    pub reason: String,
+    pub response: Option<String>,
+    pub state: DisputeState,
+    pub opened_timestamp: u64,
+}
+
+pub fn may_load_dispute<S: ReadonlyStorage>(
+    store: &S,
+    business_address: &HumanAddr,
+    reviewer: &HumanAddr,
+) -> Option<Dispute> {
+    let disputes: ReadOnlyCashMap<Dispute, S> = ReadOnlyCashMap::init(KEY_DISPUTES, store);
+    disputes.get(review_key(business_address, reviewer).as_bytes())
+}
+
+pub fn save_dispute<S: Storage>(store: &mut S, dispute: Dispute) -> StdResult<()> {
+    let key = review_key(&dispute.business_address, &dispute.reviewer);
+    let mut disputes: CashMap<Dispute, S> = CashMap::init(KEY_DISPUTES, store);
+    disputes.insert(key.as_bytes(), dispute)
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct NativeReceipt {
+    pub payer: HumanAddr,
+    pub business: HumanAddr,
+    pub amount: Uint128,
+    pub timestamp: u64,
+    pub block_height: u64,
+}
+
+/// Saves a receipt for a native payment that was routed through the contract, and returns its id.
+/// Receipts are never removed, so the length of the map is used as the next id.
+pub fn create_native_receipt<S: Storage>(store: &mut S, receipt: NativeReceipt) -> StdResult<u64> {
+    let mut all_receipts: CashMap<NativeReceipt, S> = CashMap::init(KEY_NATIVE_RECEIPTS, store);
+    let receipt_id = all_receipts.len() as u64;
+
+    all_receipts
+        .insert(&receipt_id.to_be_bytes(), receipt)
+        .map_err(|_| StdError::generic_err("couldn't save native payment receipt"))?;
+
+    Ok(receipt_id)
+}
+
+pub fn may_load_native_receipt<S: ReadonlyStorage>(
+    store: &S,
+    receipt_id: u64,
+) -> Option<NativeReceipt> {
+    let all_receipts: ReadOnlyCashMap<NativeReceipt, S> =
+        ReadOnlyCashMap::init(KEY_NATIVE_RECEIPTS, store);
+
+    all_receipts.get(&receipt_id.to_be_bytes())
+}
@@ -1,55 +1,132 @@
 use cosmwasm_std::{StdError, StdResult};
 
-const MAX_EXPANDED_VALUE: u128 = 5000;
-const MAX_CHOOSABLE_VALUE: u128 = 5;
+use crate::state::WeightStrategy;
 
-pub fn recalculate_weighted_average(
-    my_added_weight: u128,
-    my_previous_weight: u128,
-    my_new_rating: u128,
-    my_previous_rating: u128,
+/// Applies the weight strategy on a review's raw weight. `total_raw_weight` is the sum of the
+/// raw weights of all the reviews on the business, including this one.
+pub fn apply_weight_strategy(
+    strategy: &WeightStrategy,
+    raw_weight: u128,
+    total_raw_weight: u128,
+) -> StdResult<u128> {
+    match strategy {
+        WeightStrategy::Linear => Ok(raw_weight),
+        WeightStrategy::SquareRoot => Ok(integer_sqrt(raw_weight)),
+        WeightStrategy::Logarithmic => Ok(log2_fixed(result_add(raw_weight, 1)?)),
+        WeightStrategy::Capped { max_share_percent } => {
+            let cap = result_div(
+                result_mul(total_raw_weight, *max_share_percent as u128)?,
+                100,
+            )?;
+            Ok(std::cmp::min(raw_weight, cap))
+        }
+    }
+}
 
-    previous_total_weight: u128,
-    previous_average_rating: u128,
-) -> StdResult<(u128, u128)> {
-    let my_previous_rating_expanded = result_div(
-        result_mul(my_previous_rating, MAX_EXPANDED_VALUE)?,
-        MAX_CHOOSABLE_VALUE,
-    )?;
-
-    let my_new_rating_expanded = result_div(
-        result_mul(my_new_rating, MAX_EXPANDED_VALUE)?,
-        MAX_CHOOSABLE_VALUE,
-    )?;
-
-    let weight_without_me = result_sub(previous_total_weight, my_previous_weight)?;
-
-    let mut rating_rest = 0;
-    if weight_without_me != 0 {
-        rating_rest = result_div(
-            result_sub(
-                result_mul(previous_average_rating, previous_total_weight)?,
-                result_mul(my_previous_rating_expanded, my_previous_weight)?,
-            )?,
-            weight_without_me,
-        )?;
+fn integer_sqrt(value: u128) -> u128 {
+    if value < 2 {
+        return value;
+    }
+
+    // Newton's method, starting from a power of two that is above the root
+    let mut root = 1u128 << ((128 - value.leading_zeros()) / 2 + 1);
+    loop {
+        let next = (root + value / root) / 2;
+        if next >= root {
+            return root;
+        }
+        root = next;
+    }
+}
+
+const LOG2_FRACTION_BITS: u32 = 16;
+
+/// Returns floor(log2(value) * 2^16), for a positive value
+fn log2_fixed(value: u128) -> u128 {
+    let integer_part = 127 - value.leading_zeros();
+
+    // the mantissa value / 2^integer_part, in [1, 2), with 32 fraction bits
+    let mut mantissa = if integer_part >= 32 {
+        value >> (integer_part - 32)
+    } else {
+        value << (32 - integer_part)
+    };
+
+    // every squaring of the mantissa reveals the next bit of the fraction
+    let mut fraction = 0u128;
+    for _ in 0..LOG2_FRACTION_BITS {
+        mantissa = (mantissa * mantissa) >> 32;
+        fraction <<= 1;
+        if mantissa >= 2 << 32 {
+            mantissa >>= 1;
+            fraction |= 1;
+        }
+    }
+
+    ((integer_part as u128) << LOG2_FRACTION_BITS) | fraction
+}
+
+// fixed-point factors have 32 fraction bits
+const DECAY_ONE: u128 = 1 << 32;
+const DECAY_FRACTION_BITS: u32 = 16;
+// 2^(-1/2^k) for k = 1..=16
+const HALVING_ROOTS: [u128; DECAY_FRACTION_BITS as usize] = [
+    3037000500, 3611622603, 3938502376, 4112874773, 4202935003, 4248701965, 4271771996, 4283353945,
+    4289156690, 4292061010, 4293513907, 4294240540, 4294603903, 4294785595, 4294876445, 4294921870,
+];
+
+/// Returns 2^(-elapsed / half_life) with 32 fraction bits, rounded down. The fraction of a half
+/// life is taken with a resolution of 1/65536.
+pub fn decay_factor(elapsed: u64, half_life: u64) -> u128 {
+    let halvings = elapsed / half_life;
+    if halvings >= 32 {
+        return 0;
     }
 
-    let my_total_weigth = result_add(my_previous_weight, my_added_weight)?;
-    let new_total_weight = result_add(weight_without_me, my_total_weigth)?;
-
-    let mut new_average = 0;
-    if new_total_weight != 0 {
-        new_average = result_div(
-            result_add(
-                result_mul(rating_rest, weight_without_me)?,
-                result_mul(my_new_rating_expanded, my_total_weigth)?,
-            )?,
-            new_total_weight,
-        )?;
+    let fraction = ((elapsed % half_life) as u128) * (1 << DECAY_FRACTION_BITS) / half_life as u128;
+
+    let mut factor = DECAY_ONE >> halvings;
+    for (bit, root) in HALVING_ROOTS.iter().enumerate() {
+        if fraction & (1 << (DECAY_FRACTION_BITS - 1 - bit as u32)) != 0 {
+            factor = (factor * root) >> 32;
+        }
     }
+    factor
+}
+
+/// Multiplies a value by a factor that was returned from `decay_factor`, without overflowing
+pub fn apply_decay(value: u128, factor: u128) -> u128 {
+    (value >> 32) * factor + (((value & (DECAY_ONE - 1)) * factor) >> 32)
+}
 
-    Ok((new_average, new_total_weight))
+/// Returns the decayed weight and the decayed weighted rating of a review, in units of 2^-32 of
+/// a weight. Both are 0 once the review is 32 half lives old.
+pub fn decayed_contribution(
+    weight: u128,
+    rating: u128,
+    elapsed: u64,
+    half_life: u64,
+) -> StdResult<(u128, u128)> {
+    let decayed_weight = apply_decay(
+        result_mul(weight, DECAY_ONE)?,
+        decay_factor(elapsed, half_life),
+    );
+
+    Ok((decayed_weight, result_mul(decayed_weight, rating)?))
+}
+
+// the highest weight that an average can be taken over without overflowing, since averages are
+// taken of weights in hundredths of a rating, with 6 decimal places
+const MAX_AVERAGED_WEIGHT: u128 = u128::MAX / 100_000_000;
+
+/// Drops as many of the 32 fraction bits of a decayed rating sum and weight as it takes for
+/// their average to be taken without overflowing, which large amounts of 18 decimal tokens need
+pub fn averageable_decayed_sums(rating_sum: u128, weight: u128) -> (u128, u128) {
+    let mut shift = 0;
+    while shift < 32 && weight >> shift > MAX_AVERAGED_WEIGHT {
+        shift += 1;
+    }
+    (rating_sum >> shift, weight >> shift)
 }
 
 pub fn result_add(lhs: u128, rhs: u128) -> StdResult<u128> {
@@ -57,12 +134,12 @@ pub fn result_add(lhs: u128, rhs: u128) -> StdResult<u128> {
         .ok_or_else(|| StdError::generic_err("overflow in addition"))
 }
 
-fn result_mul(lhs: u128, rhs: u128) -> StdResult<u128> {
+pub fn result_mul(lhs: u128, rhs: u128) -> StdResult<u128> {
     lhs.checked_mul(rhs)
         .ok_or_else(|| StdError::generic_err("overflow in multiplication"))
 }
 
-fn result_sub(lhs: u128, rhs: u128) -> StdResult<u128> {
+pub fn result_sub(lhs: u128, rhs: u128) -> StdResult<u128> {
     lhs.checked_sub(rhs)
         .ok_or_else(|| StdError::generic_err("underflow in subtraction"))
 }